@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Existing tokens are treated as freshly issued.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,19 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+-- Drafts and scheduled issues have not been published yet.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at DROP NOT NULL;
+ALTER TABLE newsletter_issues
+    ADD COLUMN send_at timestamptz;
+ALTER TABLE newsletter_issues
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+-- Issues created before this migration were enqueued as soon as they were published.
+ALTER TABLE newsletter_issues
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'sent'
+        CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled'));
+UPDATE newsletter_issues
+SET status = 'sending'
+WHERE newsletter_issue_id IN (SELECT newsletter_issue_id FROM issue_delivery_queue);
+ALTER TABLE newsletter_issues
+    ALTER COLUMN status DROP DEFAULT;
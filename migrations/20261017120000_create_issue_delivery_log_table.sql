@@ -0,0 +1,12 @@
+-- Add migration script here
+-- One row per recipient once its delivery task has left issue_delivery_queue.
+CREATE TABLE issue_delivery_log
+(
+    newsletter_issue_id uuid        NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email    TEXT        NOT NULL,
+    outcome             TEXT        NOT NULL CHECK (outcome IN ('delivered', 'failed', 'skipped')),
+    n_retries           smallint    NOT NULL,
+    error_message       TEXT,
+    recorded_at         timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
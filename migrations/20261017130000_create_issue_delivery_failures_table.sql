@@ -0,0 +1,15 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN enqueued_at timestamptz NOT NULL DEFAULT now();
+-- Dead letters: delivery tasks which have failed for good, until an admin requeues them.
+CREATE TABLE issue_delivery_failures
+(
+    failure_id          uuid        NOT NULL PRIMARY KEY,
+    newsletter_issue_id uuid        NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email    TEXT        NOT NULL,
+    n_retries           smallint    NOT NULL,
+    last_error          TEXT        NOT NULL,
+    enqueued_at         timestamptz NOT NULL,
+    failed_at           timestamptz NOT NULL,
+    UNIQUE (newsletter_issue_id, subscriber_email)
+);
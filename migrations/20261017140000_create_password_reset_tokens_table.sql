@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Where password reset links are sent to. Users without an email cannot reset their password by email.
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+-- Only the SHA-256 hash of a reset token is stored, the token itself only ever appears in the email.
+CREATE TABLE password_reset_tokens
+(
+    token_hash TEXT        NOT NULL PRIMARY KEY,
+    user_id    UUID        NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    used_at    timestamptz NULL
+);
@@ -0,0 +1,21 @@
+-- Add migration script here
+-- One TOTP secret per user. The row is created unconfirmed when enrollment starts,
+-- and two-factor authentication is only enforced once `confirmed_at` is set.
+CREATE TABLE user_totp_secrets
+(
+    user_id        UUID        NOT NULL PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+    secret         TEXT        NOT NULL,
+    created_at     timestamptz NOT NULL DEFAULT now(),
+    confirmed_at   timestamptz NULL,
+    -- The last accepted time step, so that a code cannot be replayed.
+    last_used_step BIGINT      NULL
+);
+
+-- Single-use codes for when the authenticator app is lost. Only their SHA-256 hashes are stored.
+CREATE TABLE user_recovery_codes
+(
+    user_id   UUID        NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT        NOT NULL,
+    used_at   timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,19 @@
+-- Add migration script here
+-- Existing users, i.e. the seeded one, become owners. New users always get an explicit role.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
+-- Deactivated users can neither log in nor keep using an existing session.
+ALTER TABLE users ADD COLUMN deactivated_at timestamptz NULL;
+
+-- Only the SHA-256 hash of an invitation token is stored, the token itself only ever appears in the email.
+CREATE TABLE user_invitations
+(
+    token_hash  TEXT        NOT NULL PRIMARY KEY,
+    email       TEXT        NOT NULL,
+    role        TEXT        NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by  UUID        NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at  timestamptz NOT NULL DEFAULT now(),
+    expires_at  timestamptz NOT NULL,
+    accepted_at timestamptz NULL
+);
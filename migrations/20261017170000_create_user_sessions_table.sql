@@ -0,0 +1,12 @@
+-- Add migration script here
+-- One row per logged-in session. The session state itself stays in Redis,
+-- a session whose row is gone is logged out on its next request.
+CREATE TABLE user_sessions(
+    session_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_seen_at timestamptz NOT NULL DEFAULT now(),
+    ip TEXT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
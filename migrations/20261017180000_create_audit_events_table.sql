@@ -0,0 +1,23 @@
+-- Add migration script here
+-- Append-only record of administrative actions.
+-- actor_id has no foreign key, so that events outlive the users who made them.
+CREATE TABLE audit_events(
+    event_id BIGSERIAL PRIMARY KEY,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    actor_id uuid NULL,
+    action TEXT NOT NULL,
+    target_id TEXT NULL,
+    ip TEXT NULL,
+    details JSONB NOT NULL DEFAULT '{}'
+);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
+
+CREATE FUNCTION reject_audit_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_event_changes();
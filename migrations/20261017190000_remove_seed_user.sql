@@ -0,0 +1,6 @@
+-- Add migration script here
+-- The seeded admin has a password published in this repository. It is removed unless its password
+-- has been changed since. The first admin user is now created with `zero2prod-admin create-user`.
+DELETE FROM users
+WHERE user_id = '4d0dea38-5c5a-4865-8de0-c9b84b52ea82'
+  AND password_hash = '$argon2id$v=19$m=15000,t=2,p=1$FZ4g+r3ZFVCKW7n61K2+fA$FrYEljfkD8qK3EgHY26tDoHI4Rz7fLDlz6cfQzWEsuI';
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Bearer tokens for the JSON API under /api/v1. Only the SHA-256 hash of a token is stored,
+-- the token itself is shown once, when it is created.
+CREATE TABLE api_tokens
+(
+    token_id     uuid        NOT NULL PRIMARY KEY,
+    user_id      uuid        NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    name         TEXT        NOT NULL,
+    token_hash   TEXT        NOT NULL UNIQUE,
+    scopes       TEXT[]      NOT NULL,
+    created_at   timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz NULL,
+    revoked_at   timestamptz NULL
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- A key reused for a different request is refused. Rows saved before have no fingerprint.
+ALTER TABLE idempotency
+    ADD COLUMN request_fingerprint TEXT NULL;
+-- The flash messages sent with the saved response, sent again when it is replayed.
+ALTER TABLE idempotency
+    ADD COLUMN response_flash_messages JSONB NULL;
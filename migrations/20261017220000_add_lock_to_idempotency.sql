@@ -0,0 +1,5 @@
+-- Add migration script here
+-- A key is locked while its request is processed, and can be taken over by a retry
+-- once the lock has expired, e.g. as the server was restarted. Cleared once the response is saved.
+ALTER TABLE idempotency
+    ADD COLUMN locked_until timestamptz NULL;
@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Deliveries claimed by a worker are skipped by the others until `claimed_until`,
+-- which outlives the transaction claiming them, so that emails are sent outside of it.
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN claimed_until timestamptz NULL;
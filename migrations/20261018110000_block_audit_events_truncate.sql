@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Row triggers do not fire on TRUNCATE, which would empty audit_events at once.
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_audit_event_changes();
@@ -0,0 +1,630 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{
+    change_password_in_db, check_password_strength, generate_token, hash_password,
+    revoke_other_sessions, BreachedPasswords, Password,
+};
+use crate::configuration::{PasswordHashingSettings, Settings};
+use crate::domain::{SubscriberEmail, UserRole};
+use crate::idempotency_expiring_worker::delete_expired_idempotency_key;
+use crate::issue_delivery_worker::requeue_failed_deliveries;
+use crate::routes::format_timestamp;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub const USAGE: &str = "\
+Usage: zero2prod-admin <command>
+
+Commands:
+  create-user <username> [--email <email>] [--role owner|editor|viewer] [--password-stdin]
+  disable-user <username>
+  enable-user <username>
+  reset-password <username> [--password-stdin]
+  queue list
+  queue requeue (--all | <failure_id>...)
+  purge-idempotency-keys [--older-than-hours <hours>]
+  migrate
+  help
+
+Passwords are generated and printed once, unless --password-stdin is given.
+The configuration is read the same way as the server does, see APP_ENVIRONMENT.
+";
+
+// Rows shown per table by `queue list`.
+const MAX_LISTED_ROWS: i64 = 100;
+const GENERATED_PASSWORD_LENGTH: usize = 24;
+// Same as the expiry of the idempotency expiring worker.
+const DEFAULT_IDEMPOTENCY_KEY_MAX_AGE_HOURS: u32 = 48;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    CreateUser {
+        username: String,
+        email: Option<String>,
+        role: UserRole,
+        password_from_stdin: bool,
+    },
+    DisableUser {
+        username: String,
+    },
+    EnableUser {
+        username: String,
+    },
+    ResetPassword {
+        username: String,
+        password_from_stdin: bool,
+    },
+    ListQueue,
+    /// Moves failed deliveries back to the queue, all of them when `failure_ids` is `None`.
+    RequeueFailures {
+        failure_ids: Option<Vec<Uuid>>,
+    },
+    PurgeIdempotencyKeys {
+        older_than_hours: u32,
+    },
+    Migrate,
+    Help,
+}
+
+impl Command {
+    /// Parses the command line arguments, without the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args: VecDeque<String> = args.into_iter().collect();
+        let Some(name) = args.pop_front() else {
+            return Ok(Command::Help);
+        };
+        let command = match name.as_str() {
+            "create-user" => {
+                let mut args =
+                    Arguments::parse(args, &["--email", "--role"], &["--password-stdin"])?;
+                let role = match args.value("--role") {
+                    Some(role) => UserRole::try_from(role)?,
+                    None => UserRole::Owner,
+                };
+                Command::CreateUser {
+                    username: args.single_positional("<username>")?,
+                    email: args.value("--email"),
+                    role,
+                    password_from_stdin: args.switch("--password-stdin"),
+                }
+            }
+            "disable-user" => Command::DisableUser {
+                username: Arguments::parse(args, &[], &[])?.single_positional("<username>")?,
+            },
+            "enable-user" => Command::EnableUser {
+                username: Arguments::parse(args, &[], &[])?.single_positional("<username>")?,
+            },
+            "reset-password" => {
+                let mut args = Arguments::parse(args, &[], &["--password-stdin"])?;
+                Command::ResetPassword {
+                    username: args.single_positional("<username>")?,
+                    password_from_stdin: args.switch("--password-stdin"),
+                }
+            }
+            "queue" => match args.pop_front().as_deref() {
+                Some("list") => {
+                    Arguments::parse(args, &[], &[])?.no_positional()?;
+                    Command::ListQueue
+                }
+                Some("requeue") => {
+                    let args = Arguments::parse(args, &[], &["--all"])?;
+                    match (args.switch("--all"), args.positional.is_empty()) {
+                        (true, true) => Command::RequeueFailures { failure_ids: None },
+                        (false, false) => {
+                            let failure_ids = args
+                                .positional
+                                .iter()
+                                .map(|id| {
+                                    Uuid::parse_str(id)
+                                        .map_err(|_| format!("{} is not a valid failure id.", id))
+                                })
+                                .collect::<Result<_, _>>()?;
+                            Command::RequeueFailures {
+                                failure_ids: Some(failure_ids),
+                            }
+                        }
+                        _ => return Err("Pass either --all or failure ids to requeue.".into()),
+                    }
+                }
+                _ => return Err("Expected `queue list` or `queue requeue`.".into()),
+            },
+            "purge-idempotency-keys" => {
+                let mut args = Arguments::parse(args, &["--older-than-hours"], &[])?;
+                args.no_positional()?;
+                let older_than_hours = match args.value("--older-than-hours") {
+                    Some(hours) => hours
+                        .parse()
+                        .map_err(|_| format!("{} is not a valid number of hours.", hours))?,
+                    None => DEFAULT_IDEMPOTENCY_KEY_MAX_AGE_HOURS,
+                };
+                Command::PurgeIdempotencyKeys { older_than_hours }
+            }
+            "migrate" => {
+                Arguments::parse(args, &[], &[])?.no_positional()?;
+                Command::Migrate
+            }
+            "help" | "--help" | "-h" => Command::Help,
+            other => return Err(format!("Unknown command `{}`.", other)),
+        };
+        Ok(command)
+    }
+}
+
+/// The arguments following a command: `--name value` options, `--name` switches and the rest.
+struct Arguments {
+    positional: Vec<String>,
+    values: HashMap<String, String>,
+    switches: HashSet<String>,
+}
+
+impl Arguments {
+    fn parse(
+        args: VecDeque<String>,
+        value_options: &[&str],
+        switch_options: &[&str],
+    ) -> Result<Self, String> {
+        let mut parsed = Arguments {
+            positional: Vec::new(),
+            values: HashMap::new(),
+            switches: HashSet::new(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if value_options.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} expects a value.", arg))?;
+                parsed.values.insert(arg, value);
+            } else if switch_options.contains(&arg.as_str()) {
+                parsed.switches.insert(arg);
+            } else if arg.starts_with('-') {
+                return Err(format!("Unknown option `{}`.", arg));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn value(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    fn single_positional(&mut self, name: &str) -> Result<String, String> {
+        match self.positional.len() {
+            1 => Ok(self.positional.remove(0)),
+            0 => Err(format!("Missing {}.", name)),
+            _ => Err(format!("Expected a single {}.", name)),
+        }
+    }
+
+    fn no_positional(&self) -> Result<(), String> {
+        match self.positional.first() {
+            Some(arg) => Err(format!("Unexpected argument `{}`.", arg)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs the commands of `zero2prod-admin` against the database of the configuration.
+pub struct AdminCli {
+    pool: PgPool,
+    password_hashing: PasswordHashingSettings,
+    breached_passwords: BreachedPasswords,
+}
+
+impl AdminCli {
+    pub fn new(configuration: &Settings) -> Result<Self, anyhow::Error> {
+        let breached_passwords = match &configuration.password_policy.breached_passwords_file {
+            Some(path) => BreachedPasswords::load(path)?,
+            None => BreachedPasswords::default(),
+        };
+        Ok(Self {
+            pool: get_connection_pool(&configuration.database),
+            password_hashing: configuration.password_hashing.clone(),
+            breached_passwords,
+        })
+    }
+
+    /// Runs `command`, returning what to print on success.
+    pub async fn execute(&self, command: Command) -> Result<String, anyhow::Error> {
+        match command {
+            Command::CreateUser {
+                username,
+                email,
+                role,
+                password_from_stdin,
+            } => {
+                self.create_user(username, email, role, password_from_stdin)
+                    .await
+            }
+            Command::DisableUser { username } => self.set_deactivated(&username, true).await,
+            Command::EnableUser { username } => self.set_deactivated(&username, false).await,
+            Command::ResetPassword {
+                username,
+                password_from_stdin,
+            } => self.reset_password(&username, password_from_stdin).await,
+            Command::ListQueue => self.list_queue().await,
+            Command::RequeueFailures { failure_ids } => self.requeue_failures(failure_ids).await,
+            Command::PurgeIdempotencyKeys { older_than_hours } => {
+                let n_deleted = delete_expired_idempotency_key(&self.pool, older_than_hours)
+                    .await
+                    .context("Failed to purge the idempotency keys.")?;
+                Ok(format!(
+                    "Deleted {} idempotency keys older than {} hours.\n",
+                    n_deleted, older_than_hours
+                ))
+            }
+            Command::Migrate => {
+                sqlx::migrate!("./migrations")
+                    .run(&self.pool)
+                    .await
+                    .context("Failed to run the database migrations.")?;
+                Ok("The database schema is up to date.\n".into())
+            }
+            Command::Help => Ok(USAGE.into()),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_user(
+        &self,
+        username: String,
+        email: Option<String>,
+        role: UserRole,
+        password_from_stdin: bool,
+    ) -> Result<String, anyhow::Error> {
+        let username = username.trim();
+        if username.is_empty() {
+            anyhow::bail!("The username must not be empty.");
+        }
+        let email = email
+            .map(SubscriberEmail::parse)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        let (password, generated) = self.new_password(username, password_from_stdin)?;
+        let password_hash = hash_password(password, &self.password_hashing).await?;
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        let user_id = Uuid::new_v4();
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, password_hash, email, role)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id,
+            username,
+            password_hash.expose_secret(),
+            email.as_ref().map(|email| email.as_ref()),
+            role.as_str(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to create the user.")?;
+        if result.rows_affected() == 0 {
+            anyhow::bail!("A user with this username or email address already exists.");
+        }
+        let event = AuditEvent::from_command_line(AuditAction::UserCreated)
+            .target(user_id)
+            .details(serde_json::json!({ "source": "command_line", "role": role.as_str() }));
+        record_audit_event(&mut *transaction, event).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the new user.")?;
+
+        let mut output = format!("Created the {} user {} ({}).\n", role, username, user_id);
+        if let Some(generated) = generated {
+            writeln!(output, "Password: {}", generated.expose_secret()).unwrap();
+        }
+        Ok(output)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_deactivated(
+        &self,
+        username: &str,
+        deactivated: bool,
+    ) -> Result<String, anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        // Sessions of a deactivated user are refused by `reject_anonymous_user`.
+        let user_id = sqlx::query_scalar!(
+            r#"
+            UPDATE users
+            SET deactivated_at = CASE WHEN $2 THEN COALESCE(deactivated_at, now()) END
+            WHERE username = $1
+            RETURNING user_id
+            "#,
+            username,
+            deactivated,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to update the status of the user.")?
+        .ok_or_else(|| anyhow::anyhow!("There is no user named {}.", username))?;
+
+        let (action, message) = if deactivated {
+            (AuditAction::UserDeactivated, "disabled")
+        } else {
+            (AuditAction::UserReactivated, "enabled")
+        };
+        let event = AuditEvent::from_command_line(action).target(user_id);
+        record_audit_event(&mut *transaction, event).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the status of the user.")?;
+        Ok(format!("The user {} has been {}.\n", username, message))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn reset_password(
+        &self,
+        username: &str,
+        password_from_stdin: bool,
+    ) -> Result<String, anyhow::Error> {
+        let user_id =
+            sqlx::query_scalar!("SELECT user_id FROM users WHERE username = $1", username)
+                .fetch_optional(&self.pool)
+                .await
+                .context("Failed to look up the user.")?
+                .ok_or_else(|| anyhow::anyhow!("There is no user named {}.", username))?;
+        let (password, generated) = self.new_password(username, password_from_stdin)?;
+        let password_hash = hash_password(password, &self.password_hashing).await?;
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        change_password_in_db(user_id, &password_hash, &mut *transaction).await?;
+        let n_revoked = revoke_other_sessions(user_id, None, &mut *transaction).await?;
+        let event = AuditEvent::from_command_line(AuditAction::PasswordReset)
+            .target(user_id)
+            .details(
+                serde_json::json!({ "source": "command_line", "revoked_sessions": n_revoked }),
+            );
+        record_audit_event(&mut *transaction, event).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the new password.")?;
+
+        let mut output = format!(
+            "The password of {} has been reset, {} sessions have been revoked.\n",
+            username, n_revoked
+        );
+        if let Some(generated) = generated {
+            writeln!(output, "Password: {}", generated.expose_secret()).unwrap();
+        }
+        Ok(output)
+    }
+
+    /// Reads the new password of `username` from stdin, or generates one.
+    /// A generated password is returned a second time, to be shown to the operator.
+    fn new_password(
+        &self,
+        username: &str,
+        from_stdin: bool,
+    ) -> Result<(Password, Option<SecretString>), anyhow::Error> {
+        let (password, generated) = if from_stdin {
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read the password from stdin.")?;
+            (line.trim_end_matches(['\r', '\n']).to_string(), false)
+        } else {
+            (generate_token(GENERATED_PASSWORD_LENGTH), true)
+        };
+        let password = Password::parse(password)?;
+        check_password_strength(&password, username, &self.breached_passwords)?;
+        let generated = generated.then(|| password.inner_ref().clone());
+        Ok((password, generated))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_queue(&self) -> Result<String, anyhow::Error> {
+        let mut output = String::new();
+        let n_queued = sqlx::query_scalar!(r#"SELECT count(*) as "n!" FROM issue_delivery_queue"#)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to count the queued deliveries.")?;
+        writeln!(output, "Queued deliveries: {}", n_queued).unwrap();
+        let queued = sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id, subscriber_email, n_retries, retry_after, enqueued_at
+            FROM issue_delivery_queue
+            ORDER BY enqueued_at, subscriber_email
+            LIMIT $1
+            "#,
+            MAX_LISTED_ROWS,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch the queued deliveries.")?;
+        for task in queued {
+            writeln!(
+                output,
+                "  issue={} recipient={} retries={} retry_after={} enqueued_at={}",
+                task.newsletter_issue_id,
+                task.subscriber_email,
+                task.n_retries,
+                format_timestamp(task.retry_after),
+                format_timestamp(Some(task.enqueued_at)),
+            )
+            .unwrap();
+        }
+
+        let n_failed =
+            sqlx::query_scalar!(r#"SELECT count(*) as "n!" FROM issue_delivery_failures"#)
+                .fetch_one(&self.pool)
+                .await
+                .context("Failed to count the failed deliveries.")?;
+        writeln!(output, "Failed deliveries: {}", n_failed).unwrap();
+        let failures = sqlx::query!(
+            r#"
+            SELECT failure_id, newsletter_issue_id, subscriber_email, n_retries, last_error, failed_at
+            FROM issue_delivery_failures
+            ORDER BY failed_at DESC
+            LIMIT $1
+            "#,
+            MAX_LISTED_ROWS,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch the failed deliveries.")?;
+        for failure in failures {
+            writeln!(
+                output,
+                "  failure_id={} issue={} recipient={} retries={} failed_at={} error={}",
+                failure.failure_id,
+                failure.newsletter_issue_id,
+                failure.subscriber_email,
+                failure.n_retries,
+                format_timestamp(Some(failure.failed_at)),
+                failure.last_error,
+            )
+            .unwrap();
+        }
+        Ok(output)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn requeue_failures(
+        &self,
+        failure_ids: Option<Vec<Uuid>>,
+    ) -> Result<String, anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")?;
+        let failure_ids = match failure_ids {
+            Some(failure_ids) => failure_ids,
+            None => sqlx::query_scalar!("SELECT failure_id FROM issue_delivery_failures")
+                .fetch_all(&mut *transaction)
+                .await
+                .context("Failed to fetch the failed deliveries.")?,
+        };
+        let n_requeued = requeue_failed_deliveries(&mut transaction, &failure_ids)
+            .await
+            .context("Failed to requeue failed deliveries.")?;
+        let event = AuditEvent::from_command_line(AuditAction::FailedDeliveriesRequeued)
+            .details(serde_json::json!({ "source": "command_line", "requeued": n_requeued }));
+        record_audit_event(&mut *transaction, event).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit requeued deliveries.")?;
+        Ok(format!(
+            "{} failed deliveries have been requeued.\n",
+            n_requeued
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+    use crate::domain::UserRole;
+    use claims::{assert_err, assert_ok_eq};
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        Command::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn create_user_takes_options_in_any_order() {
+        assert_ok_eq!(
+            parse(&[
+                "create-user",
+                "--role",
+                "editor",
+                "alice",
+                "--password-stdin"
+            ]),
+            Command::CreateUser {
+                username: "alice".into(),
+                email: None,
+                role: UserRole::Editor,
+                password_from_stdin: true,
+            }
+        );
+    }
+
+    #[test]
+    fn new_users_are_owners_by_default() {
+        assert_ok_eq!(
+            parse(&["create-user", "alice", "--email", "alice@example.com"]),
+            Command::CreateUser {
+                username: "alice".into(),
+                email: Some("alice@example.com".into()),
+                role: UserRole::Owner,
+                password_from_stdin: false,
+            }
+        );
+    }
+
+    #[test]
+    fn requeue_needs_either_all_or_failure_ids() {
+        assert_ok_eq!(
+            parse(&["queue", "requeue", "--all"]),
+            Command::RequeueFailures { failure_ids: None }
+        );
+        assert_err!(parse(&["queue", "requeue"]));
+        assert_err!(parse(&[
+            "queue",
+            "requeue",
+            "--all",
+            "4d0dea38-5c5a-4865-8de0-c9b84b52ea82"
+        ]));
+        assert_err!(parse(&["queue", "requeue", "not-a-uuid"]));
+    }
+
+    #[test]
+    fn unknown_commands_and_options_are_rejected() {
+        assert_err!(parse(&["drop-database"]));
+        assert_err!(parse(&["disable-user", "alice", "--force"]));
+        assert_err!(parse(&["disable-user", "alice", "-f"]));
+        assert_err!(parse(&["migrate", "--dry-run"]));
+        assert_err!(parse(&["disable-user"]));
+        assert_err!(parse(&["create-user", "alice", "--role"]));
+        assert_err!(parse(&["create-user", "alice", "--role", "admin"]));
+    }
+
+    #[test]
+    fn idempotency_keys_can_be_purged_after_more_than_255_hours() {
+        assert_ok_eq!(
+            parse(&["purge-idempotency-keys", "--older-than-hours", "720"]),
+            Command::PurgeIdempotencyKeys {
+                older_than_hours: 720
+            }
+        );
+        assert_err!(parse(&[
+            "purge-idempotency-keys",
+            "--older-than-hours",
+            "-1"
+        ]));
+    }
+
+    #[test]
+    fn no_command_shows_the_usage() {
+        assert_ok_eq!(parse(&[]), Command::Help);
+    }
+}
@@ -0,0 +1,97 @@
+/// # Administrative actions recorded in `audit_events`
+/// The string representation is what gets stored, so existing variants must keep it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    LoginSucceeded,
+    LoginFailed,
+    LoggedOut,
+    SessionRevoked,
+    PasswordChanged,
+    PasswordReset,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    NewsletterIssueCreated,
+    NewsletterIssueUpdated,
+    FailedDeliveriesRequeued,
+    SubscriberCreated,
+    SubscriberConfirmed,
+    SubscriberDeleted,
+    UserInvited,
+    InvitationAccepted,
+    UserCreated,
+    UserRoleChanged,
+    UserDeactivated,
+    UserReactivated,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 22] = [
+        AuditAction::LoginSucceeded,
+        AuditAction::LoginFailed,
+        AuditAction::LoggedOut,
+        AuditAction::SessionRevoked,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::NewsletterIssueCreated,
+        AuditAction::NewsletterIssueUpdated,
+        AuditAction::FailedDeliveriesRequeued,
+        AuditAction::SubscriberCreated,
+        AuditAction::SubscriberConfirmed,
+        AuditAction::SubscriberDeleted,
+        AuditAction::UserInvited,
+        AuditAction::InvitationAccepted,
+        AuditAction::UserCreated,
+        AuditAction::UserRoleChanged,
+        AuditAction::UserDeactivated,
+        AuditAction::UserReactivated,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSucceeded => "login.succeeded",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::LoggedOut => "logout",
+            AuditAction::SessionRevoked => "session.revoked",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::TwoFactorEnabled => "two_factor.enabled",
+            AuditAction::TwoFactorDisabled => "two_factor.disabled",
+            AuditAction::NewsletterIssueCreated => "newsletter_issue.created",
+            AuditAction::NewsletterIssueUpdated => "newsletter_issue.updated",
+            AuditAction::FailedDeliveriesRequeued => "failed_deliveries.requeued",
+            AuditAction::SubscriberCreated => "subscriber.created",
+            AuditAction::SubscriberConfirmed => "subscriber.confirmed",
+            AuditAction::SubscriberDeleted => "subscriber.deleted",
+            AuditAction::UserInvited => "user.invited",
+            AuditAction::InvitationAccepted => "invitation.accepted",
+            AuditAction::UserCreated => "user.created",
+            AuditAction::UserRoleChanged => "user.role_changed",
+            AuditAction::UserDeactivated => "user.deactivated",
+            AuditAction::UserReactivated => "user.reactivated",
+            AuditAction::ApiTokenCreated => "api_token.created",
+            AuditAction::ApiTokenRevoked => "api_token.revoked",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid audit action.", s))
+    }
+}
@@ -0,0 +1,74 @@
+use crate::audit::AuditAction;
+use crate::utils::client_ip;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+pub struct AuditEvent {
+    action: AuditAction,
+    actor_id: Option<Uuid>,
+    target_id: Option<String>,
+    ip: Option<String>,
+    details: serde_json::Value,
+}
+
+impl AuditEvent {
+    /// `action` done by `actor_id`, from the client address of `request`.
+    /// The actor is unknown for failed logins with a wrong username.
+    pub fn new(action: AuditAction, actor_id: Option<Uuid>, request: &HttpRequest) -> Self {
+        Self {
+            action,
+            actor_id,
+            target_id: None,
+            ip: client_ip(request).map(|ip| ip.to_string()),
+            details: serde_json::json!({}),
+        }
+    }
+
+    /// `action` done with `zero2prod-admin`, which has neither an actor nor a peer address.
+    pub fn from_command_line(action: AuditAction) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            target_id: None,
+            ip: None,
+            details: serde_json::json!({ "source": "command_line" }),
+        }
+    }
+
+    /// The user, issue, etc. the action was done to.
+    pub fn target(mut self, target_id: impl ToString) -> Self {
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Appends `event` to `audit_events`. Pass the transaction of the audited change, if any,
+/// so that the event is only recorded if the change is committed.
+#[tracing::instrument(name = "Record audit event", skip_all, fields(action = %event.action))]
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    event: AuditEvent,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (actor_id, action, target_id, ip, details)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        event.actor_id,
+        event.action.as_str(),
+        event.target_id,
+        event.ip,
+        event.details,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record the audit event.")?;
+    Ok(())
+}
@@ -0,0 +1,7 @@
+mod action;
+mod event;
+mod query;
+
+pub use action::AuditAction;
+pub use event::{record_audit_event, AuditEvent};
+pub use query::{get_audit_events, AuditFilter, AuditQuery, StoredAuditEvent};
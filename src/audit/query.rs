@@ -0,0 +1,166 @@
+use crate::audit::AuditAction;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// Query string of `/admin/audit` and its CSV export. Empty fields are not filtered on.
+#[derive(Deserialize, Default)]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    /// Username of the actor.
+    pub actor: Option<String>,
+    pub since: Option<NaiveDate>,
+    /// Inclusive.
+    pub until: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    pub fn parse(query: AuditQuery) -> Result<Self, String> {
+        let action = non_empty(query.action)
+            .map(AuditAction::try_from)
+            .transpose()?;
+        let since = non_empty(query.since).map(parse_date).transpose()?;
+        let until = non_empty(query.until).map(parse_date).transpose()?;
+        Ok(Self {
+            action,
+            actor: non_empty(query.actor),
+            since,
+            until,
+        })
+    }
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// `<input type="date">` submits `YYYY-MM-DD`.
+fn parse_date(s: String) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(|_| format!("{} is not a valid date.", s))
+}
+
+pub struct StoredAuditEvent {
+    pub event_id: i64,
+    pub occurred_at: DateTime<Utc>,
+    /// Username of the actor, if known and still existing.
+    pub actor: Option<String>,
+    pub action: String,
+    pub target_id: Option<String>,
+    pub ip: Option<String>,
+    pub details: serde_json::Value,
+}
+
+/// The events matching `filter`, most recent first, at most `limit` of them if given.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_events(
+    filter: &AuditFilter,
+    limit: Option<i64>,
+    pool: &PgPool,
+) -> Result<Vec<StoredAuditEvent>, anyhow::Error> {
+    let since = filter
+        .since
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|since| since.and_utc());
+    let until = filter
+        .until
+        .and_then(|date| date.succ_opt())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|until| until.and_utc());
+    let events = sqlx::query_as!(
+        StoredAuditEvent,
+        r#"
+        SELECT
+            e.event_id,
+            e.occurred_at,
+            u.username as "actor?",
+            e.action,
+            e.target_id,
+            e.ip,
+            e.details
+        FROM audit_events e
+        LEFT JOIN users u ON u.user_id = e.actor_id
+        WHERE
+            ($1::text IS NULL OR e.action = $1) AND
+            ($2::text IS NULL OR u.username = $2) AND
+            ($3::timestamptz IS NULL OR e.occurred_at >= $3) AND
+            ($4::timestamptz IS NULL OR e.occurred_at < $4)
+        ORDER BY e.event_id DESC
+        LIMIT $5
+        "#,
+        filter.action.map(|action| action.as_str()),
+        filter.actor,
+        since,
+        until,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the audit events.")?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::*;
+
+    #[test]
+    fn empty_fields_are_not_filtered_on() {
+        let query = AuditQuery {
+            action: Some("".into()),
+            actor: Some("  ".into()),
+            since: None,
+            until: Some("".into()),
+        };
+        assert_ok_eq!(
+            AuditFilter::parse(query),
+            AuditFilter {
+                action: None,
+                actor: None,
+                since: None,
+                until: None,
+            }
+        );
+    }
+
+    #[test]
+    fn filled_fields_are_parsed() {
+        let query = AuditQuery {
+            action: Some("login.failed".into()),
+            actor: Some("admin".into()),
+            since: Some("2026-10-01".into()),
+            until: Some("2026-10-17".into()),
+        };
+        assert_ok_eq!(
+            AuditFilter::parse(query),
+            AuditFilter {
+                action: Some(AuditAction::LoginFailed),
+                actor: Some("admin".into()),
+                since: NaiveDate::from_ymd_opt(2026, 10, 1),
+                until: NaiveDate::from_ymd_opt(2026, 10, 17),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_actions_and_invalid_dates_are_rejected() {
+        assert_err!(AuditFilter::parse(AuditQuery {
+            action: Some("drop.table".into()),
+            ..Default::default()
+        }));
+        assert_err!(AuditFilter::parse(AuditQuery {
+            since: Some("17/10/2026".into()),
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,240 @@
+use crate::authentication::token::{generate_token, hash_token};
+use crate::domain::UserRole;
+use crate::routes::ApiError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+// Makes API tokens recognisable, e.g. by secret scanners, and tells them apart from other tokens.
+const API_TOKEN_PREFIX: &str = "z2p_";
+
+/// # What an API token may be used for
+/// A token is issued with a subset of the scopes allowed by the role of its user,
+/// and the role is checked again on every request, as it may have changed since.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    SubscribersRead,
+    SubscribersWrite,
+    IssuesRead,
+    IssuesWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+        ApiScope::IssuesRead,
+        ApiScope::IssuesWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+            ApiScope::IssuesRead => "issues:read",
+            ApiScope::IssuesWrite => "issues:write",
+        }
+    }
+
+    /// Viewers only get read access, as in the admin pages.
+    pub fn is_allowed_for(&self, role: UserRole) -> bool {
+        match self {
+            ApiScope::SubscribersRead | ApiScope::IssuesRead => true,
+            ApiScope::SubscribersWrite | ApiScope::IssuesWrite => role.can_publish(),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for ApiScope {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid API scope.", s))
+    }
+}
+
+/// The user an API request is made on behalf of, exposed to the handlers as request data.
+#[derive(Debug, Clone)]
+pub struct ApiPrincipal {
+    pub user_id: Uuid,
+    pub token_id: Uuid,
+    role: UserRole,
+    scopes: Vec<ApiScope>,
+}
+
+impl ApiPrincipal {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope) && scope.is_allowed_for(self.role)
+    }
+}
+
+/// Creates a token named `name` for `user_id`, returning its id and the token itself.
+/// Only its hash is stored, so this is the only time the token is known.
+/// The token is stored in `transaction`, for the caller to commit it with its audit event.
+#[tracing::instrument(skip(transaction))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    role: UserRole,
+    name: &str,
+    scopes: &[ApiScope],
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(Uuid, String), anyhow::Error> {
+    if let Some(scope) = scopes.iter().find(|scope| !scope.is_allowed_for(role)) {
+        anyhow::bail!("The {} role cannot be granted the {} scope.", role, scope);
+    }
+    let token_id = Uuid::new_v4();
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_token(32));
+    let scopes: Vec<String> = scopes.iter().map(|scope| scope.to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        token_id,
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to store the API token.")?;
+    Ok((token_id, token))
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The API tokens of `user_id`, including the revoked ones, newest first.
+#[tracing::instrument(skip(pool))]
+pub async fn get_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the API tokens of the user.")?;
+    Ok(tokens)
+}
+
+/// Revokes one API token of `user_id`. Returns `false` if there is no such token,
+/// or if it has already been revoked.
+#[tracing::instrument(skip(transaction))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE user_id = $1 AND token_id = $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        token_id,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns `None` if the token is unknown or revoked, or if its user has been deactivated.
+/// Otherwise the token is marked as used.
+#[tracing::instrument(skip_all)]
+pub async fn authenticate_api_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<ApiPrincipal>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH used_token AS (
+            UPDATE api_tokens
+            SET last_used_at = now()
+            WHERE token_hash = $1 AND revoked_at IS NULL
+            RETURNING token_id, user_id, scopes
+        )
+        SELECT token_id, user_id, scopes, role
+        FROM used_token
+        JOIN users USING (user_id)
+        WHERE deactivated_at IS NULL
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the API token.")?;
+    row.map(|row| {
+        Ok(ApiPrincipal {
+            user_id: row.user_id,
+            token_id: row.token_id,
+            role: UserRole::try_from(row.role).map_err(anyhow::Error::msg)?,
+            // Scopes which no longer exist are ignored rather than failing every request.
+            scopes: row
+                .scopes
+                .into_iter()
+                .filter_map(|scope| ApiScope::try_from(scope).ok())
+                .collect(),
+        })
+    })
+    .transpose()
+}
+
+/// Lets only requests with a valid `Authorization: Bearer` API token through, and exposes
+/// their `ApiPrincipal` to the handlers as request data.
+pub async fn reject_invalid_api_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(token) = bearer_token(&req) else {
+        return Err(ApiError::Unauthorized("Missing or malformed bearer token.").into());
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| anyhow::anyhow!("The database pool is not registered."))
+        .map_err(ApiError::Unexpected)?;
+    let Some(principal) = authenticate_api_token(&token, pool)
+        .await
+        .map_err(ApiError::Unexpected)?
+    else {
+        return Err(ApiError::Unauthorized("The API token is invalid or has been revoked.").into());
+    };
+    req.extensions_mut().insert(principal);
+    next.call(req).await
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then(|| token.to_string())
+}
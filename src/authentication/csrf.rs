@@ -0,0 +1,78 @@
+use crate::authentication::token::{generate_token, hash_token};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_session::SessionInsertError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+const INVALID_CSRF_TOKEN_MESSAGE: &str =
+    "Your form has expired, please reload the page and submit it again.";
+
+/// The CSRF token of the session, to be submitted with every form posted to `/admin`.
+#[derive(Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// The hidden field to put in the forms.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="csrf_token" value="{}">"#,
+            self.0
+        )
+    }
+}
+
+/// Stores a new CSRF token in `session`, to be called as it is logged in.
+/// Issuing it up front keeps concurrent first requests of the session from each
+/// storing a token of their own, all but one of which would then be rejected.
+pub fn issue_csrf_token(session: &TypedSession) -> Result<String, SessionInsertError> {
+    let token = generate_token(32);
+    session.insert_csrf_token(&token)?;
+    Ok(token)
+}
+
+/// Rejects form submissions whose `csrf_token` field does not match the token of the session,
+/// so that other sites cannot post forms on behalf of a logged-in user.
+/// The token is created on the first request of the session, and exposed to the handlers
+/// as `CsrfToken` request data.
+pub async fn reject_invalid_csrf_token(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    // Sessions opened before the token was issued at login get one now.
+    let token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => issue_csrf_token(&session).map_err(e500)?,
+    };
+
+    if !req.method().is_safe() {
+        // The form is read here and handed back to the handler untouched.
+        let body = req.extract::<web::Bytes>().await?;
+        let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).unwrap_or_default();
+        let submitted = fields
+            .into_iter()
+            .find(|(name, _)| name == "csrf_token")
+            .map(|(_, value)| value);
+        // Comparing the hashes keeps the comparison time independent of the token.
+        if submitted.map(|submitted| hash_token(&submitted)) != Some(hash_token(&token)) {
+            tracing::warn!("Missing or invalid CSRF token");
+            // An `Ok` response, so that the flash message reaches the page loaded next.
+            FlashMessage::error(INVALID_CSRF_TOKEN_MESSAGE).send();
+            let response = HttpResponse::Forbidden().body(INVALID_CSRF_TOKEN_MESSAGE);
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+        req.set_payload(Payload::from(body));
+    }
+
+    req.extensions_mut().insert(CsrfToken(token));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
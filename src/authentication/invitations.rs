@@ -0,0 +1,144 @@
+use crate::authentication::token::{generate_token, hash_token};
+use crate::domain::{SubscriberEmail, UserRole};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+pub enum InvitationOutcome {
+    /// The token to put in the invitation link. Only its hash is stored.
+    Created(String),
+    /// There is already a user with this email address.
+    EmailInUse,
+}
+
+/// Invites `email` to join as `role`, valid for `ttl`. The invitation is stored in `transaction`,
+/// for the caller to commit it with its audit event.
+#[tracing::instrument(name = "Create user invitation", skip(transaction))]
+pub async fn create_invitation(
+    email: &SubscriberEmail,
+    role: UserRole,
+    invited_by: Uuid,
+    ttl: chrono::Duration,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<InvitationOutcome, anyhow::Error> {
+    let email_in_use = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE email = $1) as "exists!""#,
+        email.as_ref(),
+    )
+    .fetch_one(transaction.deref_mut())
+    .await
+    .context("Failed to check whether the email address is in use.")?;
+    if email_in_use {
+        return Ok(InvitationOutcome::EmailInUse);
+    }
+
+    let token = generate_token(32);
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (token_hash, email, role, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        hash_token(&token),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        Utc::now() + ttl,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to store the invitation.")?;
+    Ok(InvitationOutcome::Created(token))
+}
+
+pub enum AcceptOutcome {
+    Accepted {
+        user_id: Uuid,
+    },
+    /// The token is unknown, expired or has already been used.
+    InvalidToken,
+    UsernameTaken,
+}
+
+/// Creates the invited user. The email address and the role come from the invitation.
+/// It only takes effect once `transaction` is committed. The password is hashed with
+/// `hash_password` beforehand, as hashing takes a while, so that no row is locked meanwhile.
+#[tracing::instrument(
+    name = "Accept user invitation",
+    skip(token, password_hash, transaction)
+)]
+pub async fn accept_invitation(
+    token: &str,
+    username: &str,
+    password_hash: &SecretString,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<AcceptOutcome, anyhow::Error> {
+    let invitation = sqlx::query!(
+        r#"
+        UPDATE user_invitations
+        SET accepted_at = now()
+        WHERE
+            token_hash = $1 AND
+            accepted_at IS NULL AND
+            expires_at > now() AND
+            NOT EXISTS (SELECT 1 FROM users WHERE users.email = user_invitations.email)
+        RETURNING email, role
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await
+    .context("Failed to mark the invitation as accepted.")?;
+    let Some(invitation) = invitation else {
+        return Ok(AcceptOutcome::InvalidToken);
+    };
+
+    let user_id = Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        invitation.email,
+        invitation.role,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to create the invited user.")?;
+    if result.rows_affected() == 0 {
+        // Dropping the transaction rolls back the invitation update, so it can be used again.
+        return Ok(AcceptOutcome::UsernameTaken);
+    }
+    Ok(AcceptOutcome::Accepted { user_id })
+}
+
+pub struct PendingInvitation {
+    pub email: String,
+    pub role: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_pending_invitations(
+    pool: &PgPool,
+) -> Result<Vec<PendingInvitation>, anyhow::Error> {
+    let invitations = sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the pending invitations.")?;
+    Ok(invitations)
+}
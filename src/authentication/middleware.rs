@@ -1,10 +1,13 @@
+use crate::domain::UserRole;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
 use actix_web::middleware::Next;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage};
+use anyhow::Context;
+use sqlx::PgPool;
 use std::fmt::Display;
 use std::ops::Deref;
 use uuid::Uuid;
@@ -26,6 +29,8 @@ impl Deref for UserId {
     }
 }
 
+/// Lets only logged-in, active users through, and exposes their `UserId` and `UserRole`
+/// to the handlers as request data.
 pub async fn reject_anonymous_user(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -34,15 +39,85 @@ pub async fn reject_anonymous_user(
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
-        }
-        None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, response).into())
-        }
+    let Some(user_id) = session.get_user_id().map_err(e500)? else {
+        return Err(login_redirect("The user has not logged in"));
+    };
+    let Some(session_id) = session.get_session_id().map_err(e500)? else {
+        session.log_out();
+        return Err(login_redirect("The session has not been recorded"));
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered."))?;
+    let Some(access) = get_user_access(user_id, session_id, pool)
+        .await
+        .map_err(e500)?
+    else {
+        session.log_out();
+        return Err(login_redirect(
+            "The session has been revoked, or the user has been deactivated",
+        ));
+    };
+    // A session which only passed the password check, e.g. one opened before
+    // two-factor authentication was enabled, is not good enough.
+    if access.two_factor_enabled && !session.is_two_factor_verified().map_err(e500)? {
+        session.log_out();
+        return Err(login_redirect(
+            "The session has not passed two-factor authentication",
+        ));
     }
+    req.extensions_mut().insert(UserId(user_id));
+    req.extensions_mut().insert(access.role);
+    next.call(req).await
+}
+
+fn login_redirect(reason: &'static str) -> actix_web::Error {
+    let response = see_other("/login");
+    InternalError::from_response(anyhow::anyhow!(reason), response).into()
+}
+
+struct UserAccess {
+    role: UserRole,
+    two_factor_enabled: bool,
+}
+
+/// Returns `None` if the session has been revoked, or if the user has been deleted
+/// or deactivated since logging in. Otherwise the session is marked as seen.
+#[tracing::instrument(skip(pool))]
+async fn get_user_access(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<UserAccess>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH seen_session AS (
+            UPDATE user_sessions
+            SET last_seen_at = now()
+            WHERE session_id = $2 AND user_id = $1
+            RETURNING user_id
+        )
+        SELECT
+            role,
+            EXISTS (
+                SELECT 1 FROM user_totp_secrets
+                WHERE user_id = $1 AND confirmed_at IS NOT NULL
+            ) as "two_factor_enabled!"
+        FROM users
+        JOIN seen_session USING (user_id)
+        WHERE deactivated_at IS NULL
+        "#,
+        user_id,
+        session_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the role of the user.")?;
+    row.map(|row| {
+        Ok(UserAccess {
+            role: UserRole::try_from(row.role).map_err(anyhow::Error::msg)?,
+            two_factor_enabled: row.two_factor_enabled,
+        })
+    })
+    .transpose()
 }
@@ -1,5 +1,35 @@
+mod api_tokens;
+mod csrf;
+mod invitations;
 mod middleware;
 mod password;
+mod password_policy;
+mod password_reset;
+mod rate_limit;
+mod sessions;
+mod token;
+mod two_factor;
 
+pub use api_tokens::{
+    create_api_token, get_api_tokens, reject_invalid_api_token, revoke_api_token, ApiPrincipal,
+    ApiScope,
+};
+pub use csrf::{issue_csrf_token, reject_invalid_csrf_token, CsrfToken};
+pub use invitations::{
+    accept_invitation, create_invitation, get_pending_invitations, AcceptOutcome, InvitationOutcome,
+};
 pub use middleware::{reject_anonymous_user, UserId};
-pub use password::{change_password_in_db, validate_credentials, AuthError, Credentials, Password};
+pub use password::{
+    change_password_in_db, hash_password, validate_credentials, AuthError, Credentials, Password,
+};
+pub use password_policy::{check_password_strength, BreachedPasswords};
+pub use password_reset::{
+    consume_reset_token, get_reset_token_username, issue_reset_token, ResetToken,
+};
+pub use rate_limit::LoginRateLimiter;
+pub use sessions::{get_user_sessions, record_session, revoke_other_sessions, revoke_session};
+pub(crate) use token::generate_token;
+pub use two_factor::{
+    confirm_totp_enrollment, disable_two_factor, get_two_factor_status, start_totp_enrollment,
+    two_factor_enabled, verify_second_factor, TwoFactorStatus,
+};
@@ -1,9 +1,10 @@
+use crate::configuration::PasswordHashingSettings;
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, SecretString};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
 use argon2::password_hash::SaltString;
 use uuid::Uuid;
@@ -22,18 +23,21 @@ pub struct Credentials {
     pub password: SecretString,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// Checks `credentials` against the stored hash. On success, a hash computed with weaker
+/// parameters than `hashing`, or with another algorithm or version, is replaced by a new one.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, hashing, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    hashing: &PasswordHashingSettings,
     pool: &PgPool,
 ) -> Result<Uuid, AuthError> {
-    // These two lines are for forcing calculating hash in blocking task.
+    let params = hashing
+        .params()
+        .context("Invalid password hashing settings.")?;
+    // Unknown usernames are checked against a made-up hash with the current parameters,
+    // so that they take as long to reject as wrong passwords.
     let mut user_id = None;
-    let mut expected_password_hash = SecretString::new(Box::from(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
-    ));
+    let mut expected_password_hash = dummy_password_hash(&params);
 
     // Fetch user information from the database
     if let Some((stored_user_id, stored_hash_password)) =
@@ -43,15 +47,37 @@ pub async fn validate_credentials(
         expected_password_hash = stored_hash_password;
     }
 
+    let password_candidate = credentials.password.clone();
+    let stored_password_hash = expected_password_hash.clone();
     spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+        verify_password_hash(stored_password_hash, password_candidate)
     })
     .await
     .context("Failed to spawn blocking task.")??; // Nested Error => Result<Result<(), PublishError>, Error>??;
 
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+
+    if needs_rehash(&expected_password_hash, &params) {
+        // The login goes on regardless, the upgrade is tried again on the next one.
+        if let Err(e) = rehash_password(
+            user_id,
+            expected_password_hash,
+            credentials.password,
+            params,
+            pool,
+        )
+        .await
+        {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade the password hash."
+            );
+        }
+    }
+    Ok(user_id)
 }
 
 #[tracing::instrument(
@@ -63,6 +89,7 @@ fn verify_password_hash(
     password_candidate: SecretString,
 ) -> Result<(), AuthError> {
     // Calculate the password hash by using the password_hash stored in the database, following PHC string format.
+    // The algorithm, version and parameters all come from the stored hash.
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
     Argon2::default()
@@ -76,6 +103,66 @@ fn verify_password_hash(
     Ok(())
 }
 
+/// A well-formed hash which no password matches, costing `params` to verify.
+fn dummy_password_hash(params: &Params) -> SecretString {
+    SecretString::new(Box::from(format!(
+        "$argon2id$v=19$m={},t={},p={}$\
+        gZiV/M1gPc22ElAH/Jh1Hw$\
+        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+        params.m_cost(),
+        params.t_cost(),
+        params.p_cost(),
+    )))
+}
+
+/// Whether `password_hash` falls short of what new hashes are computed with.
+fn needs_rehash(password_hash: &SecretString, params: &Params) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+        return true;
+    };
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(&password_hash) {
+        Ok(current) => {
+            current.m_cost() < params.m_cost()
+                || current.t_cost() < params.t_cost()
+                || current.p_cost() < params.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(current_password_hash, password, params, pool)
+)]
+async fn rehash_password(
+    user_id: Uuid,
+    current_password_hash: SecretString,
+    password: SecretString,
+    params: Params,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&password, params))
+            .await?
+            .context("Failed to hash password")?;
+    // A password changed in the meantime is left alone.
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2 AND password_hash = $3",
+        password_hash.expose_secret(),
+        user_id,
+        current_password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash.")?;
+    Ok(())
+}
+
 #[tracing::instrument(name = "Get stored credentials", skip(credentials, pool))]
 pub async fn get_stored_credentials(
     credentials: &Credentials,
@@ -85,7 +172,7 @@ pub async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND deactivated_at IS NULL
         "#,
         credentials.username,
     );
@@ -98,40 +185,49 @@ pub async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+/// Stores a hash from `hash_password`, which is computed beforehand
+/// so that no connection is held while hashing.
+#[tracing::instrument(name = "Change password", skip(password_hash, executor))]
 pub async fn change_password_in_db(
     user_id: Uuid,
-    password: Password,
-    pool: &PgPool,
+    password_hash: &SecretString,
+    executor: impl PgExecutor<'_>,
 ) -> Result<(), anyhow::Error> {
-    // Compute password_hash
-    let password_hash = spawn_blocking_with_tracing(|| compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
-
     // Update users table, column: password_hash
     sqlx::query!(
         "UPDATE users SET password_hash=$1 WHERE user_id=$2",
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
     Ok(())
 }
 
-fn compute_password_hash(password: Password) -> Result<SecretString, anyhow::Error> {
+/// Hashes a new password on a blocking thread, as argon2 is CPU-bound by design.
+pub async fn hash_password(
+    password: Password,
+    hashing: &PasswordHashingSettings,
+) -> Result<SecretString, anyhow::Error> {
+    let params = hashing
+        .params()
+        .context("Invalid password hashing settings.")?;
+    spawn_blocking_with_tracing(move || compute_password_hash(password.inner_ref(), params))
+        .await?
+        .context("Failed to hash password")
+}
+
+fn compute_password_hash(
+    password: &SecretString,
+    params: Params,
+) -> Result<SecretString, anyhow::Error> {
     // 1. Generate random salt
     let salt = SaltString::generate(&mut rand::thread_rng());
     // 2. Argon2 algorithm.
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
 
     Ok(SecretString::new(Box::from(password_hash)))
 }
@@ -0,0 +1,276 @@
+use crate::authentication::Password;
+use anyhow::Context;
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+
+// A password made of fewer different characters, e.g. `aaaaaaaaaaaa` or `121212121212`,
+// is a repeated pattern rather than a password.
+const MIN_DISTINCT_CHARACTERS: usize = 5;
+// Shorter usernames would rule out too many passwords for little benefit.
+const MIN_USERNAME_LENGTH_TO_CHECK: usize = 3;
+
+/// Why a new password is refused, on top of the length rules of `Password::parse`.
+/// The messages are shown to the user as they are.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum WeakPassword {
+    #[error("The password must not contain your username.")]
+    ContainsUsername,
+    #[error("The password must not be made of a few repeated characters.")]
+    RepeatedCharacters,
+    #[error("This password has appeared in a data breach, please choose another one.")]
+    Breached,
+    #[error("The password could not be checked against known breaches, please try again.")]
+    Unchecked,
+}
+
+/// SHA-1 hashes of passwords known from data breaches, looked up in a file sorted by hash,
+/// as written by the Pwned Passwords downloader.
+///
+/// The file lists one hash per line, in hex, optionally followed by `:count`. Blank lines and
+/// lines starting with `#` are only allowed before the first hash. Only the file offsets are
+/// kept in memory: each lookup binary searches the file, reading a few dozen lines of it.
+#[derive(Default)]
+pub struct BreachedPasswords {
+    file: Option<SortedHashFile>,
+}
+
+struct SortedHashFile {
+    path: PathBuf,
+    // Offset of the first hash, past the comments at the top.
+    hashes_start: u64,
+    len: u64,
+}
+
+impl BreachedPasswords {
+    /// Checks that the file is sorted, reading it line by line.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to read the breached passwords file {:?}.", path))?;
+        let (hashes_start, len) = check_sorted(BufReader::new(file))
+            .with_context(|| format!("Invalid breached passwords file {:?}.", path))?;
+        Ok(Self {
+            file: Some(SortedHashFile {
+                path: path.to_path_buf(),
+                hashes_start,
+                len,
+            }),
+        })
+    }
+
+    pub fn contains(&self, password: &str) -> Result<bool, std::io::Error> {
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+        let mut reader = BufReader::new(File::open(&file.path)?);
+        find_hash(
+            &mut reader,
+            file.hashes_start,
+            file.len,
+            &sha1_hex(password),
+        )
+    }
+}
+
+/// Returns the offset of the first hash and the length of a valid file.
+fn check_sorted(mut reader: impl BufRead) -> Result<(u64, u64), anyhow::Error> {
+    let mut offset = 0;
+    let mut hashes_start = None;
+    let mut previous = String::new();
+    let mut line = String::new();
+    for i in 1.. {
+        line.clear();
+        let n_read = reader.read_line(&mut line)?;
+        if n_read == 0 {
+            break;
+        }
+        let trimmed = line.trim();
+        if hashes_start.is_none() {
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                offset += n_read as u64;
+                continue;
+            }
+            hashes_start = Some(offset);
+        }
+        let Some(hash) = parse_hash(trimmed) else {
+            anyhow::bail!("Line {} is not a SHA-1 hash in hex.", i);
+        };
+        if hash <= previous {
+            anyhow::bail!("Line {} is not sorted by hash.", i);
+        }
+        previous = hash;
+        offset += n_read as u64;
+    }
+    Ok((hashes_start.unwrap_or(offset), offset))
+}
+
+/// Binary searches the lines starting between `start` and `end` for `hash`, in upper case.
+fn find_hash<R: BufRead + Seek>(
+    reader: &mut R,
+    mut start: u64,
+    mut end: u64,
+    hash: &str,
+) -> Result<bool, std::io::Error> {
+    let mut line = Vec::new();
+    while start < end {
+        let middle = start + (end - start) / 2;
+        // The first line starting at or after `middle`.
+        let line_start = if middle == start {
+            start
+        } else {
+            reader.seek(SeekFrom::Start(middle - 1))?;
+            line.clear();
+            middle - 1 + reader.read_until(b'\n', &mut line)? as u64
+        };
+        if line_start >= end {
+            end = middle;
+            continue;
+        }
+        reader.seek(SeekFrom::Start(line_start))?;
+        line.clear();
+        let n_read = reader.read_until(b'\n', &mut line)? as u64;
+        let listed = std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| parse_hash(line.trim()))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "The breached passwords file has changed.",
+                )
+            })?;
+        match listed.as_str().cmp(hash) {
+            Ordering::Equal => return Ok(true),
+            Ordering::Less => start = line_start + n_read,
+            Ordering::Greater => end = line_start,
+        }
+    }
+    Ok(false)
+}
+
+fn parse_hash(line: &str) -> Option<String> {
+    let hash = line.split(':').next().unwrap_or_default();
+    (hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hash.to_ascii_uppercase())
+}
+
+fn sha1_hex(password: &str) -> String {
+    format!("{:X}", Sha1::digest(password.as_bytes()))
+}
+
+/// Checks a new password of `username` against obviously weak patterns and known breaches.
+pub fn check_password_strength(
+    password: &Password,
+    username: &str,
+    breached_passwords: &BreachedPasswords,
+) -> Result<(), WeakPassword> {
+    let password = password.expose_secret();
+    let username = username.trim().to_lowercase();
+    if username.graphemes(true).count() >= MIN_USERNAME_LENGTH_TO_CHECK
+        && password.to_lowercase().contains(&username)
+    {
+        return Err(WeakPassword::ContainsUsername);
+    }
+    let distinct_characters: HashSet<&str> = password.graphemes(true).collect();
+    if distinct_characters.len() < MIN_DISTINCT_CHARACTERS {
+        return Err(WeakPassword::RepeatedCharacters);
+    }
+    match breached_passwords.contains(password) {
+        Ok(true) => return Err(WeakPassword::Breached),
+        Ok(false) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to look up the breached passwords file");
+            return Err(WeakPassword::Unchecked);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeakPassword;
+    use super::{check_password_strength, check_sorted, find_hash, sha1_hex, BreachedPasswords};
+    use crate::authentication::Password;
+    use claims::assert_ok;
+    use std::io::Cursor;
+
+    const SAMPLE_FILE: &str = "configurations/breached_passwords.txt";
+
+    fn check(password: &str, username: &str) -> Result<(), WeakPassword> {
+        let password = Password::parse(password).unwrap();
+        let breached_passwords = BreachedPasswords::load(SAMPLE_FILE).unwrap();
+        check_password_strength(&password, username, &breached_passwords)
+    }
+
+    fn contains(contents: &str, password: &str) -> bool {
+        let (start, end) = check_sorted(contents.as_bytes()).unwrap();
+        find_hash(&mut Cursor::new(contents), start, end, &sha1_hex(password)).unwrap()
+    }
+
+    #[test]
+    fn every_listed_password_is_found_and_no_other() {
+        let passwords: Vec<String> = (0..50).map(|i| format!("password{}", i)).collect();
+        let mut hashes: Vec<String> = passwords.iter().map(|p| sha1_hex(p)).collect();
+        hashes.sort();
+        let contents = format!("# Sample\r\n\r\n{}:42\r\n", hashes.join(":42\r\n"));
+        for password in &passwords {
+            assert!(contains(&contents, password));
+        }
+        assert!(!contains(&contents, "password50"));
+        assert!(!contains("# Nothing yet\n", "password0"));
+    }
+
+    #[test]
+    fn lowercase_hashes_without_count_are_accepted() {
+        let contents = sha1_hex("password1234").to_ascii_lowercase();
+        assert!(contains(&contents, "password1234"));
+    }
+
+    #[test]
+    fn a_line_which_is_not_a_hash_is_rejected() {
+        assert!(check_sorted("password1234\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn an_unsorted_file_is_rejected() {
+        let contents = format!("{}\n{}\n", sha1_hex("b"), sha1_hex("a"));
+        assert!(check_sorted(contents.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn a_breached_password_is_refused() {
+        assert_eq!(check("password1234", "admin"), Err(WeakPassword::Breached));
+    }
+
+    #[test]
+    fn a_password_containing_the_username_is_refused() {
+        assert_eq!(
+            check("my-Admin-password", "admin"),
+            Err(WeakPassword::ContainsUsername)
+        );
+    }
+
+    #[test]
+    fn very_short_usernames_are_not_looked_for() {
+        assert_ok!(check("correct horse battery", "or"));
+    }
+
+    #[test]
+    fn a_few_repeated_characters_are_refused() {
+        for password in ["aaaaaaaaaaaa", "121212121212", "abcdabcdabcd"] {
+            assert_eq!(
+                check(password, "admin"),
+                Err(WeakPassword::RepeatedCharacters)
+            );
+        }
+    }
+
+    #[test]
+    fn a_random_password_is_accepted() {
+        assert_ok!(check(&uuid::Uuid::new_v4().to_string(), "admin"));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::authentication::token::{generate_token, hash_token};
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+/// A freshly issued reset token, together with the address it has to be sent to.
+/// Only the hash of `token` is stored in the database.
+pub struct ResetToken {
+    pub email: SubscriberEmail,
+    pub token: String,
+}
+
+/// Issues a single-use reset token for `username`, valid for `ttl`.
+///
+/// Returns `None` when no user with an email address goes by `username`.
+/// The token is generated and hashed either way, so that the response time
+/// does not tell an attacker whether the username exists.
+#[tracing::instrument(name = "Issue password reset token", skip(pool))]
+pub async fn issue_reset_token(
+    username: &str,
+    ttl: chrono::Duration,
+    pool: &PgPool,
+) -> Result<Option<ResetToken>, anyhow::Error> {
+    let token = generate_token(32);
+    let token_hash = hash_token(&token);
+
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email as "email!"
+        FROM users
+        WHERE username = $1 AND email IS NOT NULL AND deactivated_at IS NULL
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the user requesting a password reset.")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let email = SubscriberEmail::parse(row.email)
+        .map_err(|e| anyhow::anyhow!("The stored email address of the user is invalid: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        token_hash,
+        row.user_id,
+        Utc::now() + ttl,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the password reset token.")?;
+
+    Ok(Some(ResetToken { email, token }))
+}
+
+/// The username of the user `token` was issued to, as long as the token can still be used.
+/// The new password is checked against it before the token gets consumed.
+#[tracing::instrument(name = "Look up password reset token", skip(token, pool))]
+pub async fn get_reset_token_username(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let username = sqlx::query_scalar!(
+        r#"
+        SELECT users.username
+        FROM password_reset_tokens
+        JOIN users ON users.user_id = password_reset_tokens.user_id
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the password reset token.")?;
+    Ok(username)
+}
+
+/// Marks `token` as used and returns the id of the user it was issued to.
+/// Returns `None` if the token is unknown, expired or has already been used.
+/// Every other outstanding token of the user is invalidated as well.
+/// The token stays usable unless `transaction` is committed, with the new password.
+#[tracing::instrument(name = "Consume password reset token", skip(token, transaction))]
+pub async fn consume_reset_token(
+    token: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await
+    .context("Failed to mark the password reset token as used.")?;
+
+    if let Some(user_id) = user_id {
+        sqlx::query!(
+            r#"
+            UPDATE password_reset_tokens
+            SET used_at = now()
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            user_id,
+        )
+        .execute(transaction.deref_mut())
+        .await
+        .context("Failed to invalidate the other password reset tokens of the user.")?;
+    }
+    Ok(user_id)
+}
@@ -0,0 +1,217 @@
+use crate::configuration::LoginRateLimitSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, SetExpiry, SetOptions};
+use secrecy::{ExposeSecret, SecretString};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Counts failed logins per username and per IP address, and wrong second factor codes
+/// per user, in sliding windows kept in Redis.
+/// A username, an address or a user with too many failures is locked out, for longer each
+/// time it happens again, see `LoginRateLimitSettings::lockout_duration()`.
+#[derive(Clone)]
+pub struct LoginRateLimiter {
+    connection: ConnectionManager,
+    settings: LoginRateLimitSettings,
+}
+
+#[derive(Clone, Copy)]
+enum Subject<'a> {
+    Username(&'a str),
+    Ip(IpAddr),
+    // The second login step of a user, whatever the session it is attempted from.
+    TwoFactor(Uuid),
+}
+
+impl LoginRateLimiter {
+    pub async fn new(
+        redis_uri: &SecretString,
+        settings: LoginRateLimitSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret())
+            .context("Failed to parse the Redis URI.")?;
+        let connection = client
+            .get_connection_manager()
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self {
+            connection,
+            settings,
+        })
+    }
+
+    /// How much longer `username` or `ip` stays locked out, if either of them is.
+    #[tracing::instrument(name = "Check login lockout", skip(self))]
+    pub async fn lockout(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        self.lockout_of(subjects(username, ip)).await
+    }
+
+    /// Records a failed login. Returns the lockout it triggered, if any.
+    #[tracing::instrument(name = "Record failed login", skip(self))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        self.record_failure_of(subjects(username, ip)).await
+    }
+
+    /// Forgets the failures of `username` once it has logged in.
+    /// Those of the IP address are kept, they may come from attempts on other usernames.
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        self.forget_failures(Subject::Username(username)).await
+    }
+
+    /// How much longer the second login step of `user_id` stays locked out, if it is.
+    #[tracing::instrument(name = "Check two-factor lockout", skip(self))]
+    pub async fn two_factor_lockout(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        self.lockout_of([Subject::TwoFactor(user_id)]).await
+    }
+
+    /// Records a wrong second factor code. Returns the lockout it triggered, if any.
+    #[tracing::instrument(name = "Record failed two-factor code", skip(self))]
+    pub async fn record_two_factor_failure(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        self.record_failure_of([Subject::TwoFactor(user_id)]).await
+    }
+
+    /// Forgets the wrong codes of `user_id` once it has passed the second login step.
+    #[tracing::instrument(name = "Record successful two-factor code", skip(self))]
+    pub async fn record_two_factor_success(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
+        self.forget_failures(Subject::TwoFactor(user_id)).await
+    }
+
+    async fn lockout_of(
+        &self,
+        subjects: impl IntoIterator<Item = Subject<'_>>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut lockout = None;
+        for subject in subjects {
+            let remaining: i64 = connection
+                .pttl(self.key("locked", subject))
+                .await
+                .context("Failed to read a login lockout from Redis.")?;
+            // PTTL is negative when the key does not exist.
+            if remaining > 0 {
+                let remaining = Duration::from_millis(remaining as u64);
+                lockout = lockout.max(Some(remaining));
+            }
+        }
+        Ok(lockout)
+    }
+
+    async fn record_failure_of(
+        &self,
+        subjects: impl IntoIterator<Item = Subject<'_>>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut lockout = None;
+        for subject in subjects {
+            let n_failures = self.add_failure(subject).await?;
+            if n_failures >= self.max_failures(subject) {
+                let duration = self.lock_out(subject).await?;
+                lockout = lockout.max(Some(duration));
+            }
+        }
+        Ok(lockout)
+    }
+
+    async fn forget_failures(&self, subject: Subject<'_>) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .del(&[self.key("failures", subject), self.key("lockouts", subject)])
+            .await
+            .context("Failed to reset the failed logins in Redis.")?;
+        Ok(())
+    }
+
+    /// Adds a failure to the sorted set of `subject`, scored by time,
+    /// and returns how many failures are left in the window.
+    async fn add_failure(&self, subject: Subject<'_>) -> Result<u32, anyhow::Error> {
+        let key = self.key("failures", subject);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("The system clock is before the UNIX epoch.")?
+            .as_millis() as u64;
+        let window = self.settings.window().as_millis() as u64;
+        let mut connection = self.connection.clone();
+        let (n_failures,): (u32,) = redis::pipe()
+            .atomic()
+            .zrembyscore(&key, "-inf", now.saturating_sub(window))
+            .ignore()
+            .zadd(&key, format!("{}-{}", now, Uuid::new_v4()), now)
+            .ignore()
+            .zcard(&key)
+            .pexpire(&key, window as i64)
+            .ignore()
+            .query_async(&mut connection)
+            .await
+            .context("Failed to record a failed login in Redis.")?;
+        Ok(n_failures)
+    }
+
+    /// Locks `subject` out and starts counting its failures afresh.
+    /// The lockout count is forgotten once a window has passed without a new lockout.
+    async fn lock_out(&self, subject: Subject<'_>) -> Result<Duration, anyhow::Error> {
+        let lockouts_key = self.key("lockouts", subject);
+        let mut connection = self.connection.clone();
+        let n_lockouts: u32 = connection
+            .incr(&lockouts_key, 1)
+            .await
+            .context("Failed to count the login lockouts in Redis.")?;
+        let duration = self.settings.lockout_duration(n_lockouts);
+        let () = redis::pipe()
+            .atomic()
+            .pexpire(
+                &lockouts_key,
+                (duration + self.settings.window()).as_millis() as i64,
+            )
+            .ignore()
+            .set_options(
+                self.key("locked", subject),
+                1,
+                SetOptions::default().with_expiration(SetExpiry::PX(duration.as_millis() as u64)),
+            )
+            .ignore()
+            .del(self.key("failures", subject))
+            .ignore()
+            .query_async(&mut connection)
+            .await
+            .context("Failed to store a login lockout in Redis.")?;
+        tracing::warn!(n_lockouts, ?duration, "Login locked out");
+        Ok(duration)
+    }
+
+    fn max_failures(&self, subject: Subject<'_>) -> u32 {
+        match subject {
+            Subject::Username(_) => self.settings.max_failures_per_username,
+            Subject::Ip(_) => self.settings.max_failures_per_ip,
+            Subject::TwoFactor(_) => self.settings.max_two_factor_failures,
+        }
+    }
+
+    fn key(&self, kind: &str, subject: Subject<'_>) -> String {
+        let prefix = &self.settings.key_prefix;
+        match subject {
+            Subject::Username(username) => format!("{}:{}:username:{}", prefix, kind, username),
+            Subject::Ip(ip) => format!("{}:{}:ip:{}", prefix, kind, ip),
+            Subject::TwoFactor(user_id) => format!("{}:{}:two_factor:{}", prefix, kind, user_id),
+        }
+    }
+}
+
+fn subjects(username: &str, ip: Option<IpAddr>) -> impl Iterator<Item = Subject<'_>> {
+    std::iter::once(Subject::Username(username)).chain(ip.map(Subject::Ip))
+}
@@ -0,0 +1,116 @@
+use crate::session_state::SESSION_TTL;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Records a new logged-in session of `user_id`, returning the id to keep in its state.
+/// Rows of the user's sessions which have expired from the session store are pruned on the way.
+#[tracing::instrument(name = "Record user session", skip(user_agent, pool))]
+pub async fn record_session(
+    user_id: Uuid,
+    ip: Option<IpAddr>,
+    user_agent: Option<&str>,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1 AND last_seen_at < $2",
+        user_id,
+        session_expiry_threshold(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to prune the expired sessions.")?;
+
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, ip, user_agent)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        session_id,
+        user_id,
+        ip.map(|ip| ip.to_string()),
+        user_agent,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the session.")?;
+    Ok(session_id)
+}
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// The sessions of `user_id` which are still alive, most recently seen first.
+#[tracing::instrument(skip(pool))]
+pub async fn get_user_sessions(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    let sessions = sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip, user_agent
+        FROM user_sessions
+        WHERE user_id = $1 AND last_seen_at >= $2
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        session_expiry_threshold(),
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the sessions of the user.")?;
+    Ok(sessions)
+}
+
+/// Revokes one session of `user_id`. Returns `false` if there is no such session.
+#[tracing::instrument(skip(executor))]
+pub async fn revoke_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1 AND session_id = $2",
+        user_id,
+        session_id,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the session.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes every session of `user_id` but `keep`, returning how many were revoked.
+#[tracing::instrument(skip(executor))]
+pub async fn revoke_other_sessions(
+    user_id: Uuid,
+    keep: Option<Uuid>,
+    executor: impl PgExecutor<'_>,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the other sessions.")?;
+    Ok(result.rows_affected())
+}
+
+/// Sessions not seen since then have been forgotten by the session store.
+fn session_expiry_threshold() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::seconds(SESSION_TTL.whole_seconds())
+}
@@ -0,0 +1,18 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// A random alphanumeric token, for links sent by email.
+pub(crate) fn generate_token(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+/// Tokens are only stored as their SHA-256 hash, so a leaked table does not hand out working links.
+/// They are long and random enough that a fast unsalted hash is fine, unlike passwords.
+pub(crate) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
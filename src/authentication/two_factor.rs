@@ -0,0 +1,376 @@
+use crate::authentication::token::hash_token;
+use anyhow::Context;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+// RFC 6238 defaults, which every authenticator app understands.
+const ISSUER: &str = "zero2prod";
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Where a user stands with two-factor authentication, as shown on `/admin/security`.
+pub enum TwoFactorStatus {
+    Disabled,
+    /// Enrollment has started, but no code has been confirmed yet.
+    Pending(TotpEnrollment),
+    Enabled {
+        remaining_recovery_codes: i64,
+    },
+}
+
+/// What the user needs to add the account to an authenticator app.
+pub struct TotpEnrollment {
+    /// Base32 encoded, for apps where the secret is typed in by hand.
+    pub secret: String,
+    /// The `otpauth://` URI encoded in the QR code.
+    pub provisioning_uri: String,
+}
+
+impl TotpEnrollment {
+    fn new(secret: String, username: &str) -> Result<Self, anyhow::Error> {
+        let provisioning_uri = totp(&secret, username)?.get_url();
+        Ok(Self {
+            secret,
+            provisioning_uri,
+        })
+    }
+
+    pub fn qr_code_svg(&self) -> Result<String, anyhow::Error> {
+        let code = QrCode::new(self.provisioning_uri.as_bytes())
+            .context("Failed to encode the provisioning URI as a QR code.")?;
+        Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+    }
+}
+
+fn totp(secret: &str, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("The stored TOTP secret is invalid: {:?}", e))?;
+    // ':' separates the issuer from the account name in the provisioning URI label.
+    let account_name = username.replace(':', "");
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECONDS,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name,
+    )
+    .context("Failed to build the TOTP generator.")
+}
+
+/// Returns the time step `code` was generated for, accepting one step of clock drift either way.
+fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<u64> {
+    let current_step = now / STEP_SECONDS;
+    [
+        current_step.saturating_sub(1),
+        current_step,
+        current_step + 1,
+    ]
+    .into_iter()
+    .find(|step| totp.check(code, step * STEP_SECONDS))
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the UNIX epoch.")
+        .as_secs()
+}
+
+#[tracing::instrument(name = "Check if two-factor authentication is enabled", skip(pool))]
+pub async fn two_factor_enabled(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let enabled = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM user_totp_secrets WHERE user_id = $1 AND confirmed_at IS NOT NULL
+        ) as "enabled!"
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether two-factor authentication is enabled.")?;
+    Ok(enabled)
+}
+
+#[tracing::instrument(name = "Get two-factor authentication status", skip(pool))]
+pub async fn get_two_factor_status(
+    user_id: Uuid,
+    username: &str,
+    pool: &PgPool,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            secret,
+            confirmed_at IS NOT NULL as "confirmed!",
+            (
+                SELECT count(*) FROM user_recovery_codes
+                WHERE user_id = $1 AND used_at IS NULL
+            ) as "remaining_recovery_codes!"
+        FROM user_totp_secrets
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the two-factor authentication settings.")?;
+
+    let status = match row {
+        None => TwoFactorStatus::Disabled,
+        Some(row) if row.confirmed => TwoFactorStatus::Enabled {
+            remaining_recovery_codes: row.remaining_recovery_codes,
+        },
+        Some(row) => TwoFactorStatus::Pending(TotpEnrollment::new(row.secret, username)?),
+    };
+    Ok(status)
+}
+
+/// Generates a new TOTP secret for the user, replacing an unconfirmed one.
+/// Does nothing if two-factor authentication is already enabled.
+#[tracing::instrument(name = "Start TOTP enrollment", skip(pool))]
+pub async fn start_totp_enrollment(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut secret = [0u8; 20];
+    thread_rng().fill(&mut secret[..]);
+    let secret = Secret::Raw(secret.to_vec()).to_encoded().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_totp_secrets (user_id, secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, created_at = now(), last_used_step = NULL
+        WHERE user_totp_secrets.confirmed_at IS NULL
+        "#,
+        user_id,
+        secret,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the new TOTP secret.")?;
+    Ok(())
+}
+
+/// Enables two-factor authentication if `code` matches the pending secret.
+/// Returns the recovery codes, which are shown to the user once and only stored hashed.
+/// It only takes effect once `transaction` is committed.
+#[tracing::instrument(name = "Confirm TOTP enrollment", skip(code, transaction))]
+pub async fn confirm_totp_enrollment(
+    user_id: Uuid,
+    code: &str,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let secret = sqlx::query_scalar!(
+        r#"
+        SELECT secret FROM user_totp_secrets
+        WHERE user_id = $1 AND confirmed_at IS NULL
+        FOR UPDATE
+        "#,
+        user_id,
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await
+    .context("Failed to fetch the pending TOTP secret.")?;
+    let Some(secret) = secret else {
+        return Ok(None);
+    };
+    let Some(step) = matching_step(&totp(&secret, "")?, code, unix_time_now()) else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE user_totp_secrets
+        SET confirmed_at = now(), last_used_step = $2
+        WHERE user_id = $1
+        "#,
+        user_id,
+        step as i64,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to confirm the TOTP secret.")?;
+
+    let recovery_codes: Vec<String> = std::iter::repeat_with(generate_recovery_code)
+        .take(RECOVERY_CODE_COUNT)
+        .collect();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::TEXT[]) as code_hash
+        "#,
+        user_id,
+        &code_hashes,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to store the recovery codes.")?;
+
+    Ok(Some(recovery_codes))
+}
+
+/// Checks the second login step: either a TOTP code from the authenticator app,
+/// or one of the recovery codes. Both can only be used once.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let code = code.trim();
+    if code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+        verify_totp_code(user_id, code, pool).await
+    } else {
+        use_recovery_code(user_id, code, pool).await
+    }
+}
+
+async fn verify_totp_code(user_id: Uuid, code: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let secret = sqlx::query_scalar!(
+        r#"
+        SELECT secret FROM user_totp_secrets
+        WHERE user_id = $1 AND confirmed_at IS NOT NULL
+        "#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the TOTP secret.")?;
+    let Some(secret) = secret else {
+        return Ok(false);
+    };
+    let Some(step) = matching_step(&totp(&secret, "")?, code, unix_time_now()) else {
+        return Ok(false);
+    };
+    // Only a step later than the last accepted one goes through, so a code cannot be replayed.
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_totp_secrets
+        SET last_used_step = $2
+        WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+        "#,
+        user_id,
+        step as i64,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the used TOTP step.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+async fn use_recovery_code(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to mark the recovery code as used.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// It only takes effect once `transaction` is committed.
+#[tracing::instrument(name = "Disable two-factor authentication", skip(transaction))]
+pub async fn disable_two_factor(
+    user_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM user_recovery_codes WHERE user_id = $1",
+        user_id
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to delete the recovery codes.")?;
+    sqlx::query!("DELETE FROM user_totp_secrets WHERE user_id = $1", user_id)
+        .execute(transaction.deref_mut())
+        .await
+        .context("Failed to delete the TOTP secret.")?;
+    Ok(())
+}
+
+/// Recovery codes look like `abcde-12345`.
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let chars: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+/// Users may type a recovery code without the dash, in upper case, or with surrounding spaces.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_code, hash_recovery_code, matching_step, totp, STEP_SECONDS};
+    use totp_rs::Secret;
+
+    fn secret() -> String {
+        Secret::Raw(b"12345678901234567890".to_vec())
+            .to_encoded()
+            .to_string()
+    }
+
+    #[test]
+    fn a_code_from_the_current_or_a_neighbouring_step_is_accepted() {
+        let totp = totp(&secret(), "admin").unwrap();
+        let now = 1_000 * STEP_SECONDS;
+        for step in [999, 1_000, 1_001] {
+            let code = totp.generate(step * STEP_SECONDS);
+            assert_eq!(matching_step(&totp, &code, now), Some(step));
+        }
+    }
+
+    #[test]
+    fn a_code_from_two_steps_ago_is_rejected() {
+        let totp = totp(&secret(), "admin").unwrap();
+        let now = 1_000 * STEP_SECONDS;
+        let code = totp.generate(998 * STEP_SECONDS);
+        assert_eq!(matching_step(&totp, &code, now), None);
+    }
+
+    #[test]
+    fn the_provisioning_uri_names_the_issuer_and_the_account() {
+        let uri = totp(&secret(), "ad:min").unwrap().get_url();
+        assert!(uri.starts_with("otpauth://totp/zero2prod:admin?secret="));
+    }
+
+    #[test]
+    fn recovery_codes_are_matched_regardless_of_formatting() {
+        let code = generate_recovery_code();
+        let sloppy = format!(" {} ", code.replace('-', "").to_uppercase());
+        assert_eq!(hash_recovery_code(&code), hash_recovery_code(&sloppy));
+    }
+}
@@ -0,0 +1,34 @@
+use std::process::ExitCode;
+use zero2prod::admin_cli::{AdminCli, Command, USAGE};
+use zero2prod::configuration::get_configuration;
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Logs go to stderr, stdout is left to the output of the command.
+    let subscriber = get_subscriber("zero2prod-admin", "warn", std::io::stderr);
+    init_subscriber(subscriber);
+
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let outcome = match AdminCli::new(&configuration) {
+        Ok(cli) => cli.execute(command).await,
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
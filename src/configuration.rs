@@ -1,3 +1,4 @@
+use rand::Rng;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
@@ -5,7 +6,11 @@ use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use std::net::IpAddr;
+use std::num::NonZeroU16;
+use std::sync::Arc;
+
+use crate::email_client::{EmailTransport, PostmarkEmailClient, SmtpEmailClient, SmtpTls};
 
 // `Settings` struct is basically coming from `configurations/base.toml`, `local.toml`, or `production.toml`
 // In production environment, the values may be overridden by environment variables. See `get_configuration()`.
@@ -16,6 +21,11 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub delivery: DeliverySettings,
+    pub login_rate_limit: LoginRateLimitSettings,
+    pub idempotency: IdempotencySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub password_policy: PasswordPolicySettings,
     pub redis_uri: SecretString,
 }
 
@@ -26,12 +36,45 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: SecretString,
+    // How long a subscription confirmation link stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: u32,
+    // How long a password reset link stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub password_reset_token_ttl_minutes: u32,
+    // How long an invitation link for a new admin user stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub invitation_ttl_hours: u32,
+    // How long in-flight requests and worker tasks are given to finish on shutdown.
+    // The delivery worker is given at least the claim timeout to complete its batch.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+    // The reverse proxies in front of the application, whose `Forwarded` and
+    // `X-Forwarded-For` headers tell the address of the client, see `utils::client_ip`.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 impl ApplicationSettings {
     pub fn addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    pub fn subscription_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_ttl_hours.into())
+    }
+
+    pub fn password_reset_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.password_reset_token_ttl_minutes.into())
+    }
+
+    pub fn invitation_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.invitation_ttl_hours.into())
+    }
+
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -68,24 +111,71 @@ impl DatabaseSettings {
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    // Which service delivers our emails, `postmark` by default.
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: SecretString,
     pub timeout_milliseconds: u64,
+    // Required when `transport = "smtp"`.
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+    #[serde(default)]
+    pub tls: SmtpTls,
 }
 
 impl EmailClientSettings {
-    pub fn client(&self) -> EmailClient {
+    pub fn client(&self) -> Arc<dyn EmailTransport> {
         let sender_email = self
             .sender()
             .expect("Invalid sender email address in settings.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url.clone(),
-            sender_email,
-            self.authorization_token.clone(),
-            timeout,
-        )
+        match self.transport {
+            EmailTransportKind::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url.clone(),
+                sender_email,
+                self.authorization_token.clone(),
+                timeout,
+            )),
+            EmailTransportKind::Smtp => {
+                let smtp = self
+                    .smtp
+                    .as_ref()
+                    .expect("Missing [email_client.smtp] settings for the SMTP transport.");
+                let credentials = smtp.username.clone().map(|username| {
+                    let password = smtp.password.clone().unwrap_or_default();
+                    (username, password)
+                });
+                Arc::new(
+                    SmtpEmailClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        credentials,
+                        sender_email,
+                        timeout,
+                    )
+                    .expect("Failed to build the SMTP transport."),
+                )
+            }
+        }
     }
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
@@ -96,6 +186,152 @@ impl EmailClientSettings {
     }
 }
 
+// Settings of issue_delivery_worker.rs
+#[derive(Deserialize, Clone)]
+pub struct DeliverySettings {
+    // How many queued deliveries the worker claims and sends at once, at least one.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: NonZeroU16,
+    // How long claimed deliveries are left to the worker which claimed them, before another
+    // one claims them again, e.g. as the first one has crashed. Must outlast sending a batch,
+    // and is also how long shutdown waits for a batch in flight.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub claim_timeout_seconds: u64,
+    // Attempts per recipient, the first one included, before a delivery is given up.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: u16,
+    // Retry backoff, see `DeliverySettings::retry_delay()`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub multiplier: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+    // Fraction of the delay added or removed at random, e.g. 0.2 for ±20%.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub jitter: f64,
+    // Idle workers are woken up by NOTIFY, they only poll the queue this often as a safety net.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+}
+
+impl DeliverySettings {
+    pub fn claim_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.claim_timeout_seconds)
+    }
+
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    /// Exponential backoff before the next attempt, once `n_attempts` have failed:
+    /// `base_delay * multiplier^(n_attempts - 1)`, capped at `max_delay`, then jittered
+    /// so that recipients which failed together are not retried together.
+    pub fn retry_delay(&self, n_attempts: u16) -> std::time::Duration {
+        let exponent = i32::from(n_attempts.saturating_sub(1));
+        let delay = (self.base_delay_milliseconds as f64 * self.multiplier.powi(exponent))
+            .min(self.max_delay_milliseconds as f64);
+        let jitter = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        std::time::Duration::from_millis((delay * (1.0 + jitter)).max(0.0) as u64)
+    }
+}
+
+// Settings of authentication/rate_limit.rs
+#[derive(Deserialize, Clone)]
+pub struct LoginRateLimitSettings {
+    // Failed attempts within the window which lock out a username, or an IP address.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u32,
+    // Wrong second factor codes within the window which lock out the second login step of a user.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_two_factor_failures: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    // Lockout length, see `LoginRateLimitSettings::lockout_duration()`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_lockout_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_lockout_seconds: u64,
+    // Prepended to the Redis keys, so that several deployments can share one instance.
+    pub key_prefix: String,
+}
+
+impl LoginRateLimitSettings {
+    pub fn window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.window_seconds)
+    }
+
+    /// The `n_lockouts`-th lockout in a row lasts `base_lockout * 2^(n_lockouts - 1)`,
+    /// capped at `max_lockout`.
+    pub fn lockout_duration(&self, n_lockouts: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(n_lockouts.saturating_sub(1));
+        let seconds = self
+            .base_lockout_seconds
+            .saturating_mul(factor)
+            .min(self.max_lockout_seconds);
+        std::time::Duration::from_secs(seconds)
+    }
+}
+
+// Settings of idempotency/middleware.rs
+#[derive(Deserialize, Clone)]
+pub struct IdempotencySettings {
+    // How long a request keeps its idempotency key locked, after which a retry may take it over.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lock_timeout_seconds: u64,
+    // How long a request waits for another one with the same key to finish,
+    // before being told to retry later.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub wait_timeout_milliseconds: u64,
+}
+
+impl IdempotencySettings {
+    pub fn lock_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lock_timeout_seconds)
+    }
+
+    pub fn wait_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.wait_timeout_milliseconds)
+    }
+}
+
+// Settings of authentication/password.rs
+// Raising them is enough to strengthen the stored hashes: weaker ones are rehashed as their users log in.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PasswordHashingSettings {
+    // argon2id memory cost, in KiB.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_size_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+
+impl PasswordHashingSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(
+            self.memory_size_kib,
+            self.iterations,
+            self.parallelism,
+            None,
+        )
+    }
+}
+
+// Settings of authentication/password_policy.rs
+#[derive(Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    // SHA-1 hashes of breached passwords sorted by hash, see `BreachedPasswords`. No check without it.
+    pub breached_passwords_file: Option<String>,
+}
+
 ///# Read configurations from toml or environment variables.
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine current directory");
@@ -155,3 +391,85 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+    use std::time::Duration;
+
+    use crate::configuration::{DeliverySettings, LoginRateLimitSettings};
+
+    fn delivery_settings(jitter: f64) -> DeliverySettings {
+        DeliverySettings {
+            batch_size: NonZeroU16::new(500).unwrap(),
+            claim_timeout_seconds: 600,
+            max_attempts: 5,
+            base_delay_milliseconds: 1000,
+            multiplier: 2.0,
+            max_delay_milliseconds: 5000,
+            jitter,
+            poll_interval_milliseconds: 60000,
+        }
+    }
+
+    #[test]
+    fn a_batch_size_of_zero_is_rejected() {
+        let settings = |batch_size: u16| {
+            config::Config::builder()
+                .add_source(config::File::from_str(
+                    &format!(
+                        "batch_size = {}\n\
+                        claim_timeout_seconds = 600\n\
+                        max_attempts = 5\n\
+                        base_delay_milliseconds = 1000\n\
+                        multiplier = 2.0\n\
+                        max_delay_milliseconds = 5000\n\
+                        jitter = 0.2\n\
+                        poll_interval_milliseconds = 60000",
+                        batch_size
+                    ),
+                    config::FileFormat::Toml,
+                ))
+                .build()
+                .unwrap()
+                .try_deserialize::<DeliverySettings>()
+        };
+        assert!(settings(0).is_err());
+        assert_eq!(settings(1).unwrap().batch_size.get(), 1);
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially_up_to_the_max_delay() {
+        let settings = delivery_settings(0.0);
+        let delays: Vec<Duration> = (1..=5).map(|n| settings.retry_delay(n)).collect();
+        assert_eq!(
+            delays,
+            [1000, 2000, 4000, 5000, 5000].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn retry_delay_stays_within_the_jitter_range() {
+        let settings = delivery_settings(0.5);
+        for _ in 0..100 {
+            let delay = settings.retry_delay(2);
+            assert!(delay >= Duration::from_millis(1000));
+            assert!(delay <= Duration::from_millis(3000));
+        }
+    }
+
+    #[test]
+    fn lockout_duration_doubles_up_to_the_max_lockout() {
+        let settings = LoginRateLimitSettings {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 20,
+            max_two_factor_failures: 5,
+            window_seconds: 900,
+            base_lockout_seconds: 60,
+            max_lockout_seconds: 300,
+            key_prefix: "login_rate_limit".into(),
+        };
+        let durations: Vec<Duration> = (1..=5).map(|n| settings.lockout_duration(n)).collect();
+        assert_eq!(durations, [60, 120, 240, 300, 300].map(Duration::from_secs));
+    }
+}
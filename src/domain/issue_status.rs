@@ -0,0 +1,84 @@
+/// # Lifecycle of a newsletter issue
+/// `Draft` -> `Scheduled` -> `Sending` -> `Sent`.
+/// Drafts may also be published directly (`Draft` -> `Sending`),
+/// and drafts or scheduled issues may be `Cancelled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueStatus {
+    Draft,
+    Scheduled,
+    Sending,
+    Sent,
+    Cancelled,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Draft => "draft",
+            IssueStatus::Scheduled => "scheduled",
+            IssueStatus::Sending => "sending",
+            IssueStatus::Sent => "sent",
+            IssueStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Only issues that haven't been handed over to the delivery worker can be edited.
+    pub fn is_editable(&self) -> bool {
+        matches!(self, IssueStatus::Draft | IssueStatus::Scheduled)
+    }
+}
+
+impl std::fmt::Display for IssueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for IssueStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "draft" => Ok(IssueStatus::Draft),
+            "scheduled" => Ok(IssueStatus::Scheduled),
+            "sending" => Ok(IssueStatus::Sending),
+            "sent" => Ok(IssueStatus::Sent),
+            "cancelled" => Ok(IssueStatus::Cancelled),
+            other => Err(format!("{} is not a valid newsletter issue status.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::*;
+
+    #[test]
+    fn statuses_round_trip_through_their_string_representation() {
+        let statuses = [
+            IssueStatus::Draft,
+            IssueStatus::Scheduled,
+            IssueStatus::Sending,
+            IssueStatus::Sent,
+            IssueStatus::Cancelled,
+        ];
+        for status in statuses {
+            assert_ok_eq!(IssueStatus::try_from(status.as_str().to_string()), status);
+        }
+    }
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(IssueStatus::try_from("published".to_string()));
+    }
+
+    #[test]
+    fn only_drafts_and_scheduled_issues_are_editable() {
+        assert!(IssueStatus::Draft.is_editable());
+        assert!(IssueStatus::Scheduled.is_editable());
+        assert!(!IssueStatus::Sending.is_editable());
+        assert!(!IssueStatus::Sent.is_editable());
+        assert!(!IssueStatus::Cancelled.is_editable());
+    }
+}
@@ -1,7 +1,11 @@
+mod issue_status;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod user_role;
 
+pub use issue_status::*;
 pub use new_subscriber::*;
 pub use subscriber_email::*;
 pub use subscriber_name::*;
+pub use user_role::*;
@@ -0,0 +1,80 @@
+/// # What an admin user is allowed to do
+/// - `Owner`: everything, including managing the other users under `/admin/users`.
+/// - `Editor`: write and publish newsletter issues, and requeue failed deliveries.
+/// - `Viewer`: read-only access to the issue history and the failed deliveries.
+///
+/// Every role may change its own password and two-factor authentication settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl UserRole {
+    pub const ALL: [UserRole; 3] = [UserRole::Owner, UserRole::Editor, UserRole::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Owner => "owner",
+            UserRole::Editor => "editor",
+            UserRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_manage_users(&self) -> bool {
+        matches!(self, UserRole::Owner)
+    }
+
+    pub fn can_publish(&self) -> bool {
+        matches!(self, UserRole::Owner | UserRole::Editor)
+    }
+}
+
+impl std::fmt::Display for UserRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for UserRole {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "owner" => Ok(UserRole::Owner),
+            "editor" => Ok(UserRole::Editor),
+            "viewer" => Ok(UserRole::Viewer),
+            other => Err(format!("{} is not a valid user role.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use super::*;
+
+    #[test]
+    fn roles_round_trip_through_their_string_representation() {
+        for role in UserRole::ALL {
+            assert_ok_eq!(UserRole::try_from(role.as_str().to_string()), role);
+        }
+    }
+
+    #[test]
+    fn unknown_role_is_rejected() {
+        assert_err!(UserRole::try_from("admin".to_string()));
+    }
+
+    #[test]
+    fn only_owners_manage_users_and_viewers_cannot_publish() {
+        assert!(UserRole::Owner.can_manage_users());
+        assert!(!UserRole::Editor.can_manage_users());
+        assert!(!UserRole::Viewer.can_manage_users());
+        assert!(UserRole::Owner.can_publish());
+        assert!(UserRole::Editor.can_publish());
+        assert!(!UserRole::Viewer.can_publish());
+    }
+}
@@ -0,0 +1,109 @@
+mod postmark;
+mod smtp;
+
+pub use postmark::PostmarkEmailClient;
+pub use smtp::{SmtpEmailClient, SmtpTls};
+
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::domain::SubscriberEmail;
+
+/// # Email transport
+/// Abstraction over the service used to deliver emails.
+/// The implementation is selected by `transport` in the `[email_client]` settings,
+/// see `EmailClientSettings::client()`.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync + std::fmt::Debug {
+    /// Same as `send_email`, but attaches extra MIME headers to the message,
+    /// e.g. `List-Unsubscribe` for newsletter issues.
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), SendError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    /// Sends several emails at once, returning one result per email in the same order.
+    /// By default the emails are sent one by one; services with a batch API override this.
+    async fn send_batch(&self, emails: &[OutgoingEmail<'_>]) -> Vec<Result<(), SendError>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            let result = self
+                .send_email_with_headers(
+                    &email.recipient,
+                    email.subject,
+                    email.html_content,
+                    email.text_content,
+                    &email.headers,
+                )
+                .await;
+            results.push(result);
+        }
+        results
+    }
+}
+
+/// One message of `EmailTransport::send_batch`.
+#[derive(Debug)]
+pub struct OutgoingEmail<'a> {
+    pub recipient: SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: Vec<EmailHeader>,
+}
+
+/// Why a message has not been sent.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    /// The message may go through later, e.g. after a timeout or a 5xx response.
+    /// `retry_after` is set when the service asks us to wait, e.g. `429` with `Retry-After`.
+    #[error("{message}")]
+    Retryable {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The message will never go through, e.g. the recipient is invalid or inactive.
+    #[error("{0}")]
+    Permanent(String),
+}
+
+impl SendError {
+    pub fn retryable(message: impl Into<String>) -> Self {
+        SendError::Retryable {
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+}
+
+/// A custom header, serialized as `{"Name": "...", "Value": "..."}` in the Postmark `Headers` array.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
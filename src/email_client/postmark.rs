@@ -0,0 +1,574 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailHeader, EmailTransport, OutgoingEmail, SendError};
+
+/// Postmark accepts at most 500 messages per `/email/batch` request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// Per-message `ErrorCode`s which retrying will not fix.
+/// 300: Invalid email request, 406: Inactive recipient.
+/// See <https://postmarkapp.com/developer/api/overview#error-codes>
+const PERMANENT_ERROR_CODES: [i64; 2] = [300, 406];
+
+/// Sends emails through the [Postmark](https://postmarkapp.com) HTTP API.
+#[derive(Debug)]
+pub struct PostmarkEmailClient {
+    http_client: reqwest::Client,
+    // ? This depends on an external crate. Do we need abstraction?
+    sender: SubscriberEmail,
+    // ? SubscriberEmail is a domain type, so why is it used here?
+    base_url: String,
+    // external service url to send email
+    authorization_token: SecretString,
+}
+
+impl PostmarkEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: SecretString,
+        time_out: Duration,
+    ) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(time_out)
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            sender,
+            base_url,
+            authorization_token,
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("{}/email", self.base_url)
+    }
+
+    fn batch_url(&self) -> String {
+        format!("{}/email/batch", self.base_url)
+    }
+
+    /// POSTs `body` to `url`, classifying failures as `SendError`s.
+    /// Only a `422` for an invalid message or an inactive recipient is permanent.
+    /// Anything else, e.g. a `401` for a wrong server token or a `422` for an unconfirmed
+    /// sender signature, is a problem of ours or of Postmark rather than of the recipient,
+    /// and is retried until it has been fixed.
+    async fn post<T: Serialize + ?Sized>(
+        &self,
+        url: String,
+        body: &T,
+    ) -> Result<reqwest::Response, SendError> {
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| SendError::retryable(format!("{:#}", anyhow::Error::from(e))))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+        let message = format!("Postmark responded with {}: {}", status, body);
+        let error_code = serde_json::from_str::<ErrorResponse>(&body)
+            .ok()
+            .map(|e| e.error_code);
+        let is_permanent = status == StatusCode::UNPROCESSABLE_ENTITY
+            && error_code.is_some_and(|code| PERMANENT_ERROR_CODES.contains(&code));
+        if is_permanent {
+            Err(SendError::Permanent(message))
+        } else {
+            Err(SendError::Retryable {
+                message,
+                retry_after,
+            })
+        }
+    }
+
+    async fn send_batch_request(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<BatchResponseItem>, SendError> {
+        let request_body: Vec<SendEmailRequest> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+                headers: &email.headers,
+            })
+            .collect();
+
+        let response_body = self
+            .post(self.batch_url(), &request_body)
+            .await?
+            .json::<Vec<BatchResponseItem>>()
+            .await
+            .map_err(|e| SendError::retryable(format!("{:#}", anyhow::Error::from(e))))?;
+        if response_body.len() != emails.len() {
+            return Err(SendError::retryable(format!(
+                "Postmark returned {} results for a batch of {} messages.",
+                response_body.len(),
+                emails.len()
+            )));
+        }
+        Ok(response_body)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+
+        self.post(self.url(), &request_body).await?;
+        Ok(())
+    }
+
+    async fn send_batch(&self, emails: &[OutgoingEmail<'_>]) -> Vec<Result<(), SendError>> {
+        let mut results = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_batch_request(chunk).await {
+                Ok(items) => results.extend(items.into_iter().map(BatchResponseItem::into_result)),
+                // Postmark accepts or rejects a batch request as a whole.
+                Err(e) => results.extend(chunk.iter().map(|_| Err(e.clone()))),
+            }
+        }
+        results
+    }
+}
+
+/// # Postmark API
+///
+/// ```shell
+/// curl "https://api.postmarkapp.com/email" \
+///   -X POST \
+///   -H "Accept: application/json" \
+///   -H "Content-Type: application/json" \
+///   -H "X-Postmark-Server-Token: xxx-yyy-zzz" \
+///   -d '{
+///         "From": "info@example.com",
+///         "To": "dummy_destination@gmail.com",
+///         "Subject": "Hello from Postmark",
+///         "HtmlBody": "<strong>Hello</strong> dear Postmark user.",
+///         "MessageStream": "broadcast"
+///       }'
+/// ```
+///
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+/// The body of the error responses of Postmark.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    error_code: i64,
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.to_utc() - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// One element of the `/email/batch` response, in the order of the request.
+/// `ErrorCode` is `0` when the message has been accepted.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResponseItem {
+    error_code: i64,
+    message: String,
+}
+
+impl BatchResponseItem {
+    fn into_result(self) -> Result<(), SendError> {
+        match self.error_code {
+            0 => Ok(()),
+            code => {
+                let message = format!("Postmark error {}: {}", code, self.message);
+                if PERMANENT_ERROR_CODES.contains(&code) {
+                    Err(SendError::Permanent(message))
+                } else {
+                    Err(SendError::retryable(message))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::SecretString;
+    use wiremock::http::Method;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::postmark::parse_retry_after;
+    use crate::email_client::{
+        EmailHeader, EmailTransport, OutgoingEmail, PostmarkEmailClient, SendError,
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                let json_keys = ["From", "To", "Subject", "HtmlBody", "TextBody"];
+                json_keys.into_iter().all(|k| body.get(k).is_some())
+            } else {
+                false
+            }
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn authorization_token() -> SecretString {
+        SecretString::new(Box::from(Faker.fake::<String>()))
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn timeout_ms(timeout_milliseconds: u64) -> Duration {
+        Duration::from_millis(timeout_milliseconds)
+    }
+
+    fn email_client(base_url: String, timeout_milliseconds: u64) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
+            base_url,
+            email(),
+            authorization_token(),
+            timeout_ms(timeout_milliseconds),
+        )
+    }
+
+    async fn send_email_for_testing(email_client: PostmarkEmailClient) -> Result<(), SendError> {
+        email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_request() {
+        // Mockserver
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method(Method::POST))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        assert_ok!(send_email_for_testing(email_client).await)
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        assert_ok!(send_email_for_testing(email_client).await)
+    }
+
+    #[tokio::test]
+    async fn send_email_gets_error_when_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send_email_for_testing(email_client).await;
+        assert!(matches!(outcome, Err(SendError::Retryable { .. })));
+    }
+
+    #[tokio::test]
+    async fn send_email_gets_a_permanent_error_when_server_returns_422_for_an_inactive_recipient() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send_email_for_testing(email_client).await;
+        assert!(matches!(outcome, Err(SendError::Permanent(_))));
+    }
+
+    #[tokio::test]
+    async fn authentication_and_account_errors_are_retried() {
+        for response in [
+            ResponseTemplate::new(401),
+            ResponseTemplate::new(403),
+            ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 401,
+                "Message": "Sender signature not confirmed."
+            })),
+        ] {
+            let mock_server = MockServer::start().await;
+            let email_client = email_client(mock_server.uri().to_string(), 200);
+            Mock::given(any())
+                .respond_with(response)
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            let outcome = send_email_for_testing(email_client).await;
+            assert!(matches!(outcome, Err(SendError::Retryable { .. })));
+        }
+    }
+
+    #[test]
+    fn retry_after_is_read_as_seconds_or_as_an_http_date() {
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        let in_a_minute = (chrono::Utc::now() + chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let delay = parse_retry_after(&in_a_minute).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn send_email_honours_retry_after_when_server_returns_429() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send_email_for_testing(email_client).await;
+        assert!(matches!(
+            outcome,
+            Err(SendError::Retryable {
+                retry_after: Some(retry_after),
+                ..
+            }) if retry_after == Duration::from_secs(30)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send_email_for_testing(email_client).await;
+        assert!(matches!(outcome, Err(SendError::Retryable { .. })));
+    }
+
+    struct HeadersBodyMatcher;
+
+    impl wiremock::Match for HeadersBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body["Headers"][0]["Name"] == "List-Unsubscribe"
+                    && body["Headers"][0]["Value"] == "<https://example.com/unsubscribe>"
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_the_headers_in_the_request_body() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+
+        Mock::given(path("/email"))
+            .and(SendEmailBodyMatcher)
+            .and(HeadersBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe",
+            "<https://example.com/unsubscribe>",
+        )];
+        let outcome = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+        assert_ok!(outcome);
+    }
+
+    fn outgoing_email<'a>(subject: &'a str, content: &'a str) -> OutgoingEmail<'a> {
+        OutgoingEmail {
+            recipient: email(),
+            subject,
+            html_content: content,
+            text_content: content,
+            headers: vec![],
+        }
+    }
+
+    struct BatchBodyMatcher(usize);
+
+    impl wiremock::Match for BatchBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<Vec<serde_json::Value>, _> = serde_json::from_slice(&request.body);
+            match result {
+                Ok(messages) => {
+                    messages.len() == self.0
+                        && messages
+                            .iter()
+                            .all(|m| m.get("To").is_some() && m.get("Subject").is_some())
+                }
+                Err(_) => false,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_batch_maps_each_error_code_to_a_result() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = (0..3).map(|_| outgoing_email(&subject, &content)).collect();
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method(Method::POST))
+            .and(BatchBodyMatcher(3))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "Inactive recipient"},
+                {"ErrorCode": 429, "Message": "Rate limit exceeded"},
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = email_client.send_batch(&emails).await;
+
+        // Assert
+        assert_eq!(results.len(), 3);
+        assert_ok!(&results[0]);
+        assert!(matches!(results[1], Err(SendError::Permanent(_))));
+        assert!(matches!(results[2], Err(SendError::Retryable { .. })));
+    }
+
+    #[tokio::test]
+    async fn send_batch_retries_every_message_when_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri().to_string(), 200);
+        let (subject, content) = (subject(), content());
+        let emails: Vec<_> = (0..2).map(|_| outgoing_email(&subject, &content)).collect();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = email_client.send_batch(&emails).await;
+
+        // Assert
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(SendError::Retryable { .. }))));
+    }
+}
@@ -0,0 +1,228 @@
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::response::Category;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailHeader, EmailTransport, SendError};
+
+/// How the connection to the SMTP server is secured.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text, e.g. for a local sink like MailHog.
+    None,
+    /// Upgrade the connection with `STARTTLS`, usually on port 587.
+    #[default]
+    Starttls,
+    /// Implicit TLS from the first byte, usually on port 465.
+    Tls,
+}
+
+/// Sends emails to our own MTA over SMTP.
+/// `AUTH PLAIN` or `AUTH LOGIN` is used when credentials are configured,
+/// whichever the server advertises first.
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl std::fmt::Debug for SmtpEmailClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpEmailClient")
+            .field("sender", &self.sender)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, SecretString)>,
+        sender: SubscriberEmail,
+        time_out: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let tls = match tls {
+            SmtpTls::None => Tls::None,
+            SmtpTls::Starttls => Tls::Required(TlsParameters::new(host.into())?),
+            SmtpTls::Tls => Tls::Wrapper(TlsParameters::new(host.into())?),
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(port)
+            .tls(tls)
+            .timeout(Some(time_out));
+        if let Some((username, password)) = credentials {
+            builder = builder
+                .credentials(Credentials::new(
+                    username,
+                    password.expose_secret().to_string(),
+                ))
+                .authentication(vec![Mechanism::Plain, Mechanism::Login]);
+        }
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+
+    fn build_message(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Message, anyhow::Error> {
+        let mut builder = Message::builder()
+            .from(self.sender.as_ref().parse::<Mailbox>()?)
+            .to(recipient.as_ref().parse::<Mailbox>()?)
+            .subject(subject);
+        for header in headers {
+            let name = HeaderName::new_from_ascii(header.name.clone())?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+        }
+        let message = builder.multipart(MultiPart::alternative_plain_html(
+            text_content.to_string(),
+            html_content.to_string(),
+        ))?;
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpEmailClient {
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), SendError> {
+        // A message we fail to build will not build any better on the next attempt.
+        let message = self
+            .build_message(recipient, subject, html_content, text_content, headers)
+            .map_err(|e| SendError::Permanent(format!("{:#}", e)))?;
+
+        self.transport.send(message).await.map_err(|e| {
+            // 5xx replies, e.g. `550 No such user`, are permanent. 4xx replies, timeouts
+            // and connection failures are worth another try, and so are the 53x replies
+            // to a failed authentication, which are no fault of the recipient.
+            let is_authentication_error = e
+                .status()
+                .is_some_and(|code| code.category == Category::Unspecified3);
+            let is_permanent = e.is_permanent() && !is_authentication_error;
+            let message = format!("{:#}", anyhow::Error::from(e));
+            if is_permanent {
+                SendError::Permanent(message)
+            } else {
+                SendError::retryable(message)
+            }
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use claims::assert_ok;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailTransport, SmtpEmailClient, SmtpTls};
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// A minimal plain-text SMTP sink, accepting every command and recording the DATA section.
+    async fn start_smtp_sink() -> (u16, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(String::new()));
+        let data = received.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data.lock().unwrap().push_str(&line);
+                        data.lock().unwrap().push('\n');
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.get(..4).map(|c| c.to_ascii_uppercase()) {
+                    Some(c) if c == "EHLO" => b"250 localhost\r\n",
+                    Some(c) if c == "DATA" => {
+                        in_data = true;
+                        b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                    }
+                    Some(c) if c == "QUIT" => {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+        (port, received)
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_with_its_headers_over_smtp() {
+        // Arrange
+        let (port, received) = start_smtp_sink().await;
+        let email_client = SmtpEmailClient::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            None,
+            email(),
+            Duration::from_secs(2),
+        )
+        .unwrap();
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe",
+            "<https://example.com/unsubscribe>",
+        )];
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Newsletter title",
+                "<p>Newsletter body</p>",
+                "Newsletter body",
+                &headers,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let data = received.lock().unwrap().clone();
+        assert!(data.contains("Subject: Newsletter title"));
+        assert!(data.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        assert!(data.contains("Content-Type: multipart/alternative"));
+        assert!(data.contains("<p>Newsletter body</p>"));
+    }
+}
@@ -0,0 +1,373 @@
+use crate::authentication::{ApiPrincipal, UserId};
+use crate::configuration::IdempotencySettings;
+use crate::idempotency::{
+    release_lock, save_response, try_processing, IdempotencyKey, IdempotencyLock, NextAction,
+};
+use crate::routes::error_chain_fmt;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::cell::RefCell;
+use std::fmt::Formatter;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+// The hidden field of the forms of the dashboard, for browsers cannot set headers.
+const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("{0}")]
+    InvalidKey(String),
+    #[error("The idempotency key has already been used for a different request.")]
+    KeyReused,
+    // Carries how long to wait before retrying.
+    #[error("A request with the same idempotency key is still being processed.")]
+    InProgress(Duration),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IdempotencyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for IdempotencyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IdempotencyError::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            IdempotencyError::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            IdempotencyError::InProgress(_) => StatusCode::CONFLICT,
+            IdempotencyError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let IdempotencyError::InProgress(retry_after) = self {
+            response.insert_header((RETRY_AFTER, retry_after_seconds(*retry_after)));
+        }
+        match self {
+            IdempotencyError::Unexpected(_) => response.finish(),
+            e => response.body(e.to_string()),
+        }
+    }
+}
+
+/// The value of the `Retry-After` header, in whole seconds and at least one.
+pub fn retry_after_seconds(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// The flash messages sent while handling a request, see `send_flash_message`.
+#[derive(Default)]
+struct SentFlashMessages(Vec<FlashMessage>);
+
+/// Sends `message`, and saves it with the response of an idempotent request,
+/// so that it is sent again when a retried request is answered with the saved response.
+pub fn send_flash_message(request: &HttpRequest, message: FlashMessage) {
+    let mut extensions = request.extensions_mut();
+    match extensions.get_mut::<SentFlashMessages>() {
+        Some(sent) => sent.0.push(message.clone()),
+        None => {
+            extensions.insert(SentFlashMessages(vec![message.clone()]));
+        }
+    }
+    message.send();
+}
+
+// Shared by the middleware and the `IdempotentTransaction` of the handler.
+type TransactionSlot = Rc<RefCell<Option<Transaction<'static, Postgres>>>>;
+
+/// The transaction an idempotent request is handled in. The handler makes its changes in it
+/// without committing them: the `idempotency` middleware commits them together with the saved
+/// response, or rolls them back if the request fails.
+pub struct IdempotentTransaction {
+    transaction: Option<Transaction<'static, Postgres>>,
+    slot: TransactionSlot,
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = IdempotencyError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    // Begun only once asked for, so that the requests turned away before reaching the handler
+    // do not hold a connection of the pool.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let slot = req.extensions().get::<TransactionSlot>().cloned();
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let slot = slot.ok_or_else(|| {
+                IdempotencyError::InvalidKey(format!(
+                    "The {} header is required.",
+                    IDEMPOTENCY_KEY_HEADER
+                ))
+            })?;
+            let pool = pool.context("The database pool is not registered.")?;
+            let transaction = begin_transaction(&pool).await?;
+            Ok(IdempotentTransaction {
+                transaction: Some(transaction),
+                slot,
+            })
+        })
+    }
+}
+
+impl Deref for IdempotentTransaction {
+    type Target = Transaction<'static, Postgres>;
+
+    fn deref(&self) -> &Self::Target {
+        self.transaction
+            .as_ref()
+            .expect("The transaction is only taken on drop")
+    }
+}
+
+impl DerefMut for IdempotentTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.transaction
+            .as_mut()
+            .expect("The transaction is only taken on drop")
+    }
+}
+
+impl Drop for IdempotentTransaction {
+    // Hands the transaction back to the middleware once the handler is done with it.
+    fn drop(&mut self) {
+        *self.slot.borrow_mut() = self.transaction.take();
+    }
+}
+
+/// # Idempotent requests
+/// Unsafe requests of a logged-in user or of an API token carrying an idempotency key,
+/// in the `Idempotency-Key` header or in the `idempotency_key` field of a form, are handled
+/// at most once per key: a retried request gets the saved response back, while reusing
+/// the key for a request with another method, path or body is refused with a 422.
+/// Requests without a key are let through untouched, and so are the ones failing with
+/// a server error, whose key is released to be retried.
+///
+/// The key is locked while the request is processed. A request with the same key arriving
+/// in the meantime waits for the response for a while, and is then answered with a 409 and
+/// a `Retry-After` header. The lock expires after the lock timeout of `IdempotencySettings`,
+/// so that a key left locked, e.g. by a server restart, can be retried.
+///
+/// It wraps the routes whose handlers make their changes in the `IdempotentTransaction` of the
+/// request, so that they are committed together with the saved response: a request failing in
+/// between, or whose key has been taken over by a retry in the meantime, leaves no changes
+/// behind. It has to run after the middleware authenticating the request, e.g. on a route of
+/// a scope wrapped by it, and `E` renders its errors, e.g. as the JSON errors of the API.
+pub async fn idempotency<E>(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error>
+where
+    E: From<IdempotencyError> + ResponseError + 'static,
+{
+    if req.method().is_safe() {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let Some(user_id) = request_owner(&req) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let body = req.extract::<web::Bytes>().await?;
+    let idempotency_key = idempotency_key(&req, &body).map_err(E::from)?;
+    let request_fingerprint = request_fingerprint(&req, &body);
+    // The body is handed back to the handler untouched.
+    req.set_payload(Payload::from(body));
+    let Some(idempotency_key) = idempotency_key else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is not registered.")
+        .map_err(|e| E::from(e.into()))?
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .context("The idempotency settings are not registered.")
+        .map_err(|e| E::from(e.into()))?
+        .clone();
+    let lock = match try_processing(
+        &pool,
+        &idempotency_key,
+        user_id,
+        &request_fingerprint,
+        &settings,
+    )
+    .await
+    .map_err(|e| E::from(e.into()))?
+    {
+        NextAction::StartProcessing(lock) => lock,
+        NextAction::ReturnSavedResponse(saved) => {
+            for message in saved.flash_messages {
+                message.send();
+            }
+            return Ok(req.into_response(saved.response));
+        }
+        NextAction::RejectReusedKey => return Err(E::from(IdempotencyError::KeyReused).into()),
+        NextAction::RetryLater(retry_after) => {
+            return Err(E::from(IdempotencyError::InProgress(retry_after)).into())
+        }
+    };
+
+    // Filled in by the `IdempotentTransaction` of the handler, if it asks for one.
+    let slot: TransactionSlot = Rc::new(RefCell::new(None));
+    req.extensions_mut().insert(slot.clone());
+
+    let result = next.call(req).await;
+    let transaction = slot.borrow_mut().take();
+    let response = match result {
+        Ok(response) if !response.status().is_server_error() => response.map_into_boxed_body(),
+        result => {
+            // Rolled back before the lock is released, for a retry not to see its changes.
+            drop(transaction);
+            release_lock(&pool, &idempotency_key, user_id, lock)
+                .await
+                .map_err(|e| E::from(e.into()))?;
+            return Ok(result?.map_into_boxed_body());
+        }
+    };
+    response
+        .request()
+        .extensions_mut()
+        .remove::<TransactionSlot>();
+    let flash_messages = response
+        .request()
+        .extensions_mut()
+        .remove::<SentFlashMessages>()
+        .unwrap_or_default();
+    let (request, response) = response.into_parts();
+    match commit_with_response(
+        &pool,
+        transaction,
+        &idempotency_key,
+        user_id,
+        &lock,
+        response,
+        &flash_messages.0,
+    )
+    .await
+    {
+        Ok(Some(response)) => Ok(ServiceResponse::new(request, response)),
+        // A retry took the key over after the lock expired, and makes the changes instead.
+        Ok(None) => Err(E::from(IdempotencyError::InProgress(Duration::ZERO)).into()),
+        Err(e) => {
+            release_lock(&pool, &idempotency_key, user_id, lock)
+                .await
+                .map_err(|e| E::from(e.into()))?;
+            Err(E::from(e.into()).into())
+        }
+    }
+}
+
+/// Saves `response` in the transaction of the handler, and commits them together.
+/// `None` if the key has been taken over in the meantime, and nothing is committed.
+async fn commit_with_response(
+    pool: &PgPool,
+    transaction: Option<Transaction<'static, Postgres>>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    lock: &IdempotencyLock,
+    response: HttpResponse,
+    flash_messages: &[FlashMessage],
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let mut transaction = match transaction {
+        Some(transaction) => transaction,
+        // The request was turned away before the handler ran, e.g. for an invalid form.
+        None => begin_transaction(pool).await?,
+    };
+    let Some(response) = save_response(
+        &mut transaction,
+        idempotency_key,
+        user_id,
+        lock,
+        response,
+        flash_messages,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the idempotent request.")?;
+    Ok(Some(response))
+}
+
+async fn begin_transaction(pool: &PgPool) -> Result<Transaction<'static, Postgres>, anyhow::Error> {
+    pool.begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+}
+
+/// The user the key belongs to, whether logged in or using an API token.
+fn request_owner(req: &ServiceRequest) -> Option<Uuid> {
+    let extensions = req.extensions();
+    extensions
+        .get::<UserId>()
+        .map(|user_id| **user_id)
+        .or_else(|| extensions.get::<ApiPrincipal>().map(|p| p.user_id))
+}
+
+fn idempotency_key(
+    req: &ServiceRequest,
+    body: &[u8],
+) -> Result<Option<IdempotencyKey>, IdempotencyError> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(header) => Some(
+            header
+                .to_str()
+                .map_err(|_| {
+                    IdempotencyError::InvalidKey(format!(
+                        "The {} header must be visible ASCII.",
+                        IDEMPOTENCY_KEY_HEADER
+                    ))
+                })?
+                .to_string(),
+        ),
+        None if req.content_type() == "application/x-www-form-urlencoded" => {
+            serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(name, _)| name == IDEMPOTENCY_KEY_FIELD)
+                .map(|(_, value)| value)
+        }
+        None => None,
+    };
+    key.map(|key| {
+        IdempotencyKey::try_from(key).map_err(|e| IdempotencyError::InvalidKey(e.to_string()))
+    })
+    .transpose()
+}
+
+/// Tells apart the requests sent with the same key.
+fn request_fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or_else(|| req.path());
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b"\n");
+    hasher.update(path);
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
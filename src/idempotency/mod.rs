@@ -1,5 +1,12 @@
 mod key;
+mod middleware;
 mod persistence;
 
 pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+pub use middleware::{
+    idempotency, retry_after_seconds, send_flash_message, IdempotencyError, IdempotentTransaction,
+    IDEMPOTENCY_KEY_HEADER,
+};
+pub use persistence::{
+    release_lock, save_response, try_processing, IdempotencyLock, NextAction, SavedResponse,
+};
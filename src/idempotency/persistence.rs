@@ -1,12 +1,21 @@
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use actix_web_flash_messages::FlashMessage;
+use chrono::{DateTime, Utc};
 use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use std::time::Duration;
+use tokio::time::Instant;
 use uuid::Uuid;
 
+use crate::configuration::IdempotencySettings;
 use crate::idempotency::IdempotencyKey;
 
+// How often a request waiting for another one with the same key checks whether it is done.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair", no_pg_array)]
 struct HeaderPairRecord {
@@ -14,41 +23,87 @@ struct HeaderPairRecord {
     value: Vec<u8>,
 }
 
-pub async fn get_saved_response(
+/// A response saved for an idempotency key, with what is needed to replay it.
+pub struct SavedResponse {
+    pub response: HttpResponse,
+    pub flash_messages: Vec<FlashMessage>,
+}
+
+/// What is stored for a key which has been used before.
+enum KeyState {
+    Completed(SavedResponse),
+    // `locked_until` is missing for the rows left in progress before keys were locked.
+    InProgress { locked_until: Option<DateTime<Utc>> },
+}
+
+struct KeyRecord {
+    state: KeyState,
+    request_fingerprint: Option<String>,
+}
+
+impl KeyRecord {
+    /// Keys saved before requests were fingerprinted match any request.
+    fn matches(&self, request_fingerprint: &str) -> bool {
+        self.request_fingerprint
+            .as_deref()
+            .is_none_or(|saved| saved == request_fingerprint)
+    }
+}
+
+async fn get_key_record(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
-    // `! as {column_name}` is required to avoid the error:
-    // `!` mark forcefully assume to tell the compiler that the column will not be null, while the column type is nullable.
-    // So if we mistakenly insert null value into the column, we will get "runtime" error.
+) -> Result<Option<KeyRecord>, anyhow::Error> {
     let query = sqlx::query!(
         r#"
-        SELECT 
-        response_status_code as "response_status_code!",
-        response_headers as "response_headers!: Vec<HeaderPairRecord>",
-        response_body as "response_body!"
+        SELECT
+        response_status_code,
+        response_headers as "response_headers: Vec<HeaderPairRecord>",
+        response_body,
+        response_flash_messages,
+        request_fingerprint,
+        locked_until
         FROM idempotency
-        WHERE user_id = $1 AND 
+        WHERE user_id = $1 AND
         idempotency_key = $2
         "#,
         user_id,
         idempotency_key.as_ref()
     );
 
-    let maybe_row = query.fetch_optional(pool).await?;
-    match maybe_row {
-        None => Ok(None),
-        Some(response) => {
-            let status_code = StatusCode::from_u16(response.response_status_code.try_into()?)?;
+    let Some(row) = query.fetch_optional(pool).await? else {
+        return Ok(None);
+    };
+    // The response columns are set together by `save_response`.
+    let state = match (
+        row.response_status_code,
+        row.response_headers,
+        row.response_body,
+    ) {
+        (Some(status_code), Some(headers), Some(body)) => {
+            let status_code = StatusCode::from_u16(status_code.try_into()?)?;
             let mut builder = HttpResponse::build(status_code);
-            for header in response.response_headers {
+            for header in headers {
                 builder.append_header((header.name, header.value));
             }
-            let http_response = builder.body(response.response_body);
-            Ok(Some(http_response))
+            KeyState::Completed(SavedResponse {
+                response: builder.body(body),
+                flash_messages: row
+                    .response_flash_messages
+                    .map(serde_json::from_value)
+                    .transpose()?
+                    .unwrap_or_default(),
+            })
         }
-    }
+        _ => KeyState::InProgress {
+            locked_until: row.locked_until,
+        },
+    };
+    Ok(Some(KeyRecord {
+        state,
+        request_fingerprint: row.request_fingerprint,
+    }))
 }
 
 impl PgHasArrayType for HeaderPairRecord {
@@ -59,12 +114,23 @@ impl PgHasArrayType for HeaderPairRecord {
     }
 }
 
+/// The lock a request holds on its idempotency key while it is processed.
+/// Its expiry tells it apart from the lock of a retry which took the key over after it expired.
+pub struct IdempotencyLock {
+    locked_until: DateTime<Utc>,
+}
+
+/// Saves `response` for the key and releases the lock, in the transaction of the request.
+/// Returns `None` if the lock has expired and the key has been taken over in the meantime,
+/// in which case the transaction has to be rolled back.
 pub async fn save_response(
-    mut transaction: Transaction<'static, Postgres>,
+    transaction: &mut Transaction<'_, Postgres>,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    lock: &IdempotencyLock,
     response: HttpResponse,
-) -> Result<HttpResponse, anyhow::Error> {
+    flash_messages: &[FlashMessage],
+) -> Result<Option<HttpResponse>, anyhow::Error> {
     let (response_head, body) = response.into_parts();
     let body_b = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
     let headers = response_head
@@ -76,82 +142,172 @@ pub async fn save_response(
         })
         .collect::<Vec<_>>();
     let status_code = response_head.status().as_u16() as i16;
+    let flash_messages = serde_json::to_value(flash_messages)?;
     let query = sqlx::query_unchecked!(
         r#"
-        UPDATE idempotency 
+        UPDATE idempotency
         SET
             response_status_code = $3,
             response_headers = $4,
-            response_body = $5
+            response_body = $5,
+            response_flash_messages = $6,
+            locked_until = NULL
         WHERE
-            user_id = $1 AND 
-            idempotency_key = $2
+            user_id = $1 AND
+            idempotency_key = $2 AND
+            locked_until = $7
         "#,
         user_id,
         idempotency_key.as_ref(),
         status_code,
         headers,
-        body_b.as_ref()
+        body_b.as_ref(),
+        flash_messages,
+        lock.locked_until
     );
-    transaction.execute(query).await?;
-    transaction.commit().await?;
+    if query
+        .execute(transaction.deref_mut())
+        .await?
+        .rows_affected()
+        == 0
+    {
+        tracing::warn!("The idempotency key was taken over before the response could be saved.");
+        return Ok(None);
+    }
 
     let http_response = response_head.set_body(body_b).map_into_boxed_body();
-    Ok(http_response)
+    Ok(Some(http_response))
+}
+
+/// Frees the key without saving a response, so that the request can be retried.
+pub async fn release_lock(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    lock: IdempotencyLock,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2 AND locked_until = $3
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        lock.locked_until
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
-    ReturnSavedResponse(HttpResponse),
+    StartProcessing(IdempotencyLock),
+    ReturnSavedResponse(SavedResponse),
+    // The key has been used for a request with another method, path or body.
+    RejectReusedKey,
+    // Another request with the key is still being processed, and its lock expires in this long.
+    RetryLater(Duration),
 }
 
+/// Locks the key for this request, unless it has been used already. A request still being
+/// processed with the same key is waited for, up to the wait timeout of `settings`.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    request_fingerprint: &str,
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
-    // The isolation level in postgres is "read committed" by default.
-    // In this isolation level, a SELECT query sees only data committed before the query began,
-    // while NEVER see data that is still uncommitted, or committed during query execution by concurrent transactions.
-
-    let mut transaction = pool.begin().await?;
-
-    // This query is for the case that the "concurrent" requests are sent to this server.
-    // The first request will insert a row into the table,
-    // and the second request will try to insert a row into the table,
-    // but it will do nothing because of the conflict.
+    let wait_deadline = Instant::now() + settings.wait_timeout();
+    // How long the lock of the request holding the key was last seen to last.
+    let mut locked_until = None;
+    let mut is_first_turn = true;
+    loop {
+        // Checked first, for the turns on which the key turns out to have been released too.
+        if !std::mem::take(&mut is_first_turn) {
+            let now = Instant::now();
+            if now >= wait_deadline {
+                let lock_left = locked_until
+                    .and_then(|locked_until: DateTime<Utc>| {
+                        (locked_until - Utc::now()).to_std().ok()
+                    })
+                    .unwrap_or_default();
+                return Ok(NextAction::RetryLater(lock_left));
+            }
+            tokio::time::sleep(POLL_INTERVAL.min(wait_deadline - now)).await;
+        }
+        if let Some(lock) = lock_key(
+            pool,
+            idempotency_key,
+            user_id,
+            request_fingerprint,
+            settings.lock_timeout(),
+        )
+        .await?
+        {
+            return Ok(NextAction::StartProcessing(lock));
+        }
+        // The key may have been released since, in which case it is locked on the next turn.
+        let Some(record) = get_key_record(pool, idempotency_key, user_id).await? else {
+            continue;
+        };
+        if !record.matches(request_fingerprint) {
+            return Ok(NextAction::RejectReusedKey);
+        }
+        match record.state {
+            KeyState::Completed(saved_response) => {
+                return Ok(NextAction::ReturnSavedResponse(saved_response))
+            }
+            KeyState::InProgress {
+                locked_until: lock_expiry,
+            } => locked_until = lock_expiry,
+        }
+    }
+}
 
-    let query = sqlx::query!(
+/// Returns `None` if the key is used by a request which has completed, or whose lock has not
+/// expired yet. A key left locked by a request with the same fingerprint is taken over once
+/// the lock has expired: the changes of the request which held it are committed together with
+/// its response, see `save_response`, and so are rolled back if it turns out to have lost the key.
+async fn lock_key(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    request_fingerprint: &str,
+    lock_timeout: Duration,
+) -> Result<Option<IdempotencyLock>, anyhow::Error> {
+    // Unlike holding a transaction open while the request is processed, the lock is committed
+    // right away, so concurrent requests can see that the key is in progress.
+    let locked_until = sqlx::query_scalar!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
-            created_at
+            request_fingerprint,
+            created_at,
+            locked_until
         )
-        VALUES ($1, $2, NOW())
-        ON CONFLICT (user_id, idempotency_key) DO NOTHING
+        VALUES ($1, $2, $3, NOW(), NOW() + make_interval(secs => $4))
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET
+            request_fingerprint = EXCLUDED.request_fingerprint,
+            created_at = EXCLUDED.created_at,
+            locked_until = EXCLUDED.locked_until
+        WHERE
+            idempotency.response_status_code IS NULL AND
+            (idempotency.locked_until IS NULL OR idempotency.locked_until < NOW()) AND
+            (idempotency.request_fingerprint IS NULL OR
+                idempotency.request_fingerprint = EXCLUDED.request_fingerprint)
+        RETURNING locked_until AS "locked_until!"
         "#,
         user_id,
         idempotency_key.as_ref(),
-    );
-
-    let n_inserted_rows = transaction.execute(query).await?.rows_affected();
-
-    if n_inserted_rows > 0 {
-        // This means that the request is the first request.
-        // This returning transaction will be used in the next `UPDATE` query.
-        // In postgres, the latter updater will *wait*
-        // for the first updater to commit or rollback.
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        // This means that the request is the second or later request.
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| {
-                anyhow::anyhow!("We expected a saved response, actually, we didn't find it.")
-            })?;
-        Ok(NextAction::ReturnSavedResponse(saved_response))
-    }
+        request_fingerprint,
+        lock_timeout.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(locked_until.map(|locked_until| IdempotencyLock { locked_until }))
 }
 
 // TODO: Implement expiry of the idempotency key.
@@ -2,6 +2,7 @@ use crate::configuration::Settings;
 use crate::startup::get_connection_pool;
 use sqlx::{Executor, PgPool};
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 // Expiring idempotency key
 // Reference implementation.
@@ -13,7 +14,7 @@ use std::time::Duration;
 #[tracing::instrument(skip_all)]
 pub async fn delete_expired_idempotency_key(
     pool: &PgPool,
-    expired_after_hours: u8,
+    expired_after_hours: u32,
 ) -> Result<u64, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let query_string = format!(
@@ -29,8 +30,12 @@ pub async fn delete_expired_idempotency_key(
     Ok(res.rows_affected())
 }
 
-async fn worker_loop(pool: PgPool, expired_after_hours: u8) -> Result<(), anyhow::Error> {
-    loop {
+async fn worker_loop(
+    pool: PgPool,
+    expired_after_hours: u32,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
         let delete_execution_result =
             delete_expired_idempotency_key(&pool, expired_after_hours).await;
         match delete_execution_result {
@@ -41,16 +46,21 @@ async fn worker_loop(pool: PgPool, expired_after_hours: u8) -> Result<(), anyhow
                 "Failed to delete expired idempotency keys."
             ),
         }
-        // Sleep for 24 hours.
-        tokio::time::sleep(Duration::from_secs(24 * 60 * 60)).await;
+        // Sleep for 24 hours, or until shutdown.
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            _ = tokio::time::sleep(Duration::from_secs(24 * 60 * 60)) => {}
+        }
     }
+    Ok(())
 }
 
 /// This is called in entry point of the application.
 pub async fn run_until_worker_stopped(
     configuration: Settings,
-    expired_after_hours: u8,
+    expired_after_hours: u32,
+    shutdown: CancellationToken,
 ) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    worker_loop(connection_pool, expired_after_hours).await
+    worker_loop(connection_pool, expired_after_hours, shutdown).await
 }
@@ -1,85 +1,172 @@
-use crate::configuration::Settings;
+use crate::configuration::{DeliverySettings, Settings};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailHeader, EmailTransport, OutgoingEmail, SendError};
+use crate::routes::unsubscribe_link;
 use crate::startup::get_connection_pool;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgListener;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::ops::DerefMut;
-use tracing::field::display;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::Span;
 use uuid::Uuid;
 
-#[tracing::instrument(skip_all, fields(newsletter_issue_id = tracing::field::Empty, email = tracing::field::Empty), err)]
-pub async fn try_execute_task(
+/// Claims up to `batch_size` delivery tasks and sends them in a single batch.
+/// The claim is committed before sending, and the outcomes are recorded afterwards in another
+/// transaction: a failure in between leaves the tasks claimed until the claim times out,
+/// rather than releasing the whole batch to be sent again right away.
+#[tracing::instrument(skip_all, fields(n_tasks = tracing::field::Empty), err)]
+pub async fn try_execute_tasks(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
+    base_url: &str,
+    settings: &DeliverySettings,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let maybe_task = dequeue_task(pool).await?;
-    if maybe_task.is_none() {
+    let (mut transaction, tasks) = claim_tasks(pool, settings).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (
-        transaction,
-        EmailTask {
-            newsletter_issue_id,
-            subscriber_email,
-            n_retries,
-        },
-    ) = maybe_task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", display(newsletter_issue_id))
-        .record("email", display(&subscriber_email));
-
-    let max_n_retries = 3;
-
-    if n_retries > max_n_retries {
-        tracing::error!(
-            "newsletter_issue_id={}, email={} has been retried {} times. We are giving up sending, deleting the task.",
-            newsletter_issue_id,
-            subscriber_email,
-            max_n_retries,
-        );
-        delete_task(transaction, newsletter_issue_id, &subscriber_email).await?;
-        return Ok(ExecutionOutcome::TaskCompleted);
+    let n_tasks = tasks.len();
+    Span::current().record("n_tasks", n_tasks);
+
+    let mut recipients = Vec::with_capacity(n_tasks);
+    for task in tasks {
+        // Normally given up right after the last failed attempt, see below.
+        // This catches tasks left over when `max_attempts` has been lowered.
+        if task.n_attempts() >= settings.max_attempts {
+            tracing::error!(
+                "newsletter_issue_id={}, email={} has been attempted {} times. We are giving up sending, deleting the task.",
+                task.newsletter_issue_id,
+                task.subscriber_email,
+                task.n_attempts(),
+            );
+            let error_message = format!("Gave up after {} attempts.", task.n_attempts());
+            fail_task(&mut transaction, &task, &error_message).await?;
+            continue;
+        }
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => recipients.push((task, email)),
+            Err(email_parse_error) => {
+                tracing::error!(
+                    error.message = %email_parse_error,
+                    email = %task.subscriber_email,
+                    "Failed to parse a stored subscriber email address. Giving up.",
+                );
+                // In this case, we don't retry, because the email address is invalid.
+                let error_message = format!("{} is not a valid email address.", email_parse_error);
+                fail_task(&mut transaction, &task, &error_message).await?;
+            }
+        }
     }
 
-    match SubscriberEmail::parse(subscriber_email.clone()) {
-        Ok(email) => {
-            let issue: NewsletterIssue = get_issue(pool, newsletter_issue_id).await?;
-            let send_result = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+    let emails: Vec<String> = recipients
+        .iter()
+        .map(|(task, _)| task.subscriber_email.clone())
+        .collect();
+    let unsubscribe_tokens = get_unsubscribe_tokens(&mut transaction, &emails).await?;
+    let mut deliveries = Vec::with_capacity(recipients.len());
+    for (task, email) in recipients {
+        match unsubscribe_tokens.get(email.as_ref()) {
+            Some(token) => deliveries.push((task, email, token.as_str())),
+            None => {
+                // The subscriber has left after the issue was enqueued.
+                tracing::info!(
+                    email = %task.subscriber_email,
+                    "The subscriber is no longer confirmed. Skipping this email."
+                );
+                complete_task(
+                    &mut transaction,
+                    &task,
+                    DeliveryOutcome::Skipped,
+                    Some("The subscriber is no longer confirmed."),
                 )
-                .await;
-            match send_result {
-                Ok(_) => {
-                    delete_task(transaction, newsletter_issue_id, email.as_ref()).await?;
-                    Ok(ExecutionOutcome::TaskCompleted)
-                }
-                Err(e) => {
+                .await?;
+            }
+        }
+    }
+
+    if deliveries.is_empty() {
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TasksProcessed(n_tasks));
+    }
+    let mut issue_ids: Vec<Uuid> = deliveries
+        .iter()
+        .map(|(task, _, _)| task.newsletter_issue_id)
+        .collect();
+    issue_ids.sort();
+    issue_ids.dedup();
+    let issues = get_issues(&mut transaction, &issue_ids).await?;
+
+    let mut outgoing_emails = Vec::with_capacity(deliveries.len());
+    for (task, email, unsubscribe_token) in &deliveries {
+        let issue = issues.get(&task.newsletter_issue_id).ok_or_else(|| {
+            anyhow::anyhow!("Newsletter issue {} not found.", task.newsletter_issue_id)
+        })?;
+        outgoing_emails.push(OutgoingEmail {
+            recipient: email.clone(),
+            subject: &issue.title,
+            html_content: &issue.html_content,
+            text_content: &issue.text_content,
+            headers: list_unsubscribe_headers(base_url, unsubscribe_token),
+        });
+    }
+
+    transaction.commit().await?;
+    let results = email_client.send_batch(&outgoing_emails).await;
+    transaction = pool.begin().await?;
+    for ((task, _, _), result) in deliveries.iter().zip(results) {
+        match result {
+            Ok(()) => {
+                complete_task(&mut transaction, task, DeliveryOutcome::Delivered, None).await?;
+            }
+            Err(SendError::Retryable {
+                message,
+                retry_after,
+            }) => {
+                let n_attempts = task.n_attempts() + 1;
+                if n_attempts >= settings.max_attempts {
                     tracing::error!(
-                        error.cause_chain = ?e,
-                        error.message = %e,
-                        "Failed to deliver issue to a confirmed subscriber\
-                        Skipping.",
+                        error.message = %message,
+                        newsletter_issue_id = %task.newsletter_issue_id,
+                        email = %task.subscriber_email,
+                        "Failed to deliver issue to a confirmed subscriber {} times. Giving up.",
+                        n_attempts,
                     );
-                    queue_retry_task(transaction, newsletter_issue_id, email.as_ref()).await?;
-                    Ok(ExecutionOutcome::TaskRetryScheduled)
+                    let error_message =
+                        format!("Gave up after {} attempts: {}", n_attempts, message);
+                    fail_task(&mut transaction, task, &error_message).await?;
+                    continue;
                 }
+                // Wait at least as long as the email service asked us to.
+                let delay = settings
+                    .retry_delay(n_attempts)
+                    .max(retry_after.unwrap_or_default());
+                tracing::warn!(
+                    error.message = %message,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    email = %task.subscriber_email,
+                    "Failed to deliver issue to a confirmed subscriber. Retrying in {:?}.",
+                    delay,
+                );
+                queue_retry_task(&mut transaction, task, delay).await?;
+            }
+            Err(SendError::Permanent(e)) => {
+                tracing::error!(
+                    error.message = %e,
+                    newsletter_issue_id = %task.newsletter_issue_id,
+                    email = %task.subscriber_email,
+                    "The email service rejected the issue for a confirmed subscriber. Giving up.",
+                );
+                fail_task(&mut transaction, task, &e).await?;
             }
-        }
-        Err(email_parse_error) => {
-            tracing::error!(
-                error.cause_chain = ?email_parse_error,
-                error.message = %email_parse_error,
-                "Failed to parse a stored subscriber email address. Skipping this email.",
-            );
-            Ok(ExecutionOutcome::TaskSkipped)
-            // In this case, we don't retry, because the email address is invalid.
         }
     }
+
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TasksProcessed(n_tasks))
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
@@ -87,37 +174,130 @@ type PgTransaction = Transaction<'static, Postgres>;
 struct EmailTask {
     newsletter_issue_id: Uuid,
     subscriber_email: String,
-    n_retries: i32,
+    n_retries: i16,
+    enqueued_at: DateTime<Utc>,
+}
+
+impl EmailTask {
+    fn n_attempts(&self) -> u16 {
+        u16::try_from(self.n_retries).unwrap_or_default()
+    }
 }
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, EmailTask)>, anyhow::Error> {
-    // Use transaction
+async fn claim_tasks(
+    pool: &PgPool,
+    settings: &DeliverySettings,
+) -> Result<(PgTransaction, Vec<EmailTask>), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let maybe_email_task = sqlx::query_as!(
+    let email_tasks = sqlx::query_as!(
         EmailTask,
         r#"
-        SELECT newsletter_issue_id, subscriber_email, n_retries
-        FROM issue_delivery_queue
-        WHERE
-            retry_after IS NULL OR now() > retry_after
-        LIMIT 1
-        FOR UPDATE SKIP LOCKED
+        UPDATE issue_delivery_queue
+        SET claimed_until = now() + make_interval(secs => $2)
+        WHERE (newsletter_issue_id, subscriber_email) IN (
+            SELECT newsletter_issue_id, subscriber_email
+            FROM issue_delivery_queue
+            WHERE
+                (retry_after IS NULL OR now() > retry_after) AND
+                (claimed_until IS NULL OR now() > claimed_until)
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING newsletter_issue_id, subscriber_email, n_retries, enqueued_at
         "#,
+        i64::from(settings.batch_size.get()),
+        settings.claim_timeout().as_secs_f64(),
     )
-    .fetch_optional(transaction.deref_mut())
+    .fetch_all(transaction.deref_mut())
     .await?;
+    Ok((transaction, email_tasks))
+}
+
+/// How a delivery task ended, as recorded in `issue_delivery_log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    Skipped,
+}
 
-    match maybe_email_task {
-        Some(email_task) => Ok(Some((transaction, email_task))),
-        None => Ok(None),
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        }
     }
 }
 
-//  4. async fn delete_task(mut transaction: PgTransaction, issue_id: Uuid, email: &str) -> Result<(), anyhow::Error>
+// Record the outcome and delete the task in the same transaction,
+// so that a recipient is either still in the queue or in the delivery log.
+#[tracing::instrument(skip_all)]
+async fn complete_task(
+    transaction: &mut PgTransaction,
+    task: &EmailTask,
+    outcome: DeliveryOutcome,
+    error_message: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id, subscriber_email, outcome, n_retries, error_message, recorded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        outcome.as_str(),
+        task.n_retries,
+        error_message,
+    );
+    transaction.execute(query).await?;
+    delete_task(
+        transaction,
+        task.newsletter_issue_id,
+        &task.subscriber_email,
+    )
+    .await
+}
+
+// Move the task to the dead letters in `issue_delivery_failures`,
+// where an admin can inspect and requeue it, see routes/admin/failures.
+#[tracing::instrument(skip_all)]
+async fn fail_task(
+    transaction: &mut PgTransaction,
+    task: &EmailTask,
+    error_message: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            failure_id, newsletter_issue_id, subscriber_email, n_retries, last_error, enqueued_at, failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries,
+        error_message,
+        task.enqueued_at,
+    );
+    transaction.execute(query).await?;
+    complete_task(
+        transaction,
+        task,
+        DeliveryOutcome::Failed,
+        Some(error_message),
+    )
+    .await
+}
+
 #[tracing::instrument(skip_all)]
 async fn delete_task(
-    mut transaction: PgTransaction,
+    transaction: &mut PgTransaction,
     issue_id: Uuid,
     email: &str,
 ) -> Result<(), anyhow::Error> {
@@ -131,111 +311,302 @@ async fn delete_task(
     );
 
     transaction.execute(query).await?;
-    transaction.commit().await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
 async fn queue_retry_task(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+    transaction: &mut PgTransaction,
+    task: &EmailTask,
+    delay: Duration,
 ) -> Result<(), anyhow::Error> {
-    // 1. Increment retries,
-    // The next job will executed after 2^retries seconds. This is called backoff retry.
+    // Increment retries, the task is not dequeued again until `retry_after`.
+    let retry_after = Utc::now() + chrono::Duration::from_std(delay)?;
     sqlx::query!(
         r#"
         UPDATE issue_delivery_queue
         SET
             n_retries = n_retries + 1,
-            retry_after = now() + ((interval '1 sec') * n_retries ^ 2)
+            retry_after = $3,
+            claimed_until = NULL
         WHERE
             newsletter_issue_id = $1 AND
             subscriber_email = $2
         "#,
-        issue_id,
-        email,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        retry_after,
     )
     .execute(transaction.deref_mut())
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
 //  5. struct NewsletterIssue, title text_content, html_content
 struct NewsletterIssue {
+    newsletter_issue_id: Uuid,
     title: String,
     text_content: String,
     html_content: String,
 }
 
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let q = sqlx::query_as!(
+async fn get_issues(
+    transaction: &mut PgTransaction,
+    issue_ids: &[Uuid],
+) -> Result<HashMap<Uuid, NewsletterIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT newsletter_issue_id, title, text_content, html_content
         FROM newsletter_issues
-        WHERE newsletter_issue_id = $1
+        WHERE newsletter_issue_id = ANY($1)
         "#,
-        issue_id,
-    );
+        issue_ids,
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(issues
+        .into_iter()
+        .map(|issue| (issue.newsletter_issue_id, issue))
+        .collect())
+}
 
-    let result = q.fetch_one(pool).await?;
-    Ok(result)
+/// Unsubscribe tokens of the confirmed subscribers among `emails`, keyed by email.
+#[tracing::instrument(skip_all)]
+async fn get_unsubscribe_tokens(
+    transaction: &mut PgTransaction,
+    emails: &[String],
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT email, unsubscribe_token
+        FROM subscriptions
+        WHERE email = ANY($1) AND status = 'confirmed'
+        "#,
+        emails,
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.email, r.unsubscribe_token))
+        .collect())
+}
+
+/// RFC 8058 one-click unsubscribe headers.
+/// Mailbox providers POST `List-Unsubscribe=One-Click` to the link in `List-Unsubscribe`.
+fn list_unsubscribe_headers(base_url: &str, unsubscribe_token: &str) -> Vec<EmailHeader> {
+    let link = unsubscribe_link(base_url, unsubscribe_token);
+    vec![
+        EmailHeader::new("List-Unsubscribe", format!("<{}>", link)),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
 }
 
 pub enum ExecutionOutcome {
-    TaskCompleted,
-    TaskRetryScheduled,
-    TaskSkipped,
+    TasksProcessed(usize),
     EmptyQueue,
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        let outcome_result = try_execute_task(&pool, &email_client).await;
+/// Postgres channel notified whenever delivery tasks are added to `issue_delivery_queue`.
+pub const ISSUE_DELIVERY_CHANNEL: &str = "issue_delivery_queue";
+
+/// Wakes up idle workers, once `transaction` is committed.
+#[tracing::instrument(skip_all)]
+pub async fn notify_delivery_workers(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    transaction
+        .execute(sqlx::query!(
+            "SELECT pg_notify($1, '')",
+            ISSUE_DELIVERY_CHANNEL
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Moves the dead letters back to `issue_delivery_queue` with a fresh retry count.
+/// Returns the number of requeued deliveries.
+#[tracing::instrument(skip_all)]
+pub async fn requeue_failed_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    failure_ids: &[Uuid],
+) -> Result<usize, sqlx::Error> {
+    let requeued = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_failures
+        WHERE failure_id = ANY($1)
+        RETURNING newsletter_issue_id, subscriber_email
+        "#,
+        failure_ids,
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let (issue_ids, emails): (Vec<Uuid>, Vec<String>) = requeued
+        .into_iter()
+        .map(|r| (r.newsletter_issue_id, r.subscriber_email))
+        .unzip();
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            SELECT * FROM UNNEST($1::uuid[], $2::text[])
+            ON CONFLICT DO NOTHING
+            "#,
+            &issue_ids,
+            &emails,
+        ))
+        .await?;
+    // The delivery log only holds recipients which have left the queue.
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_log
+            WHERE (newsletter_issue_id, subscriber_email) IN (
+                SELECT * FROM UNNEST($1::uuid[], $2::text[])
+            )
+            "#,
+            &issue_ids,
+            &emails,
+        ))
+        .await?;
+    // Issues marked as sent go back to sending until the requeued deliveries are done.
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'sending'
+            WHERE newsletter_issue_id = ANY($1) AND status = 'sent'
+            "#,
+            &issue_ids,
+        ))
+        .await?;
+    notify_delivery_workers(transaction).await?;
+    Ok(issue_ids.len())
+}
+
+/// Sleeps until new tasks are notified, the next retry comes due, or `poll_interval` elapses.
+/// Polling is only a safety net for missed notifications, e.g. while reconnecting.
+async fn wait_for_tasks(pool: &PgPool, listener: Option<&mut PgListener>, poll_interval: Duration) {
+    let timeout = match next_retry_due_in(pool).await {
+        Ok(Some(due_in)) => due_in.min(poll_interval),
+        Ok(None) => poll_interval,
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to look up the next retry of the delivery queue."
+            );
+            poll_interval
+        }
+    };
+    match listener {
+        Some(listener) => {
+            if let Ok(Err(e)) = tokio::time::timeout(timeout, listener.recv()).await {
+                // The listener reconnects on the next `recv`, don't hammer a database which is down.
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Lost the connection listening for new delivery tasks."
+                );
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        }
+        None => tokio::time::sleep(timeout).await,
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn next_retry_due_in(pool: &PgPool) -> Result<Option<Duration>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT min(retry_after) AS next_retry_after
+        FROM issue_delivery_queue
+        WHERE retry_after > now()
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row
+        .next_retry_after
+        .map(|next| (next - Utc::now()).to_std().unwrap_or_default()))
+}
+
+async fn listen_for_new_tasks(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Delivers queued issues until `shutdown` is cancelled. A batch in flight is always
+/// completed, with its outcomes recorded, before returning.
+pub async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    base_url: String,
+    settings: DeliverySettings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let mut listener = match listen_for_new_tasks(&pool).await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to listen for new delivery tasks. Falling back to polling."
+            );
+            None
+        }
+    };
+    let poll_interval = settings.poll_interval();
+    // Shutdown is only observed in between batches.
+    while !shutdown.is_cancelled() {
+        let outcome_result =
+            try_execute_tasks(&pool, email_client.as_ref(), &base_url, &settings).await;
         let outcome = match outcome_result {
             Ok(outcome) => outcome,
             Err(_) => {
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {}
+                }
                 continue;
             }
         };
 
         match outcome {
-            ExecutionOutcome::TaskCompleted => {
-                tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-                continue;
-            }
-            ExecutionOutcome::TaskRetryScheduled => {
-                tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-                continue;
-            }
-            ExecutionOutcome::TaskSkipped => {
+            // Tasks scheduled for a retry are not claimed again until their `retry_after`,
+            // so the next batch can be claimed right away.
+            ExecutionOutcome::TasksProcessed(_) => {
                 continue;
             }
             ExecutionOutcome::EmptyQueue => {
-                tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = wait_for_tasks(&pool, listener.as_mut(), poll_interval) => {}
+                }
                 continue;
             }
         }
     }
+    Ok(())
 }
 
 // Launching Background Workers
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
 
-    let sender_email = configuration
-        .email_client
-        .sender()
-        .expect("Invalid sender email address.");
-    let timeout = configuration.email_client.timeout();
-    let email_client = EmailClient::new(
-        configuration.email_client.base_url,
-        sender_email,
-        configuration.email_client.authorization_token,
-        timeout,
-    );
-    worker_loop(connection_pool, email_client).await
+    let email_client = configuration.email_client.client();
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.delivery,
+        shutdown,
+    )
+    .await
 }
@@ -0,0 +1,100 @@
+use crate::configuration::Settings;
+use crate::routes::enqueue_delivery_tasks;
+use crate::startup::get_connection_pool;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+// Scheduled newsletter issues
+// Every 30 seconds, issues in `scheduled` status whose `send_at` has passed are moved to `sending`,
+// and their delivery tasks are enqueued for issue_delivery_worker.rs.
+// Issues in `sending` status with no delivery task left are marked as `sent`.
+
+/// Returns the number of issues which have been enqueued.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_due_issues(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // SKIP LOCKED lets several worker processes run the scheduler without enqueuing an issue twice.
+    let due_issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND send_at <= now()
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+
+    for issue in &due_issues {
+        enqueue_delivery_tasks(&mut transaction, issue.newsletter_issue_id).await?;
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET status = 'sending', published_at = now()
+            WHERE newsletter_issue_id = $1
+            "#,
+            issue.newsletter_issue_id,
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(due_issues.len() as u64)
+}
+
+/// Returns the number of issues which have been marked as sent.
+#[tracing::instrument(skip_all)]
+pub async fn mark_delivered_issues_as_sent(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sent'
+        WHERE
+            status = 'sending' AND
+            NOT EXISTS (
+                SELECT 1 FROM issue_delivery_queue
+                WHERE issue_delivery_queue.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+            )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+async fn worker_loop(pool: PgPool, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        match enqueue_due_issues(&pool).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Enqueued {} scheduled newsletter issue(s).", n),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to enqueue scheduled newsletter issues."
+            ),
+        }
+        if let Err(e) = mark_delivered_issues_as_sent(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to mark delivered newsletter issues as sent."
+            );
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => {}
+            _ = tokio::time::sleep(Duration::from_secs(30)) => {}
+        }
+    }
+    Ok(())
+}
+
+/// This is called in entry point of the application.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    worker_loop(connection_pool, shutdown).await
+}
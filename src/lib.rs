@@ -1,3 +1,5 @@
+pub mod admin_cli;
+pub mod audit;
 mod authentication;
 pub mod configuration;
 pub mod domain;
@@ -5,9 +7,11 @@ pub mod email_client;
 pub mod idempotency;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
 
 pub mod idempotency_expiring_worker;
 pub mod issue_delivery_worker;
+pub mod issue_scheduling_worker;
@@ -1,41 +1,92 @@
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use std::future::Future;
+use tokio::task::{JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
 use zero2prod::configuration::get_configuration;
 use zero2prod::idempotency_expiring_worker;
 use zero2prod::issue_delivery_worker;
+use zero2prod::issue_scheduling_worker;
+use zero2prod::shutdown;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    const EXPIRED_AFTER_HOUR: u8 = 48;
+    const EXPIRED_AFTER_HOUR: u32 = 48;
     // Telemetry setup
     let subscriber = get_subscriber("rs_z2p", "info", std::io::stdout);
     init_subscriber(subscriber);
     let configuration = get_configuration().expect("Failed to read configuration");
+    // A delivery batch in flight is completed, rather than cut off after its emails have been
+    // sent, for which it is given as long as it may hold its claim.
+    let shutdown_timeout = configuration
+        .application
+        .shutdown_timeout()
+        .max(configuration.delivery.claim_timeout());
     let app = Application::build(configuration.clone()).await?;
 
     tracing::info!("Starting server port: {}", app.port());
 
-    let application_task = tokio::spawn(app.run_until_stopped());
-    let sending_email_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
-        configuration.clone(),
-    ));
-    let removing_expired_idempotency_key_task = tokio::spawn(
-        idempotency_expiring_worker::run_until_worker_stopped(configuration, EXPIRED_AFTER_HOUR),
+    let shutdown = CancellationToken::new();
+    tokio::spawn(shutdown::cancel_on_signal(shutdown.clone()));
+
+    let mut tasks = JoinSet::new();
+    spawn_task(
+        &mut tasks,
+        "Application API",
+        app.run_until_stopped(shutdown.clone()),
+    );
+    spawn_task(
+        &mut tasks,
+        "Issue Delivery Worker",
+        issue_delivery_worker::run_worker_until_stopped(configuration.clone(), shutdown.clone()),
+    );
+    spawn_task(
+        &mut tasks,
+        "Issue Scheduling Worker",
+        issue_scheduling_worker::run_worker_until_stopped(configuration.clone(), shutdown.clone()),
     );
-    tokio::select! {
-        app_outcome = application_task => {report_exit("Application API", app_outcome)},
-        email_sending_worker_outcome = sending_email_task => {
-            report_exit("Issue Delivery Worker", email_sending_worker_outcome)
-        },
-        removing_expired_idempotency_key_outcome = removing_expired_idempotency_key_task => {
-            report_exit("Idempotency Expiring Worker", removing_expired_idempotency_key_outcome)
-        },
-    };
+    spawn_task(
+        &mut tasks,
+        "Idempotency Expiring Worker",
+        idempotency_expiring_worker::run_until_worker_stopped(
+            configuration,
+            EXPIRED_AFTER_HOUR,
+            shutdown.clone(),
+        ),
+    );
+
+    // The first task to exit, either on a shutdown signal or on failure, stops the others.
+    if let Some(Ok((task_name, outcome))) = tasks.join_next().await {
+        report_exit(task_name, outcome);
+    }
+    shutdown.cancel();
+    let drained = tokio::time::timeout(shutdown_timeout, async {
+        while let Some(Ok((task_name, outcome))) = tasks.join_next().await {
+            report_exit(task_name, outcome);
+        }
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!(
+            "Tasks did not stop within {:?}, exiting anyway.",
+            shutdown_timeout
+        );
+    }
     Ok(())
 }
 
+type TaskOutcome = Result<Result<(), anyhow::Error>, JoinError>;
+
+fn spawn_task(
+    tasks: &mut JoinSet<(&'static str, TaskOutcome)>,
+    task_name: &'static str,
+    task: impl Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+) {
+    let handle = tokio::spawn(task);
+    tasks.spawn(async move { (task_name, handle.await) });
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(_)) => tracing::info!("{} task completed successfully.", task_name),
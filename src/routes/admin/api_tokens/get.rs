@@ -0,0 +1,109 @@
+use crate::authentication::{get_api_tokens, ApiScope, CsrfToken, UserId};
+use crate::domain::UserRole;
+use crate::routes::admin::issues::format_timestamp;
+use crate::utils::{e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// GET `/admin/api-tokens`, where users create the tokens other services use to call
+/// the JSON API on their behalf, and revoke them.
+#[tracing::instrument(name = "Get API tokens", skip(pool, flash_messages, csrf_token), fields(user_id = %*user_id))]
+pub async fn list_api_tokens(
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut tokens = String::new();
+    for token in get_api_tokens(**user_id, &pool).await.map_err(e500)? {
+        let action = match token.revoked_at {
+            Some(revoked_at) => format!("Revoked {}", format_timestamp(Some(revoked_at))),
+            None => format!(
+                r#"<form action="/admin/api-tokens/{}/revoke" method="post">
+                {csrf_field}
+                <button type="submit">Revoke</button>
+            </form>"#,
+                token.token_id
+            ),
+        };
+        writeln!(
+            tokens,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            escape_html(&token.name),
+            escape_html(&token.scopes.join(", ")),
+            format_timestamp(Some(token.created_at)),
+            format_timestamp(token.last_used_at),
+            action,
+        )
+        .unwrap();
+    }
+
+    // Only the scopes the role allows are offered.
+    let mut scopes = String::new();
+    for scope in ApiScope::ALL
+        .into_iter()
+        .filter(|scope| scope.is_allowed_for(*role))
+    {
+        writeln!(
+            scopes,
+            r#"<label><input type="checkbox" name="scope" value="{scope}"> {scope}</label><br>"#
+        )
+        .unwrap();
+    }
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>API tokens</title>
+</head>
+<body>
+  {message}
+    <table>
+        <tr>
+            <th>Name</th>
+            <th>Scopes</th>
+            <th>Created</th>
+            <th>Last used</th>
+            <th></th>
+        </tr>
+        {tokens}
+    </table>
+    <form action="/admin/api-tokens" method="post">
+        {csrf_field}
+        <label>Name
+            <input type="text" placeholder="Enter what the token is for" name="name">
+        </label>
+        <br>
+        {scopes}
+        <button type="submit">Create token</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
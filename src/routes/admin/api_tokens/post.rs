@@ -0,0 +1,132 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{create_api_token, revoke_api_token, ApiScope, UserId};
+use crate::domain::UserRole;
+use crate::utils::{e400, e500, escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::{Form, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+pub const NO_SCOPE_MESSAGE: &str = "Please select at least one scope for the token.";
+
+/// The checkboxes submit one `scope` pair per selected scope, so the form is read
+/// as a list of pairs rather than a struct.
+/// The token is rendered right away rather than redirecting, as this is the only time
+/// it can be shown.
+#[tracing::instrument(name = "Create an API token", skip(form, pool, request), fields(user_id = %*user_id))]
+pub async fn create_user_api_token(
+    form: Form<Vec<(String, String)>>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (field, value) in form.into_inner() {
+        match field.as_str() {
+            "name" => name = value.trim().to_string(),
+            "scope" => scopes.push(ApiScope::try_from(value).map_err(e400)?),
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        FlashMessage::error("Please give the token a name.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if scopes.is_empty() {
+        FlashMessage::error(NO_SCOPE_MESSAGE).send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    if let Some(scope) = scopes.iter().find(|scope| !scope.is_allowed_for(*role)) {
+        FlashMessage::error(format!(
+            "Your role does not allow creating tokens with the {} scope.",
+            scope
+        ))
+        .send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let (token_id, token) = create_api_token(**user_id, *role, &name, &scopes, &mut transaction)
+        .await
+        .map_err(e500)?;
+    let scopes: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+    let event = AuditEvent::new(AuditAction::ApiTokenCreated, Some(**user_id), &request)
+        .target(token_id)
+        .details(serde_json::json!({ "name": name, "scopes": scopes }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the API token.")
+        .map_err(e500)?;
+
+    let name = escape_html(&name);
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>New API token</title>
+</head>
+<body>
+    <p>The API token {name} has been created.</p>
+    <p>Copy it now, it will not be shown again. Send it as <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+    <p><code>{token}</code></p>
+    <p><a href="/admin/api-tokens">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+/// Requests made with the revoked token are refused from then on.
+#[tracing::instrument(name = "Revoke an API token", skip(pool, request), fields(user_id = %*user_id))]
+pub async fn revoke_user_api_token(
+    token_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token_id = token_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let revoked = revoke_api_token(**user_id, token_id, &mut transaction)
+        .await
+        .map_err(e500)?;
+    if revoked {
+        let event = AuditEvent::new(AuditAction::ApiTokenRevoked, Some(**user_id), &request)
+            .target(token_id);
+        record_audit_event(transaction.deref_mut(), event)
+            .await
+            .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the revocation of the API token.")
+            .map_err(e500)?;
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
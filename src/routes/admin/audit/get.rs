@@ -0,0 +1,132 @@
+use crate::audit::{get_audit_events, AuditAction, AuditFilter, AuditQuery};
+use crate::domain::UserRole;
+use crate::routes::admin::audit::AUDIT_OWNERS_ONLY_MESSAGE;
+use crate::routes::admin::issues::format_timestamp;
+use crate::utils::{e403, e500, escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+// The page shows the most recent events only, the CSV export has all of them.
+const MAX_EVENTS_ON_PAGE: i64 = 200;
+
+/// GET `/admin/audit`, the audit log of administrative actions, filtered by the query string.
+#[tracing::instrument(name = "Get audit log", skip(query, request, pool, flash_messages))]
+pub async fn list_audit_events(
+    query: web::Query<AuditQuery>,
+    request: HttpRequest,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_manage_users() {
+        return Err(e403(AUDIT_OWNERS_ONLY_MESSAGE));
+    }
+    let filter = match AuditFilter::parse(query.into_inner()) {
+        Ok(filter) => filter,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/audit"));
+        }
+    };
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut events = String::new();
+    for event in get_audit_events(&filter, Some(MAX_EVENTS_ON_PAGE), &pool)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            events,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td><code>{}</code></td>
+        </tr>"#,
+            format_timestamp(Some(event.occurred_at)),
+            escape_html(event.actor.as_deref().unwrap_or("-")),
+            escape_html(&event.action),
+            escape_html(event.target_id.as_deref().unwrap_or("-")),
+            escape_html(event.ip.as_deref().unwrap_or("-")),
+            escape_html(&event.details.to_string()),
+        )
+        .unwrap();
+    }
+
+    let mut action_options = String::from(r#"<option value="">any</option>"#);
+    for action in AuditAction::ALL {
+        let selected = if filter.action == Some(action) {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            action_options,
+            r#"<option value="{action}"{selected}>{action}</option>"#
+        )
+        .unwrap();
+    }
+    let actor = escape_html(filter.actor.as_deref().unwrap_or(""));
+    let since = filter.since.map(|d| d.to_string()).unwrap_or_default();
+    let until = filter.until.map(|d| d.to_string()).unwrap_or_default();
+    let export_link = format!(
+        "/admin/audit/export?{}",
+        escape_html(request.query_string())
+    );
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Audit log</title>
+</head>
+<body>
+  {message}
+    <form action="/admin/audit" method="get">
+        <label>Action
+            <select name="action">{action_options}</select>
+        </label>
+        <label>User
+            <input type="text" name="actor" value="{actor}" placeholder="Username">
+        </label>
+        <label>From
+            <input type="date" name="since" value="{since}">
+        </label>
+        <label>To
+            <input type="date" name="until" value="{until}">
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <p><a href="{export_link}">Export as CSV</a></p>
+    <table>
+        <tr>
+            <th>Time</th>
+            <th>User</th>
+            <th>Action</th>
+            <th>Target</th>
+            <th>IP address</th>
+            <th>Details</th>
+        </tr>
+        {events}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
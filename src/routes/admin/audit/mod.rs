@@ -0,0 +1,7 @@
+mod export;
+mod get;
+
+pub use export::export_audit_events;
+pub use get::list_audit_events;
+
+pub const AUDIT_OWNERS_ONLY_MESSAGE: &str = "Only owners can see the audit log.";
@@ -1,3 +1,5 @@
+use crate::authentication::CsrfToken;
+use crate::domain::UserRole;
 use crate::session_state::TypedSession;
 use crate::utils;
 use actix_web::http::header::{ContentType, LOCATION};
@@ -6,10 +8,12 @@ use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-#[tracing::instrument(name = "Get admin dashboard", skip(session, pool))]
+#[tracing::instrument(name = "Get admin dashboard", skip(session, pool, csrf_token))]
 pub async fn admin_dashboard(
     session: TypedSession,
+    role: web::ReqData<UserRole>,
     pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::error::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(utils::e500)? {
         get_username(user_id, &pool).await.map_err(utils::e500)?
@@ -19,6 +23,15 @@ pub async fn admin_dashboard(
             .finish());
     };
 
+    let role = role.into_inner();
+    let csrf_field = csrf_token.form_field();
+    // Only owners can manage the other users and see what they did.
+    let users_link = if role.can_manage_users() {
+        r#"<li><a href="/admin/users">Users</a></li>
+        <li><a href="/admin/audit">Audit log</a></li>"#
+    } else {
+        ""
+    };
     let body = format!(
         r#"<html lang="en">
 <head>
@@ -26,11 +39,23 @@ pub async fn admin_dashboard(
 <title>Admin dashboard</title>
 </head>
 <body>
-    <p>Welcome {username}</p>
+    <p>Welcome {username} ({role})</p>
     <p>Available actions:</p>
     <ol>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/security">Two-factor authentication</a></li>
+        <li><a href="/admin/sessions">Sessions</a></li>
+        <li><a href="/admin/api-tokens">API tokens</a></li>
         <li><a href="/admin/newsletters">Post a newsletter</a></li>
+        <li><a href="/admin/issues">Issue history</a></li>
+        <li><a href="/admin/failures">Failed deliveries</a></li>
+        {users_link}
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                {csrf_field}
+                <input type="submit" value="Logout">
+            </form>
+        </li>
     </ol>
 </body>
 </html>
@@ -0,0 +1,126 @@
+use crate::authentication::CsrfToken;
+use crate::routes::admin::issues::format_timestamp;
+use crate::utils::{e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+const MAX_FAILURES: i64 = 500;
+
+#[tracing::instrument(name = "Get failed deliveries", skip(pool, flash_messages, csrf_token))]
+pub async fn list_failures(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows = String::new();
+    for failure in get_failures(&pool).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+            <td><input type="checkbox" name="failure_id" value="{}"></td>
+            <td><a href="/admin/issues/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            failure.failure_id,
+            failure.newsletter_issue_id,
+            escape_html(&failure.title),
+            escape_html(&failure.subscriber_email),
+            failure.n_retries,
+            escape_html(&failure.last_error),
+            format_timestamp(Some(failure.enqueued_at)),
+            format_timestamp(Some(failure.failed_at)),
+        )
+        .unwrap();
+    }
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Failed deliveries</title>
+</head>
+<body>
+  {message}
+    <form action="/admin/failures/requeue" method="post">
+    {csrf_field}
+    <table>
+        <tr>
+            <th></th>
+            <th>Issue</th>
+            <th>Recipient</th>
+            <th>Retries</th>
+            <th>Last error</th>
+            <th>Enqueued at</th>
+            <th>Failed at</th>
+        </tr>
+        {rows}
+    </table>
+        <button type="submit">Requeue selected</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+struct DeliveryFailure {
+    failure_id: Uuid,
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    enqueued_at: DateTime<Utc>,
+    failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT
+            f.failure_id,
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_retries,
+            f.last_error,
+            f.enqueued_at,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY f.failed_at DESC
+        LIMIT $1
+        "#,
+        MAX_FAILURES,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch failed deliveries.")?;
+    Ok(failures)
+}
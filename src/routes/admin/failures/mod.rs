@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::list_failures;
+pub use post::{requeue_failures, NOTHING_SELECTED_MESSAGE};
@@ -0,0 +1,75 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::UserId;
+use crate::domain::UserRole;
+use crate::issue_delivery_worker::requeue_failed_deliveries;
+use crate::utils::{e400, e403, e500, see_other};
+use actix_web::web::{Form, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+pub const NOTHING_SELECTED_MESSAGE: &str = "Please select the failed deliveries to requeue.";
+
+/// The checkboxes of the failures page submit one `failure_id` pair per selected row,
+/// so the form is read as a list of pairs rather than a struct.
+#[tracing::instrument(
+name = "Requeuing failed deliveries",
+skip(form, pool, role, request),
+fields(user_id = % * user_id),
+)]
+pub async fn requeue_failures(
+    form: Form<Vec<(String, String)>>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_publish() {
+        return Err(e403("Viewers cannot requeue failed deliveries."));
+    }
+    let failure_ids = form
+        .into_inner()
+        .into_iter()
+        .filter(|(name, _)| name == "failure_id")
+        .map(|(_, value)| Uuid::parse_str(&value))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(e400)?;
+    if failure_ids.is_empty() {
+        FlashMessage::error(NOTHING_SELECTED_MESSAGE).send();
+        return Ok(see_other("/admin/failures"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_requeued = requeue_failed_deliveries(&mut transaction, &failure_ids)
+        .await
+        .context("Failed to requeue failed deliveries.")
+        .map_err(e500)?;
+    let event = AuditEvent::new(
+        AuditAction::FailedDeliveriesRequeued,
+        Some(**user_id),
+        &request,
+    )
+    .details(serde_json::json!({ "requeued": n_requeued }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit requeued deliveries.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} failed deliveries have been requeued.",
+        n_requeued
+    ))
+    .send();
+    Ok(see_other("/admin/failures"))
+}
@@ -0,0 +1,137 @@
+use crate::routes::admin::issues::summary::{format_timestamp, get_issue_summaries};
+use crate::utils::{e500, escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub const ISSUE_NOT_FOUND_MESSAGE: &str = "The newsletter issue does not exist.";
+
+// Large lists are only summarized by the counts above the table.
+const MAX_LOG_ENTRIES: i64 = 100;
+
+#[tracing::instrument(name = "Get newsletter issue delivery progress", skip(pool))]
+pub async fn issue_detail(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = match get_issue_summaries(&pool, Some(issue_id))
+        .await
+        .map_err(e500)?
+        .pop()
+    {
+        Some(issue) => issue,
+        None => {
+            FlashMessage::error(ISSUE_NOT_FOUND_MESSAGE).send();
+            return Ok(see_other("/admin/issues"));
+        }
+    };
+
+    let mut log_rows = String::new();
+    for entry in get_delivery_log(&pool, issue_id).await.map_err(e500)? {
+        writeln!(
+            log_rows,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            escape_html(&entry.subscriber_email),
+            entry.outcome,
+            entry.n_retries,
+            escape_html(entry.error_message.as_deref().unwrap_or("")),
+            format_timestamp(Some(entry.recorded_at)),
+        )
+        .unwrap();
+    }
+
+    let title = escape_html(&issue.title);
+    let status = &issue.status;
+    let send_at = format_timestamp(issue.send_at);
+    let published_at = format_timestamp(issue.published_at);
+    let (queued, retrying, delivered, failed, skipped) = (
+        issue.queued,
+        issue.retrying,
+        issue.delivered,
+        issue.failed,
+        issue.skipped,
+    );
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Status: {status}</p>
+    <p>Scheduled for: {send_at}</p>
+    <p>Published at: {published_at}</p>
+    <ul>
+        <li>Queued: {queued}</li>
+        <li>Retrying: {retrying}</li>
+        <li>Delivered: {delivered}</li>
+        <li>Failed: {failed}</li>
+        <li>Skipped: {skipped}</li>
+    </ul>
+    <p>Latest deliveries:</p>
+    <table>
+        <tr>
+            <th>Recipient</th>
+            <th>Outcome</th>
+            <th>Retries</th>
+            <th>Error</th>
+            <th>Recorded at</th>
+        </tr>
+        {log_rows}
+    </table>
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+struct DeliveryLogEntry {
+    subscriber_email: String,
+    outcome: String,
+    n_retries: i16,
+    error_message: Option<String>,
+    recorded_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_delivery_log(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<DeliveryLogEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        DeliveryLogEntry,
+        r#"
+        SELECT subscriber_email, outcome, n_retries, error_message, recorded_at
+        FROM issue_delivery_log
+        WHERE newsletter_issue_id = $1
+        ORDER BY recorded_at DESC
+        LIMIT $2
+        "#,
+        issue_id,
+        MAX_LOG_ENTRIES,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the delivery log of a newsletter issue.")?;
+    Ok(entries)
+}
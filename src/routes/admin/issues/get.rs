@@ -0,0 +1,78 @@
+use crate::routes::admin::issues::summary::{format_timestamp, get_issue_summaries};
+use crate::utils::{e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[tracing::instrument(name = "Get newsletter issue history", skip(pool, flash_messages))]
+pub async fn list_issues(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows = String::new();
+    for issue in get_issue_summaries(&pool, None).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+            <td><a href="/admin/issues/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            issue.newsletter_issue_id,
+            escape_html(&issue.title),
+            issue.status,
+            format_timestamp(issue.published_at),
+            issue.queued,
+            issue.retrying,
+            issue.delivered,
+            issue.failed,
+            issue.skipped,
+        )
+        .unwrap();
+    }
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Newsletter issues</title>
+</head>
+<body>
+  {message}
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Status</th>
+            <th>Published at</th>
+            <th>Queued</th>
+            <th>Retrying</th>
+            <th>Delivered</th>
+            <th>Failed</th>
+            <th>Skipped</th>
+        </tr>
+        {rows}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
@@ -0,0 +1,7 @@
+mod detail;
+mod get;
+mod summary;
+
+pub use detail::{issue_detail, ISSUE_NOT_FOUND_MESSAGE};
+pub use get::list_issues;
+pub use summary::{format_timestamp, get_issue_summaries, IssueSummary};
@@ -0,0 +1,75 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A newsletter issue together with the delivery progress of its recipients.
+/// `queued` and `retrying` come from `issue_delivery_queue`,
+/// the other counts from `issue_delivery_log`.
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub send_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub queued: i64,
+    pub retrying: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+/// Fetch a single issue when `issue_id` is given, otherwise all issues, newest first.
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_summaries(
+    pool: &PgPool,
+    issue_id: Option<Uuid>,
+) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let summaries = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.status,
+            i.send_at,
+            i.published_at,
+            COALESCE(q.queued, 0) AS "queued!",
+            COALESCE(q.retrying, 0) AS "retrying!",
+            COALESCE(l.delivered, 0) AS "delivered!",
+            COALESCE(l.failed, 0) AS "failed!",
+            COALESCE(l.skipped, 0) AS "skipped!"
+        FROM newsletter_issues i
+        LEFT JOIN (
+            SELECT
+                newsletter_issue_id,
+                COUNT(*) FILTER (WHERE n_retries = 0) AS queued,
+                COUNT(*) FILTER (WHERE n_retries > 0) AS retrying
+            FROM issue_delivery_queue
+            GROUP BY newsletter_issue_id
+        ) q ON q.newsletter_issue_id = i.newsletter_issue_id
+        LEFT JOIN (
+            SELECT
+                newsletter_issue_id,
+                COUNT(*) FILTER (WHERE outcome = 'delivered') AS delivered,
+                COUNT(*) FILTER (WHERE outcome = 'failed') AS failed,
+                COUNT(*) FILTER (WHERE outcome = 'skipped') AS skipped
+            FROM issue_delivery_log
+            GROUP BY newsletter_issue_id
+        ) l ON l.newsletter_issue_id = i.newsletter_issue_id
+        WHERE $1::uuid IS NULL OR i.newsletter_issue_id = $1
+        ORDER BY i.created_at DESC
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch newsletter issues with their delivery progress.")?;
+    Ok(summaries)
+}
+
+pub fn format_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "-".into())
+}
@@ -1,12 +1,41 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{revoke_session, UserId};
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
-use actix_web::HttpResponse;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::ops::DerefMut;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(e500)?.is_none() {
         Ok(see_other("/login"))
     } else {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire Postgres connection from the pool.")
+            .map_err(e500)?;
+        if let Some(session_id) = session.get_session_id().map_err(e500)? {
+            revoke_session(**user_id, session_id, transaction.deref_mut())
+                .await
+                .map_err(e500)?;
+        }
+        let event = AuditEvent::new(AuditAction::LoggedOut, Some(**user_id), &request);
+        record_audit_event(transaction.deref_mut(), event)
+            .await
+            .map_err(e500)?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the logout.")
+            .map_err(e500)?;
         session.log_out();
         FlashMessage::info("You have successfully logged out.").send();
         Ok(see_other("/login"))
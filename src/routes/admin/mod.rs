@@ -1,13 +1,27 @@
 /// # Admin module
-/// ## Note: New admin user registration not implemented.
-/// In the book, we assume the first user is seeded by database migration.
+/// The first user is seeded by database migration as an owner.
+/// Owners invite the other users from `/admin/users`.
 ///
+mod api_tokens;
+mod audit;
 mod dashboard;
+mod failures;
+mod issues;
 mod logout;
 pub mod newsletters;
 mod password;
+mod security;
+mod sessions;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use audit::{export_audit_events, list_audit_events};
+pub use dashboard::{admin_dashboard, get_username};
+pub use failures::*;
+pub use issues::*;
 pub use logout::log_out;
 pub use newsletters::*;
 pub use password::*;
+pub use security::*;
+pub use sessions::*;
+pub use users::*;
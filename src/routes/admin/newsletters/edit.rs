@@ -0,0 +1,266 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{CsrfToken, UserId};
+use crate::domain::{IssueStatus, UserRole};
+use crate::routes::admin::newsletters::post::{
+    enqueue_delivery_tasks, IssueAction, IssueTransition, PUBLISHERS_ONLY_MESSAGE,
+};
+use crate::utils::{e403, e500, escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::{Form, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+pub const NOT_EDITABLE_MESSAGE: &str = "Only drafts and scheduled newsletter issues can be edited.";
+
+struct EditableIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    status: IssueStatus,
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Get newsletter issue edit form",
+    skip(pool, flash_messages, csrf_token)
+)]
+pub async fn edit_newsletter_form(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let issue_id = issue_id.into_inner();
+    let issue = match get_issue(&pool, issue_id).await.map_err(e500)? {
+        Some(issue) if issue.status.is_editable() => issue,
+        _ => {
+            FlashMessage::error(NOT_EDITABLE_MESSAGE).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let title = escape_html(&issue.title);
+    let text_content = escape_html(&issue.text_content);
+    let html_content = escape_html(&issue.html_content);
+    let status = issue.status;
+    let send_at = issue
+        .send_at
+        .map(|send_at| send_at.format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_default();
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Edit a Newsletter</title>
+</head>
+<body>
+  {message}
+   <p>Status: {status}</p>
+   <form action="/admin/newsletters/{issue_id}" method="post">
+        {csrf_field}
+        <label>Title:<br>
+            <input type="text" name="title" value="{title}">
+        </label>
+        <br>
+        <label>Plain text content:
+        <br>
+            <textarea name="text_content" cols="50" rows="20">{text_content}</textarea>
+        </label>
+        <br>
+
+        <label>Html content:
+        <br>
+            <textarea name="html_content" cols="50" rows="20">{html_content}</textarea>
+        </label>
+        <br>
+        <label>Send at (UTC):<br>
+            <input type="datetime-local" name="send_at" value="{send_at}">
+        </label>
+        <br>
+        <button type="submit" name="action" value="publish">Publish now</button>
+        <button type="submit" name="action" value="schedule">Schedule</button>
+        <button type="submit" name="action" value="draft">Save as draft</button>
+        <button type="submit" name="action" value="cancel">Cancel issue</button>
+
+   </form>
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#,
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditFormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+    action: IssueAction,
+    send_at: Option<String>,
+}
+
+#[tracing::instrument(
+name = "Updating newsletter issue",
+skip(form, pool, role, request),
+fields(user_id = % * user_id),
+)]
+pub async fn update_newsletter(
+    issue_id: web::Path<Uuid>,
+    form: Form<EditFormData>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_publish() {
+        return Err(e403(PUBLISHERS_ONLY_MESSAGE));
+    }
+    let issue_id = issue_id.into_inner();
+    let EditFormData {
+        title,
+        text_content,
+        html_content,
+        action,
+        send_at,
+    } = form.into_inner();
+    let edit_page = format!("/admin/newsletters/{}", issue_id);
+    let transition = match IssueTransition::parse(action, send_at) {
+        Ok(transition) => transition,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&edit_page));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_updated_rows = update_issue(
+        &mut transaction,
+        issue_id,
+        &title,
+        &text_content,
+        &html_content,
+        &transition,
+    )
+    .await
+    .context("Failed to update newsletter issue details")
+    .map_err(e500)?;
+    if n_updated_rows == 0 {
+        // Either the issue does not exist, or it has been published concurrently.
+        FlashMessage::error(NOT_EDITABLE_MESSAGE).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+
+    if transition.status == IssueStatus::Sending {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+    // The status tells whether the issue has been published, scheduled or cancelled.
+    let event = AuditEvent::new(
+        AuditAction::NewsletterIssueUpdated,
+        Some(**user_id),
+        &request,
+    )
+    .target(issue_id)
+    .details(serde_json::json!({ "title": title, "status": transition.status.as_str() }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the newsletter issue update.")
+        .map_err(e500)?;
+
+    transition.success_message().send();
+    match transition.status {
+        IssueStatus::Draft | IssueStatus::Scheduled => Ok(see_other(&edit_page)),
+        _ => Ok(see_other("/admin/newsletters")),
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<Option<EditableIssue>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, status, send_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the newsletter issue.")?;
+
+    let issue = match row {
+        None => return Ok(None),
+        Some(row) => EditableIssue {
+            title: row.title,
+            text_content: row.text_content,
+            html_content: row.html_content,
+            status: IssueStatus::try_from(row.status).map_err(anyhow::Error::msg)?,
+            send_at: row.send_at,
+        },
+    };
+    Ok(Some(issue))
+}
+
+#[tracing::instrument(skip_all)]
+async fn update_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    transition: &IssueTransition,
+) -> Result<u64, sqlx::Error> {
+    // The status condition guards against editing an issue which has already been enqueued.
+    let q = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            status = $5,
+            send_at = $6,
+            published_at = $7
+        WHERE
+            newsletter_issue_id = $1 AND
+            status IN ('draft', 'scheduled')
+        "#,
+        issue_id,
+        title,
+        text_content,
+        html_content,
+        transition.status.as_str(),
+        transition.send_at,
+        transition.published_at,
+    );
+    let result = transaction.execute(q).await?;
+    Ok(result.rows_affected())
+}
@@ -1,27 +1,55 @@
+use crate::authentication::CsrfToken;
+use crate::utils::{e500, escape_html};
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
 use std::fmt::Write;
 use uuid::Uuid;
 
-pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn publish_newsletter_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let mut message = String::new();
     for m in flash_messages.iter() {
         writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let mut pending_issues = String::new();
+    for issue in get_pending_issues(&pool).await.map_err(e500)? {
+        let send_at = issue
+            .send_at
+            .map(|send_at| format!(" for {} UTC", send_at.format("%Y-%m-%d %H:%M")))
+            .unwrap_or_default();
+        writeln!(
+            pending_issues,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({}{})</li>"#,
+            issue.newsletter_issue_id,
+            escape_html(&issue.title),
+            issue.status,
+            send_at,
+        )
+        .unwrap();
+    }
+
     let idempotency_key = Uuid::new_v4().to_string();
     let html_body = format!(
         r#"<!doctype html>
 <html lang="en">
 <head>
 <meta charset="UTF-8">
- <meta http-equiv="content-type" content="text/html">    
+ <meta http-equiv="content-type" content="text/html">
 <title>Publish a Newsletter</title>
 </head>
 <body>
   {message}
    <form action="/admin/newsletters" method="post">
+        {csrf_field}
         <label>Title:<br>
             <input type="text" name="title" placeholder="Enter issue title">
         </label>
@@ -36,11 +64,21 @@ pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> H
         <br>
             <textarea name="html_content" cols="50" rows="20"></textarea>
         </label>
+        <br>
+        <label>Send at (UTC, only used when scheduling):<br>
+            <input type="datetime-local" name="send_at">
+        </label>
         <br>
             <input type="hidden" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Publish</button>
+        <button type="submit" name="action" value="publish">Publish</button>
+        <button type="submit" name="action" value="schedule">Schedule</button>
+        <button type="submit" name="action" value="draft">Save as draft</button>
 
    </form>
+    <p>Drafts and scheduled issues:</p>
+    <ul>
+        {pending_issues}
+    </ul>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>
@@ -48,7 +86,31 @@ pub async fn publish_newsletter_form(flash_messages: IncomingFlashMessages) -> H
         "#,
     );
 
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(html_body)
+        .body(html_body))
+}
+
+struct PendingIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    send_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_pending_issues(pool: &PgPool) -> Result<Vec<PendingIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PendingIssue,
+        r#"
+        SELECT newsletter_issue_id, title, status, send_at
+        FROM newsletter_issues
+        WHERE status IN ('draft', 'scheduled')
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch drafts and scheduled newsletter issues.")?;
+    Ok(issues)
 }
@@ -1,5 +1,10 @@
+mod edit;
 mod get;
 mod post;
 
+pub use edit::{edit_newsletter_form, update_newsletter, NOT_EDITABLE_MESSAGE};
 pub use get::publish_newsletter_form;
-pub use post::{publish_newsletter, SUCCESS_MESSAGE};
+pub use post::{
+    enqueue_delivery_tasks, insert_news_letter_issue, publish_newsletter, IssueAction,
+    IssueTransition, CANCELLED_MESSAGE, DRAFT_SAVED_MESSAGE, SUCCESS_MESSAGE,
+};
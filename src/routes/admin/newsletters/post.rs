@@ -1,12 +1,16 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
 use crate::authentication::UserId;
-use crate::email_client::EmailClient;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::utils::{e400, e500, see_other};
+use crate::domain::{IssueStatus, UserRole};
+use crate::email_client::EmailTransport;
+use crate::idempotency::{send_flash_message, IdempotentTransaction};
+use crate::issue_delivery_worker::notify_delivery_workers;
+use crate::utils::{e400, e403, e500, see_other};
 use actix_web::web::{Form, ReqData};
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -14,20 +18,101 @@ pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
+    // Required, but read by the `idempotency` middleware.
+    #[allow(dead_code)]
     idempotency_key: String,
+    #[serde(default)]
+    action: IssueAction,
+    send_at: Option<String>,
+}
+
+/// Which submit button was pressed on the newsletter form.
+/// Submissions without an `action` field are published right away.
+#[derive(serde::Deserialize, utoipa::ToSchema, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueAction {
+    #[default]
+    Publish,
+    Draft,
+    Schedule,
+    Cancel,
+}
+
+/// The state an issue moves to after an `IssueAction`.
+pub struct IssueTransition {
+    pub status: IssueStatus,
+    pub send_at: Option<DateTime<Utc>>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl IssueTransition {
+    pub fn parse(action: IssueAction, send_at: Option<String>) -> Result<Self, String> {
+        let (status, send_at) = match action {
+            IssueAction::Publish => (IssueStatus::Sending, None),
+            IssueAction::Draft => (IssueStatus::Draft, None),
+            IssueAction::Cancel => (IssueStatus::Cancelled, None),
+            IssueAction::Schedule => {
+                let send_at = send_at
+                    .filter(|s| !s.trim().is_empty())
+                    .ok_or("Please choose a date and time to send the issue at.")?;
+                let send_at = parse_send_at(&send_at)?;
+                if send_at <= Utc::now() {
+                    return Err("The scheduled time must be in the future.".into());
+                }
+                (IssueStatus::Scheduled, Some(send_at))
+            }
+        };
+        let published_at = (status == IssueStatus::Sending).then(Utc::now);
+        Ok(Self {
+            status,
+            send_at,
+            published_at,
+        })
+    }
+
+    pub fn success_message(&self) -> FlashMessage {
+        match (self.status, self.send_at) {
+            (IssueStatus::Draft, _) => FlashMessage::info(DRAFT_SAVED_MESSAGE),
+            (IssueStatus::Scheduled, Some(send_at)) => FlashMessage::info(format!(
+                "The newsletter issue has been scheduled for {} UTC.",
+                send_at.format("%Y-%m-%d %H:%M")
+            )),
+            (IssueStatus::Cancelled, _) => FlashMessage::info(CANCELLED_MESSAGE),
+            _ => success_message(),
+        }
+    }
+}
+
+/// `<input type="datetime-local">` submits `YYYY-MM-DDTHH:MM` without an offset,
+/// which we read as UTC. RFC 3339 timestamps are accepted as well.
+fn parse_send_at(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if let Ok(send_at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(send_at.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .map(|send_at| send_at.and_utc())
+        .map_err(|_| format!("{} is not a valid date and time.", s))
 }
 
 #[tracing::instrument(
 name = "Publishing newsletter",
-skip(form, pool),
+skip(form, transaction, role, request),
 fields(user_id = % * user_id),
 )]
 pub async fn publish_newsletter(
     form: Form<FormData>,
     user_id: ReqData<UserId>,
-    pool: web::Data<PgPool>,
-    _email_client: web::Data<EmailClient>,
+    role: ReqData<UserRole>,
+    // Committed by the `idempotency` middleware, together with the response.
+    mut transaction: IdempotentTransaction,
+    _email_client: web::Data<dyn EmailTransport>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_publish() {
+        return Err(e403(PUBLISHERS_ONLY_MESSAGE));
+    }
     let user_id = user_id.into_inner();
     // 1. Authenticate the request
     // ... depends on ReqData<UserId> middleware.
@@ -37,65 +122,88 @@ pub async fn publish_newsletter(
         title,
         text_content,
         html_content,
-        idempotency_key,
+        action,
+        send_at,
+        ..
     } = form.into_inner();
-    // 2. Parse the idempotency_key from the form data
-    let idempotency_key = IdempotencyKey::try_from(idempotency_key).map_err(e400)?;
-
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(http_response) => {
-            success_message().send();
-            return Ok(http_response);
+    // 2. Retried submissions are answered by the `idempotency` middleware.
+    if action == IssueAction::Cancel {
+        return Err(e400("A new newsletter issue cannot be cancelled."));
+    }
+    let transition = match IssueTransition::parse(action, send_at) {
+        Ok(transition) => transition,
+        Err(e) => {
+            send_flash_message(&request, FlashMessage::error(e));
+            return Ok(see_other("/admin/newsletters"));
         }
     };
 
-    let issue_id = insert_news_letter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_news_letter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        &transition,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
     // 4. Enqueue delivery tasks, which is processed by issue_delivery_worker.rs
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    // Drafts and scheduled issues are enqueued later, see issue_scheduling_worker.rs
+    if transition.status == IssueStatus::Sending {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
 
-    let response = see_other("/admin/newsletters");
-    /* 5. Save the response */
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
+    let event = AuditEvent::new(
+        AuditAction::NewsletterIssueCreated,
+        Some(*user_id),
+        &request,
+    )
+    .target(issue_id)
+    .details(serde_json::json!({ "title": title, "status": transition.status.as_str() }));
+    record_audit_event(&mut **transaction, event)
         .await
         .map_err(e500)?;
-    success_message().send();
-    Ok(response)
+    send_flash_message(&request, transition.success_message());
+    Ok(see_other("/admin/newsletters"))
 }
 
+pub const PUBLISHERS_ONLY_MESSAGE: &str = "Viewers cannot change newsletter issues.";
 pub const SUCCESS_MESSAGE: &str =
     "The newsletter issue has been accepted -emails will go out shortly.";
+pub const DRAFT_SAVED_MESSAGE: &str = "The newsletter issue has been saved as a draft.";
+pub const CANCELLED_MESSAGE: &str = "The newsletter issue has been cancelled.";
 fn success_message() -> FlashMessage {
     FlashMessage::info(SUCCESS_MESSAGE)
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_news_letter_issue(
+pub async fn insert_news_letter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
+    transition: &IssueTransition,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     let q = sqlx::query!(
         r#"
-        INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
-        VALUES ($1, $2, $3, $4, NOW())
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, status, send_at, published_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
         "#,
         newsletter_issue_id,
         title,
         text_content,
         html_content,
+        transition.status.as_str(),
+        transition.send_at,
+        transition.published_at,
     );
 
     transaction.execute(q).await?;
@@ -103,7 +211,7 @@ async fn insert_news_letter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -116,5 +224,6 @@ async fn enqueue_delivery_tasks(
     );
 
     transaction.execute(q).await?;
+    notify_delivery_workers(transaction).await?;
     Ok(())
 }
@@ -1,6 +1,8 @@
+use crate::authentication::CsrfToken;
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
@@ -8,7 +10,9 @@ use std::fmt::Write;
 pub async fn change_password_form(
     session: TypedSession,
     flash_message: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let mut error_messages = String::new();
     for m in flash_message.iter() {
         writeln!(error_messages, "<p><i>{}</i></p>", m.content()).unwrap();
@@ -26,6 +30,7 @@ pub async fn change_password_form(
 <body>
     {error_messages}
     <form action="/admin/password" method="post">
+        {csrf_field}
         <label >Current password
             <input type="password" name="current_password" placeholder="Enter current password">
         </label>
@@ -1,14 +1,19 @@
-use crate::authentication::UserId;
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{change_password_in_db, hash_password, revoke_other_sessions, UserId};
+use crate::authentication::{check_password_strength, AuthError, BreachedPasswords, Password};
 use crate::authentication::{validate_credentials, Credentials};
-use crate::authentication::{AuthError, Password};
+use crate::configuration::PasswordHashingSettings;
 use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
 
 use crate::utils::{e500, see_other};
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::{ExposeSecret, SecretBox};
 use serde::Deserialize;
 use sqlx::PgPool;
+use std::ops::DerefMut;
 
 #[derive(Deserialize)]
 pub struct FormData {
@@ -40,7 +45,11 @@ impl ValidPasswords {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    breached_passwords: web::Data<BreachedPasswords>,
     user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    request: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
     // Password length check.
     let form = match ValidPasswords::parse(form.into_inner()) {
@@ -64,11 +73,15 @@ pub async fn change_password(
     let user_id = user_id.into_inner();
 
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    if let Err(e) = check_password_strength(&form.new_password, &username, &breached_passwords) {
+        FlashMessage::error(e.to_string()).send();
+        return Ok(see_other("/admin/password"));
+    }
     let credentials = Credentials {
         username,
         password: form.current_password.inner_ref().clone(),
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -77,8 +90,32 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password_in_db(*user_id, form.new_password, &pool)
+    let password_hash = hash_password(form.new_password, &hashing)
+        .await
+        .map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    change_password_in_db(*user_id, &password_hash, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    // The other sessions may belong to someone who learnt the old password.
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let n_revoked = revoke_other_sessions(*user_id, current_session_id, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    let event = AuditEvent::new(AuditAction::PasswordChanged, Some(*user_id), &request)
+        .target(*user_id)
+        .details(serde_json::json!({ "revoked_sessions": n_revoked }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
         .await
+        .context("Failed to commit the password change.")
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
@@ -0,0 +1,88 @@
+use crate::authentication::{get_two_factor_status, CsrfToken, TwoFactorStatus, UserId};
+use crate::routes::admin::dashboard::get_username;
+use crate::utils::{e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// GET `/admin/security`, where two-factor authentication is set up and turned off.
+#[tracing::instrument(name = "Get security settings", skip(pool, flash_messages, csrf_token), fields(user_id = %*user_id))]
+pub async fn security_settings(
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let user_id = user_id.into_inner();
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let two_factor = match get_two_factor_status(*user_id, &username, &pool)
+        .await
+        .map_err(e500)?
+    {
+        TwoFactorStatus::Disabled => format!(
+            r#"<p>Two-factor authentication is disabled.</p>
+    <form action="/admin/security/totp" method="post">
+        {csrf_field}
+        <button type="submit">Set up two-factor authentication</button>
+    </form>"#
+        ),
+        TwoFactorStatus::Pending(enrollment) => format!(
+            r#"<p>Scan this QR code with your authenticator app, then enter the code it shows.</p>
+    {qr_code}
+    <p>Or enter the secret by hand: <code>{secret}</code></p>
+    <p>Provisioning URI: <code>{provisioning_uri}</code></p>
+    <form action="/admin/security/totp/confirm" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input type="text" name="code" inputmode="numeric" autocomplete="one-time-code">
+        </label>
+        <button type="submit">Enable two-factor authentication</button>
+    </form>"#,
+            qr_code = enrollment.qr_code_svg().map_err(e500)?,
+            secret = enrollment.secret,
+            provisioning_uri = escape_html(&enrollment.provisioning_uri),
+        ),
+        TwoFactorStatus::Enabled {
+            remaining_recovery_codes,
+        } => format!(
+            r#"<p>Two-factor authentication is enabled. {remaining_recovery_codes} unused recovery codes left.</p>
+    <form action="/admin/security/totp/disable" method="post">
+        {csrf_field}
+        <label>Authentication code or recovery code
+            <input type="text" name="code">
+        </label>
+        <button type="submit">Turn off two-factor authentication</button>
+    </form>"#
+        ),
+    };
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Security</title>
+</head>
+<body>
+  {message}
+    {two_factor}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
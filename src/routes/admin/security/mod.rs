@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::security_settings;
+pub use post::{confirm_two_factor_setup, start_two_factor_setup, turn_off_two_factor};
@@ -0,0 +1,164 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{
+    confirm_totp_enrollment, disable_two_factor, start_totp_enrollment, verify_second_factor,
+    LoginRateLimiter, UserId,
+};
+use crate::routes::too_many_codes_message;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::{Form, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::ops::DerefMut;
+
+pub const INVALID_CODE_MESSAGE: &str = "The authentication code is invalid.";
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(name = "Start two-factor setup", skip(pool), fields(user_id = %*user_id))]
+pub async fn start_two_factor_setup(
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    start_totp_enrollment(**user_id, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/security"))
+}
+
+/// The recovery codes are rendered right away rather than redirecting,
+/// as this is the only time they can be shown.
+#[tracing::instrument(name = "Confirm two-factor setup", skip(form, pool, session, request), fields(user_id = %*user_id))]
+pub async fn confirm_two_factor_setup(
+    form: Form<FormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(recovery_codes) =
+        confirm_totp_enrollment(**user_id, form.code.trim(), &mut transaction)
+            .await
+            .map_err(e500)?
+    else {
+        FlashMessage::error(INVALID_CODE_MESSAGE).send();
+        return Ok(see_other("/admin/security"));
+    };
+    let event =
+        AuditEvent::new(AuditAction::TwoFactorEnabled, Some(**user_id), &request).target(**user_id);
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the TOTP enrollment.")
+        .map_err(e500)?;
+    // The current session has just proven the second factor; every other session is refused from now on.
+    session.insert_two_factor_verified().map_err(e500)?;
+
+    let mut codes = String::new();
+    for code in recovery_codes {
+        writeln!(codes, "<li><code>{}</code></li>", code).unwrap();
+    }
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Recovery codes</title>
+</head>
+<body>
+    <p>Two-factor authentication is enabled.</p>
+    <p>Store these recovery codes somewhere safe. Each of them can be used once to log in
+    without your authenticator app, and they will not be shown again.</p>
+    <ul>
+        {codes}
+    </ul>
+    <p><a href="/admin/security">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[tracing::instrument(
+    name = "Turn off two-factor authentication",
+    skip(form, pool, request, rate_limiter),
+    fields(user_id = %*user_id)
+)]
+pub async fn turn_off_two_factor(
+    form: Form<FormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+    rate_limiter: web::Data<LoginRateLimiter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Wrong codes count towards the same lockout as at login, so that a session alone
+    // is not enough to guess the code.
+    if let Some(lockout) = rate_limiter
+        .two_factor_lockout(**user_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(too_many_codes_message(lockout)).send();
+        return Ok(see_other("/admin/security"));
+    }
+    if !verify_second_factor(**user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    {
+        let message = match rate_limiter
+            .record_two_factor_failure(**user_id)
+            .await
+            .map_err(e500)?
+        {
+            Some(lockout) => too_many_codes_message(lockout),
+            None => INVALID_CODE_MESSAGE.to_string(),
+        };
+        FlashMessage::error(message).send();
+        return Ok(see_other("/admin/security"));
+    }
+    rate_limiter
+        .record_two_factor_success(**user_id)
+        .await
+        .map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    disable_two_factor(**user_id, &mut transaction)
+        .await
+        .map_err(e500)?;
+    let event = AuditEvent::new(AuditAction::TwoFactorDisabled, Some(**user_id), &request)
+        .target(**user_id);
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit disabling two-factor authentication.")
+        .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been turned off.").send();
+    Ok(see_other("/admin/security"))
+}
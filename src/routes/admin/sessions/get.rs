@@ -0,0 +1,92 @@
+use crate::authentication::{get_user_sessions, CsrfToken, UserId};
+use crate::routes::admin::issues::format_timestamp;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// GET `/admin/sessions`, where users see where they are logged in and revoke those sessions.
+#[tracing::instrument(name = "Get sessions", skip(session, pool, flash_messages, csrf_token), fields(user_id = %*user_id))]
+pub async fn list_sessions(
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let mut sessions = String::new();
+    for user_session in get_user_sessions(**user_id, &pool).await.map_err(e500)? {
+        let action = if Some(user_session.session_id) == current_session_id {
+            "(this session)".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">
+                {csrf_field}
+                <button type="submit">Revoke</button>
+            </form>"#,
+                user_session.session_id
+            )
+        };
+        writeln!(
+            sessions,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            format_timestamp(Some(user_session.created_at)),
+            format_timestamp(Some(user_session.last_seen_at)),
+            escape_html(user_session.ip.as_deref().unwrap_or("-")),
+            escape_html(user_session.user_agent.as_deref().unwrap_or("-")),
+            action,
+        )
+        .unwrap();
+    }
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Sessions</title>
+</head>
+<body>
+  {message}
+    <table>
+        <tr>
+            <th>Logged in</th>
+            <th>Last seen</th>
+            <th>IP address</th>
+            <th>Browser</th>
+            <th></th>
+        </tr>
+        {sessions}
+    </table>
+    <form action="/admin/sessions/revoke-others" method="post">
+        {csrf_field}
+        <button type="submit">Log out all other sessions</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
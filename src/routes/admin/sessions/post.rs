@@ -0,0 +1,83 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{revoke_other_sessions, revoke_session, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+/// The revoked session is logged out by `reject_anonymous_user` on its next request.
+#[tracing::instrument(name = "Revoke a session", skip(session, pool, request), fields(user_id = %*user_id))]
+pub async fn revoke_user_session(
+    target_session_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_session_id = target_session_id.into_inner();
+    if session.get_session_id().map_err(e500)? == Some(target_session_id) {
+        FlashMessage::error("Use the logout button to end this session.").send();
+        return Ok(see_other("/admin/sessions"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let revoked = revoke_session(**user_id, target_session_id, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    if revoked {
+        let event = AuditEvent::new(AuditAction::SessionRevoked, Some(**user_id), &request)
+            .target(target_session_id);
+        record_audit_event(transaction.deref_mut(), event)
+            .await
+            .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the revoked session.")
+        .map_err(e500)?;
+    if revoked {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session does not exist or has already ended.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "Revoke the other sessions", skip(session, pool, request), fields(user_id = %*user_id))]
+pub async fn revoke_other_user_sessions(
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_revoked = revoke_other_sessions(**user_id, current_session_id, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    let event = AuditEvent::new(AuditAction::SessionRevoked, Some(**user_id), &request)
+        .details(serde_json::json!({ "revoked_sessions": n_revoked }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the revoked sessions.")
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} other sessions have been revoked.", n_revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
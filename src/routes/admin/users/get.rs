@@ -0,0 +1,184 @@
+use crate::authentication::{get_pending_invitations, CsrfToken, UserId};
+use crate::domain::UserRole;
+use crate::routes::admin::issues::format_timestamp;
+use crate::utils::{e403, e500, escape_html};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub const OWNERS_ONLY_MESSAGE: &str = "Only owners can manage users.";
+
+/// GET `/admin/users`, where owners invite new users, change roles and deactivate accounts.
+#[tracing::instrument(name = "Get users", skip(pool, flash_messages, csrf_token), fields(user_id = %*user_id))]
+pub async fn list_users(
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    if !role.can_manage_users() {
+        return Err(e403(OWNERS_ONLY_MESSAGE));
+    }
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut users = String::new();
+    for user in get_users(&pool).await.map_err(e500)? {
+        let is_current_user = user.user_id == **user_id;
+        let actions = if is_current_user {
+            "(you)".to_string()
+        } else {
+            let mut role_options = String::new();
+            for role in UserRole::ALL {
+                let selected = if role.as_str() == user.role {
+                    " selected"
+                } else {
+                    ""
+                };
+                write!(
+                    role_options,
+                    r#"<option value="{role}"{selected}>{role}</option>"#
+                )
+                .unwrap();
+            }
+            let (activation, label) = match user.deactivated_at {
+                None => ("deactivate", "Deactivate"),
+                Some(_) => ("reactivate", "Reactivate"),
+            };
+            format!(
+                r#"<form action="/admin/users/{id}/role" method="post">
+                {csrf_field}
+                <select name="role">{role_options}</select>
+                <button type="submit">Change role</button>
+            </form>
+            <form action="/admin/users/{id}/{activation}" method="post">
+                {csrf_field}
+                <button type="submit">{label}</button>
+            </form>"#,
+                id = user.user_id,
+            )
+        };
+        writeln!(
+            users,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            escape_html(&user.username),
+            escape_html(user.email.as_deref().unwrap_or("")),
+            user.role,
+            user.deactivated_at
+                .map(|at| format!("deactivated since {}", format_timestamp(Some(at))))
+                .unwrap_or_else(|| "active".to_string()),
+            actions,
+        )
+        .unwrap();
+    }
+
+    let mut invitations = String::new();
+    for invitation in get_pending_invitations(&pool).await.map_err(e500)? {
+        writeln!(
+            invitations,
+            "<li>{} as {}, valid until {}</li>",
+            escape_html(&invitation.email),
+            invitation.role,
+            format_timestamp(Some(invitation.expires_at)),
+        )
+        .unwrap();
+    }
+
+    let mut role_options = String::new();
+    for role in UserRole::ALL {
+        let selected = if role == UserRole::Editor {
+            " selected"
+        } else {
+            ""
+        };
+        write!(
+            role_options,
+            r#"<option value="{role}"{selected}>{role}</option>"#
+        )
+        .unwrap();
+    }
+
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Users</title>
+</head>
+<body>
+  {message}
+    <table>
+        <tr>
+            <th>Username</th>
+            <th>Email</th>
+            <th>Role</th>
+            <th>Status</th>
+            <th></th>
+        </tr>
+        {users}
+    </table>
+    <p>Pending invitations:</p>
+    <ul>
+        {invitations}
+    </ul>
+    <form action="/admin/users/invite" method="post">
+        {csrf_field}
+        <label>Email
+            <input type="email" name="email" placeholder="Enter the email address to invite">
+        </label>
+        <label>Role
+            <select name="role">{role_options}</select>
+        </label>
+        <button type="submit">Send invitation</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+    deactivated_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, email, role, deactivated_at
+        FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the users.")?;
+    Ok(users)
+}
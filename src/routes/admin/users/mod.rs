@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::list_users;
+pub use post::{change_user_role, deactivate_user, invite_user, reactivate_user};
@@ -0,0 +1,261 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{create_invitation, InvitationOutcome, UserId};
+use crate::domain::{SubscriberEmail, UserRole};
+use crate::email_client::{EmailTransport, SendError};
+use crate::routes::admin::users::get::OWNERS_ONLY_MESSAGE;
+use crate::startup::{ApplicationBaseUrl, InvitationTtl};
+use crate::utils::{e403, e500, see_other};
+use actix_web::web::{Form, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+use uuid::Uuid;
+
+pub const CANNOT_CHANGE_YOURSELF_MESSAGE: &str =
+    "You cannot change the role or deactivate your own account.";
+
+#[derive(Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: UserRole,
+}
+
+// Handlers take one extractor per piece of application state they use.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, email_client, base_url, invitation_ttl, request),
+    fields(user_id = %*user_id)
+)]
+pub async fn invite_user(
+    form: Form<InviteFormData>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    invitation_ttl: web::Data<InvitationTtl>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_manage_users() {
+        return Err(e403(OWNERS_ONLY_MESSAGE));
+    }
+    let InviteFormData { email, role } = form.into_inner();
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(email) => {
+            FlashMessage::error(format!("{} is not a valid email address.", email)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let outcome = create_invitation(&email, role, **user_id, invitation_ttl.0, &mut transaction)
+        .await
+        .map_err(e500)?;
+    let token = match outcome {
+        InvitationOutcome::Created(token) => token,
+        InvitationOutcome::EmailInUse => {
+            FlashMessage::error("A user with this email address already exists.").send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let event = AuditEvent::new(AuditAction::UserInvited, Some(**user_id), &request)
+        .target(&email)
+        .details(serde_json::json!({ "role": role.as_str() }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the invitation.")
+        .map_err(e500)?;
+    // Sent once committed, so that the link works as soon as it arrives.
+    send_invitation_email(email_client.as_ref(), &email, role, &base_url.0, &token)
+        .await
+        .context("Failed to send the invitation email.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("An invitation has been sent to {}.", email)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(skip(email_client, token))]
+async fn send_invitation_email(
+    email_client: &dyn EmailTransport,
+    email: &SubscriberEmail,
+    role: UserRole,
+    base_url: &str,
+    token: &str,
+) -> Result<(), SendError> {
+    let invitation_link = format!("{}/invitations/accept?token={}", base_url, token);
+    let plain_body = format!(
+        "You have been invited to manage our newsletter as {}.\nVisit {} to choose your username and password.",
+        role, invitation_link
+    );
+    let html_body = format!(
+        "You have been invited to manage our newsletter as {}.<br />Click <a href=\"{}\">here</a> to choose your username and password.",
+        role, invitation_link
+    );
+    email_client
+        .send_email(email, "You have been invited", &html_body, &plain_body)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct RoleFormData {
+    role: UserRole,
+}
+
+#[tracing::instrument(name = "Change the role of a user", skip(form, pool, request), fields(user_id = %*user_id))]
+pub async fn change_user_role(
+    target_user_id: web::Path<Uuid>,
+    form: Form<RoleFormData>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_manage_users() {
+        return Err(e403(OWNERS_ONLY_MESSAGE));
+    }
+    let target_user_id = target_user_id.into_inner();
+    // Owners cannot demote themselves, so there is always at least one owner left.
+    if target_user_id == **user_id {
+        FlashMessage::error(CANNOT_CHANGE_YOURSELF_MESSAGE).send();
+        return Ok(see_other("/admin/users"));
+    }
+    let new_role = form.into_inner().role;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    sqlx::query!(
+        "UPDATE users SET role = $2 WHERE user_id = $1",
+        target_user_id,
+        new_role.as_str(),
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to update the role of the user.")
+    .map_err(e500)?;
+    let event = AuditEvent::new(AuditAction::UserRoleChanged, Some(**user_id), &request)
+        .target(target_user_id)
+        .details(serde_json::json!({ "role": new_role.as_str() }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new role of the user.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("The role has been changed to {}.", new_role)).send();
+    Ok(see_other("/admin/users"))
+}
+
+pub async fn deactivate_user(
+    target_user_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_deactivated(
+        target_user_id.into_inner(),
+        true,
+        user_id,
+        role,
+        pool,
+        request,
+    )
+    .await
+}
+
+pub async fn reactivate_user(
+    target_user_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    set_deactivated(
+        target_user_id.into_inner(),
+        false,
+        user_id,
+        role,
+        pool,
+        request,
+    )
+    .await
+}
+
+/// The sessions of a deactivated user are refused by `reject_anonymous_user` on their next request.
+#[tracing::instrument(name = "Deactivate or reactivate a user", skip(pool, role, request), fields(user_id = %*user_id))]
+async fn set_deactivated(
+    target_user_id: Uuid,
+    deactivated: bool,
+    user_id: ReqData<UserId>,
+    role: ReqData<UserRole>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !role.can_manage_users() {
+        return Err(e403(OWNERS_ONLY_MESSAGE));
+    }
+    if target_user_id == **user_id {
+        FlashMessage::error(CANNOT_CHANGE_YOURSELF_MESSAGE).send();
+        return Ok(see_other("/admin/users"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET deactivated_at = CASE WHEN $2 THEN COALESCE(deactivated_at, now()) END
+        WHERE user_id = $1
+        "#,
+        target_user_id,
+        deactivated,
+    )
+    .execute(transaction.deref_mut())
+    .await
+    .context("Failed to update the status of the user.")
+    .map_err(e500)?;
+
+    let (action, message) = if deactivated {
+        (
+            AuditAction::UserDeactivated,
+            "The user has been deactivated.",
+        )
+    } else {
+        (
+            AuditAction::UserReactivated,
+            "The user has been reactivated.",
+        )
+    };
+    let event = AuditEvent::new(action, Some(**user_id), &request).target(target_user_id);
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the status of the user.")
+        .map_err(e500)?;
+    FlashMessage::info(message).send();
+    Ok(see_other("/admin/users"))
+}
@@ -0,0 +1,130 @@
+use crate::authentication::ApiScope;
+use crate::idempotency::{retry_after_seconds, IdempotencyError};
+use crate::routes::error_chain_fmt;
+use actix_web::http::header::{RETRY_AFTER, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt::Formatter;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+/// # Errors of the JSON API under `/api/v1`
+/// They are rendered as `{"error": {"code": ..., "message": ...}}`, where `code` is meant
+/// for programs and stays stable, and `message` is meant for people.
+/// The cause of unexpected errors is logged, but not exposed.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("This API token does not have the {0} scope.")]
+    MissingScope(ApiScope),
+    #[error("{0}")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error("The idempotency key has already been used for a different request.")]
+    IdempotencyKeyReused,
+    #[error("A request with the same idempotency key is still being processed.")]
+    RequestInProgress(Duration),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "invalid_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::MissingScope(_) => "missing_scope",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::IdempotencyKeyReused => "idempotency_key_reused",
+            ApiError::RequestInProgress(_) => "request_in_progress",
+            ApiError::Unexpected(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::MissingScope(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RequestInProgress(_) => StatusCode::CONFLICT,
+            ApiError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            ApiError::Unexpected(_) => "An unexpected error occurred.".to_string(),
+            e => e.to_string(),
+        };
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            ApiError::Unauthorized(_) => {
+                response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            ApiError::RequestInProgress(retry_after) => {
+                response.insert_header((RETRY_AFTER, retry_after_seconds(*retry_after)));
+            }
+            _ => {}
+        }
+        response.json(ApiErrorBody {
+            error: ApiErrorDetail {
+                code: self.code(),
+                message,
+            },
+        })
+    }
+}
+
+impl From<IdempotencyError> for ApiError {
+    fn from(e: IdempotencyError) -> Self {
+        match e {
+            IdempotencyError::InvalidKey(message) => ApiError::Validation(message),
+            IdempotencyError::KeyReused => ApiError::IdempotencyKeyReused,
+            IdempotencyError::InProgress(retry_after) => ApiError::RequestInProgress(retry_after),
+            IdempotencyError::Unexpected(e) => ApiError::Unexpected(e),
+        }
+    }
+}
+
+/// The body of every error response of the API.
+#[derive(Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiErrorDetail {
+    /// One of `invalid_request`, `unauthorized`, `missing_scope`, `not_found`, `conflict`,
+    /// `idempotency_key_reused`, `request_in_progress` and `internal_error`.
+    #[schema(example = "not_found")]
+    code: &'static str,
+    message: String,
+}
+
+/// Renders the errors of the `Json`, `Query` and `Path` extractors as `ApiError`s
+/// rather than as plain text.
+pub fn extractor_error(e: impl std::fmt::Display, _request: &HttpRequest) -> actix_web::Error {
+    ApiError::Validation(e.to_string()).into()
+}
+
+/// Unknown paths under `/api/v1`.
+pub async fn api_not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound("There is no such API endpoint."))
+}
@@ -0,0 +1,189 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{ApiPrincipal, ApiScope};
+use crate::domain::IssueStatus;
+use crate::idempotency::IdempotentTransaction;
+use crate::routes::api::{require_scope, ApiError, ApiErrorBody};
+use crate::routes::{
+    enqueue_delivery_tasks, get_issue_summaries, insert_news_letter_issue, IssueAction,
+    IssueTransition,
+};
+use actix_web::http::header::LOCATION;
+use actix_web::web::{Json, Path, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewIssueBody {
+    title: String,
+    text_content: String,
+    html_content: String,
+    #[serde(default)]
+    action: IssueAction,
+    /// When to send a scheduled issue, in RFC 3339.
+    send_at: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AcceptedIssue {
+    issue_id: Uuid,
+    /// `sending`, `draft` or `scheduled`.
+    #[schema(example = "sending")]
+    status: &'static str,
+    /// Where to follow the delivery progress of the issue.
+    status_url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueProgress {
+    issue_id: Uuid,
+    title: String,
+    /// One of `draft`, `scheduled`, `sending`, `sent` and `cancelled`.
+    #[schema(example = "sent")]
+    status: String,
+    send_at: Option<DateTime<Utc>>,
+    published_at: Option<DateTime<Utc>>,
+    delivery: DeliveryProgress,
+}
+
+/// Recipients by delivery state, as on the issue page of the dashboard.
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryProgress {
+    queued: i64,
+    retrying: i64,
+    delivered: i64,
+    failed: i64,
+    skipped: i64,
+}
+
+/// Creates a newsletter issue, like the newsletter form of the dashboard.
+///
+/// Delivery happens in the background, so the issue is only accepted here, and its progress
+/// is polled at `status_url`. The `Idempotency-Key` header plays the part of the hidden
+/// `idempotency_key` field of the form: a retried request gets the saved response back
+/// instead of publishing the issue twice, and reusing the key for another issue is refused.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "issues",
+    params(("Idempotency-Key" = String, Header, description = "Up to 49 characters, unique per request")),
+    request_body = NewIssueBody,
+    responses(
+        (status = 202, description = "The issue has been accepted", body = AcceptedIssue, headers(("Location" = String))),
+        (status = 400, description = "Invalid issue or idempotency key", body = ApiErrorBody),
+        (status = 409, description = "A request with the same idempotency key is still being processed", body = ApiErrorBody, headers(("Retry-After" = u64))),
+        (status = 422, description = "The idempotency key has been used for another request", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["issues:write"]))
+)]
+#[tracing::instrument(
+    name = "API: create a newsletter issue",
+    skip(body, transaction, request),
+    fields(user_id = %principal.user_id)
+)]
+pub async fn api_create_issue(
+    body: Json<NewIssueBody>,
+    principal: ReqData<ApiPrincipal>,
+    // Committed by the `idempotency` middleware, together with the response,
+    // and refused without an idempotency key.
+    transaction: Result<IdempotentTransaction, ApiError>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::IssuesWrite)?;
+    let mut transaction = transaction?;
+    let NewIssueBody {
+        title,
+        text_content,
+        html_content,
+        action,
+        send_at,
+    } = body.into_inner();
+    if action == IssueAction::Cancel {
+        return Err(ApiError::Validation(
+            "A new newsletter issue cannot be cancelled.".to_string(),
+        ));
+    }
+    let transition = IssueTransition::parse(action, send_at).map_err(ApiError::Validation)?;
+
+    let issue_id = insert_news_letter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        &transition,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    // Drafts and scheduled issues are enqueued later, see issue_scheduling_worker.rs
+    if transition.status == IssueStatus::Sending {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    let event = AuditEvent::new(
+        AuditAction::NewsletterIssueCreated,
+        Some(principal.user_id),
+        &request,
+    )
+    .target(issue_id)
+    .details(serde_json::json!({
+        "title": title,
+        "status": transition.status.as_str(),
+        "api_token_id": principal.token_id,
+    }));
+    record_audit_event(&mut **transaction, event).await?;
+
+    let status_url = format!("/api/v1/issues/{}", issue_id);
+    Ok(HttpResponse::Accepted()
+        .insert_header((LOCATION, status_url.as_str()))
+        .json(AcceptedIssue {
+            issue_id,
+            status: transition.status.as_str(),
+            status_url,
+        }))
+}
+
+/// Gets a newsletter issue with its delivery progress.
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{issue_id}",
+    tag = "issues",
+    params(("issue_id" = Uuid, Path)),
+    responses(
+        (status = 200, description = "The issue", body = IssueProgress),
+        (status = 404, description = "Unknown issue", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["issues:read"]))
+)]
+#[tracing::instrument(name = "API: get a newsletter issue", skip(pool), fields(user_id = %principal.user_id))]
+pub async fn api_get_issue(
+    issue_id: Path<Uuid>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::IssuesRead)?;
+    let issue = get_issue_summaries(&pool, Some(*issue_id))
+        .await?
+        .pop()
+        .ok_or(ApiError::NotFound(
+            "There is no newsletter issue with this id.",
+        ))?;
+    Ok(HttpResponse::Ok().json(IssueProgress {
+        issue_id: issue.newsletter_issue_id,
+        title: issue.title,
+        status: issue.status,
+        send_at: issue.send_at,
+        published_at: issue.published_at,
+        delivery: DeliveryProgress {
+            queued: issue.queued,
+            retrying: issue.retrying,
+            delivered: issue.delivered,
+            failed: issue.failed,
+            skipped: issue.skipped,
+        },
+    }))
+}
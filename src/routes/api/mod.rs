@@ -0,0 +1,24 @@
+/// # JSON API
+/// Versioned under `/api/v1`, for other services to integrate with.
+/// Requests are authenticated with the API tokens users create from `/admin/api-tokens`,
+/// see `reject_invalid_api_token`. The OpenAPI document is served at `/api/openapi.json`.
+///
+mod error;
+mod issues;
+mod openapi;
+mod subscribers;
+
+pub use error::*;
+pub use issues::*;
+pub use openapi::{api_docs, openapi_json, redoc_bundle, ApiDoc};
+pub use subscribers::*;
+
+use crate::authentication::{ApiPrincipal, ApiScope};
+
+fn require_scope(principal: &ApiPrincipal, scope: ApiScope) -> Result<(), ApiError> {
+    if principal.has_scope(scope) {
+        Ok(())
+    } else {
+        Err(ApiError::MissingScope(scope))
+    }
+}
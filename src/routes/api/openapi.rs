@@ -0,0 +1,117 @@
+use crate::routes::api::{
+    AcceptedIssue, ApiErrorBody, ApiErrorDetail, DeliveryProgress, IssueProgress, NewIssueBody,
+    NewSubscriberBody, Subscriber, SubscriberList,
+};
+use crate::routes::{FormData, IssueAction};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// The contract of the JSON API, generated from the `#[utoipa::path]` attributes of the handlers
+/// and the `ToSchema` derives of their bodies. A copy is committed as `openapi.json`,
+/// and `tests/api/openapi.rs` fails when it is out of date.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod",
+        description = "Newsletter subscribers and issues.",
+        license(name = "MIT OR Apache-2.0")
+    ),
+    paths(
+        crate::routes::subscribe,
+        super::subscribers::api_list_subscribers,
+        super::subscribers::api_create_subscriber,
+        super::subscribers::api_get_subscriber,
+        super::subscribers::api_delete_subscriber,
+        super::subscribers::api_confirm_subscriber,
+        super::issues::api_create_issue,
+        super::issues::api_get_issue,
+    ),
+    components(schemas(
+        AcceptedIssue,
+        ApiErrorBody,
+        ApiErrorDetail,
+        DeliveryProgress,
+        FormData,
+        IssueAction,
+        IssueProgress,
+        NewIssueBody,
+        NewSubscriberBody,
+        Subscriber,
+        SubscriberList,
+    )),
+    modifiers(&ApiTokenSecurity),
+    tags(
+        (name = "subscribers", description = "Newsletter subscribers."),
+        (name = "issues", description = "Newsletter issues and their delivery."),
+        (name = "public", description = "Endpoints which need no API token."),
+    )
+)]
+pub struct ApiDoc;
+
+/// Tokens are created from the "API tokens" page of the dashboard.
+struct ApiTokenSecurity;
+
+impl Modify for ApiTokenSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An API token created from the API tokens page of the dashboard.",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// GET `/api/openapi.json`
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+// Vendored with `just redoc`, rather than loaded from a CDN which could serve anything.
+const REDOC_BUNDLE: &str = "static/redoc.standalone.js";
+
+/// GET `/api/docs`, which renders `/api/openapi.json`.
+pub async fn api_docs() -> HttpResponse {
+    let html_body = r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>API documentation</title>
+</head>
+<body>
+    <redoc spec-url="/api/openapi.json"></redoc>
+    <script src="/api/docs/redoc.standalone.js"></script>
+</body>
+</html>
+
+        "#;
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body)
+}
+
+/// GET `/api/docs/redoc.standalone.js`, the Redoc bundle served from the app itself.
+pub async fn redoc_bundle() -> HttpResponse {
+    match tokio::fs::read(REDOC_BUNDLE).await {
+        Ok(bundle) => HttpResponse::Ok()
+            .content_type("text/javascript; charset=utf-8")
+            .insert_header(CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(86400),
+            ]))
+            .body(bundle),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to read the Redoc bundle {}, see `just redoc`", REDOC_BUNDLE);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{ApiPrincipal, ApiScope};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailTransport;
+use crate::routes::api::{require_scope, ApiError, ApiErrorBody};
+use crate::routes::{
+    confirm_subscriber, delete_tokens, generate_subscription_token, insert_subscriber,
+    send_confirmation_email, store_token,
+};
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::header::LOCATION;
+use actix_web::web::{Json, Path, Query, ReqData};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::DerefMut;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+const SUBSCRIPTION_STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+const SUBSCRIBER_NOT_FOUND: &str = "There is no subscriber with this id.";
+
+#[derive(Serialize, ToSchema)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    /// One of `pending_confirmation`, `confirmed` and `unsubscribed`.
+    #[schema(example = "confirmed")]
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberList {
+    subscribers: Vec<Subscriber>,
+    limit: i64,
+    offset: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParameters {
+    /// Only the subscribers in this status, one of `pending_confirmation`, `confirmed`
+    /// and `unsubscribed`.
+    status: Option<String>,
+    /// Part of the email address or of the name, case-insensitive.
+    q: Option<String>,
+    /// From 1 to 100, 50 by default.
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Lists the subscribers, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    params(ListParameters),
+    responses(
+        (status = 200, description = "A page of subscribers", body = SubscriberList),
+        (status = 400, description = "Invalid parameters", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["subscribers:read"]))
+)]
+#[tracing::instrument(name = "API: list subscribers", skip(parameters, pool), fields(user_id = %principal.user_id))]
+pub async fn api_list_subscribers(
+    parameters: Query<ListParameters>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::SubscribersRead)?;
+    let ListParameters {
+        status,
+        q,
+        limit,
+        offset,
+    } = parameters.into_inner();
+    if let Some(status) = &status {
+        if !SUBSCRIPTION_STATUSES.contains(&status.as_str()) {
+            return Err(ApiError::Validation(format!(
+                "status must be one of {}.",
+                SUBSCRIPTION_STATUSES.join(", ")
+            )));
+        }
+    }
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "limit must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let offset = offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(ApiError::Validation(
+            "offset must not be negative.".to_string(),
+        ));
+    }
+    let pattern = q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(|q| format!("%{}%", escape_like(q)));
+
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+          AND ($2::text IS NULL OR email ILIKE $2 OR name ILIKE $2)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $3 OFFSET $4
+        "#,
+        status,
+        pattern,
+        limit,
+        offset,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to list the subscribers.")?;
+    Ok(HttpResponse::Ok().json(SubscriberList {
+        subscribers,
+        limit,
+        offset,
+    }))
+}
+
+/// Gets one subscriber.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    responses(
+        (status = 200, description = "The subscriber", body = Subscriber),
+        (status = 404, description = "Unknown subscriber", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["subscribers:read"]))
+)]
+#[tracing::instrument(name = "API: get a subscriber", skip(pool), fields(user_id = %principal.user_id))]
+pub async fn api_get_subscriber(
+    subscriber_id: Path<Uuid>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::SubscribersRead)?;
+    let subscriber = get_subscriber(*subscriber_id, &pool)
+        .await?
+        .ok_or(ApiError::NotFound(SUBSCRIBER_NOT_FOUND))?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewSubscriberBody {
+    email: String,
+    name: String,
+}
+
+/// Subscribes someone the same way as the public form.
+///
+/// The subscriber is pending until they follow the link of the confirmation email.
+/// Unlike the form, an address which is already known is refused with a conflict.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    request_body = NewSubscriberBody,
+    responses(
+        (status = 201, description = "The subscriber has been created", body = Subscriber, headers(("Location" = String))),
+        (status = 400, description = "Invalid email address or name", body = ApiErrorBody),
+        (status = 409, description = "The email address is already known", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: create a subscriber",
+    skip(body, pool, email_client, base_url, request),
+    fields(user_id = %principal.user_id)
+)]
+pub async fn api_create_subscriber(
+    body: Json<NewSubscriberBody>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::SubscribersWrite)?;
+    let NewSubscriberBody { email, name } = body.into_inner();
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(email).map_err(ApiError::Validation)?,
+        name: SubscriberName::parse(name).map_err(ApiError::Validation)?,
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    let subscriber_id = match insert_subscriber(&mut transaction, &new_subscriber).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e) if is_unique_violation(&e) => {
+            return Err(ApiError::Conflict(
+                "A subscriber with this email address already exists.",
+            ))
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to insert a new subscriber in the database.")
+                .into())
+        }
+    };
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store a confirmation token for a new subscriber.")?;
+    let event = subscriber_event(
+        AuditAction::SubscriberCreated,
+        subscriber_id,
+        &principal,
+        &request,
+    );
+    record_audit_event(transaction.deref_mut(), event).await?;
+    transaction.commit().await.context(
+        "Failed to commit transaction for storing a new subscriber & confirmation token.",
+    )?;
+
+    send_confirmation_email(
+        email_client.as_ref(),
+        new_subscriber,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to send an confirmation email for a new subscriber.")?;
+
+    let subscriber = get_subscriber(subscriber_id, &pool)
+        .await?
+        .context("The new subscriber has disappeared.")?;
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/subscribers/{}", subscriber_id)))
+        .json(subscriber))
+}
+
+/// Confirms a subscriber who has confirmed their address some other way.
+///
+/// Confirming a confirmed subscriber does nothing.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers/{subscriber_id}/confirm",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    responses(
+        (status = 200, description = "The confirmed subscriber", body = Subscriber),
+        (status = 404, description = "Unknown subscriber", body = ApiErrorBody),
+        (status = 409, description = "The subscriber has unsubscribed", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: confirm a subscriber",
+    skip(pool, request),
+    fields(user_id = %principal.user_id)
+)]
+pub async fn api_confirm_subscriber(
+    subscriber_id: Path<Uuid>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::SubscribersWrite)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(subscriber_id, &pool)
+        .await?
+        .ok_or(ApiError::NotFound(SUBSCRIBER_NOT_FOUND))?;
+    match subscriber.status.as_str() {
+        "confirmed" => return Ok(HttpResponse::Ok().json(subscriber)),
+        // They have asked not to receive the newsletter anymore.
+        "unsubscribed" => {
+            return Err(ApiError::Conflict(
+                "An unsubscribed subscriber cannot be confirmed.",
+            ))
+        }
+        _ => {}
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    // It may have been confirmed or unsubscribed since it was read.
+    if confirm_subscriber(transaction.deref_mut(), subscriber_id)
+        .await
+        .context("Failed to confirm the subscriber.")?
+    {
+        let event = subscriber_event(
+            AuditAction::SubscriberConfirmed,
+            subscriber_id,
+            &principal,
+            &request,
+        );
+        record_audit_event(transaction.deref_mut(), event).await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the confirmation of the subscriber.")?;
+    let subscriber = get_subscriber(subscriber_id, &pool)
+        .await?
+        .ok_or(ApiError::NotFound(SUBSCRIBER_NOT_FOUND))?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Forgets a subscriber entirely, e.g. for erasure requests.
+///
+/// Pending deliveries to the address are dropped, and it is erased from the delivery history.
+/// Use the unsubscribe link to only stop the newsletter.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path)),
+    responses(
+        (status = 204, description = "The subscriber has been deleted"),
+        (status = 404, description = "Unknown subscriber", body = ApiErrorBody),
+    ),
+    security(("api_token" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: delete a subscriber",
+    skip(pool, request),
+    fields(user_id = %principal.user_id)
+)]
+pub async fn api_delete_subscriber(
+    subscriber_id: Path<Uuid>,
+    principal: ReqData<ApiPrincipal>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&principal, ApiScope::SubscribersWrite)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to remove the confirmation tokens of the subscriber.")?;
+    let email = sqlx::query_scalar!(
+        "DELETE FROM subscriptions WHERE id = $1 RETURNING email",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber.")?
+    .ok_or(ApiError::NotFound(SUBSCRIBER_NOT_FOUND))?;
+    erase_deliveries(&mut transaction, &email)
+        .await
+        .context("Failed to erase the deliveries to the subscriber.")?;
+    let event = subscriber_event(
+        AuditAction::SubscriberDeleted,
+        subscriber_id,
+        &principal,
+        &request,
+    );
+    record_audit_event(transaction.deref_mut(), event).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the deletion of the subscriber.")?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Drops the pending deliveries to `email` and its failures, and replaces it in the delivery log,
+/// whose rows still count towards the delivery summary of their issue.
+#[tracing::instrument(skip_all)]
+async fn erase_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_failures WHERE subscriber_email = $1",
+        email
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_log
+        SET subscriber_email = 'erased-' || gen_random_uuid(), error_message = NULL
+        WHERE subscriber_email = $1
+        "#,
+        email
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+/// `action` done to `subscriber_id` with the API token of `principal`.
+fn subscriber_event(
+    action: AuditAction,
+    subscriber_id: Uuid,
+    principal: &ApiPrincipal,
+    request: &HttpRequest,
+) -> AuditEvent {
+    AuditEvent::new(action, Some(principal.user_id), request)
+        .target(subscriber_id)
+        .details(serde_json::json!({ "api_token_id": principal.token_id }))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch the subscriber.")
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_unique_violation())
+}
+
+// `%` and `_` in the search are matched literally.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
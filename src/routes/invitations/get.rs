@@ -0,0 +1,60 @@
+use crate::utils::{escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    token: Option<String>,
+}
+
+/// GET `/invitations/accept?token=...`, the page linked from the invitation email.
+/// The token is only checked once the form is submitted.
+pub async fn accept_invitation_form(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    let token = match query.into_inner().token {
+        Some(token) => escape_html(&token),
+        None => return see_other("/login"),
+    };
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Accept invitation</title>
+</head>
+<body>
+  {message}
+   <form action="/invitations/accept" method="post">
+        <input type="hidden" name="token" value="{token}">
+        <label>Username
+            <input type="text" name="username" placeholder="Choose a username">
+        </label>
+        <br>
+        <label>Password
+            <input type="password" name="password" placeholder="Choose a password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" name="password_check" placeholder="Type the password again">
+        </label>
+        <button type="submit">Create account</button>
+   </form>
+</body>
+</html>
+
+        "#
+    );
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body)
+}
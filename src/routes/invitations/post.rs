@@ -0,0 +1,103 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{
+    accept_invitation as accept, check_password_strength, hash_password, AcceptOutcome,
+    BreachedPasswords, Password,
+};
+use crate::configuration::PasswordHashingSettings;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+
+const INVALID_INVITATION_MESSAGE: &str = "The invitation link is invalid or has expired.";
+
+#[derive(Deserialize)]
+pub struct FormData {
+    token: String,
+    username: String,
+    password: SecretString,
+    password_check: SecretString,
+}
+
+#[tracing::instrument(name = "Accept an invitation", skip(form, pool, hashing, breached_passwords, request), fields(username = %form.username, user_id = tracing::field::Empty))]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    breached_passwords: web::Data<BreachedPasswords>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        token,
+        username,
+        password,
+        password_check,
+    } = form.into_inner();
+    // Tokens we issue are alphanumeric, which also keeps the redirect below well-formed.
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        FlashMessage::error(INVALID_INVITATION_MESSAGE).send();
+        return Ok(see_other("/login"));
+    }
+    let invitation_page = format!("/invitations/accept?token={}", token);
+
+    let username = username.trim();
+    if username.is_empty() {
+        FlashMessage::error("Please choose a username.").send();
+        return Ok(see_other(&invitation_page));
+    }
+    let password = match Password::parse(password.expose_secret()) {
+        Ok(password) => password,
+        Err(_) => {
+            FlashMessage::error("The password must be between 12 and 128 characters long.").send();
+            return Ok(see_other(&invitation_page));
+        }
+    };
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&invitation_page));
+    }
+    if let Err(e) = check_password_strength(&password, username, &breached_passwords) {
+        FlashMessage::error(e.to_string()).send();
+        return Ok(see_other(&invitation_page));
+    }
+
+    let password_hash = hash_password(password, &hashing).await.map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    match accept(&token, username, &password_hash, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        AcceptOutcome::Accepted { user_id } => {
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let event = AuditEvent::new(AuditAction::InvitationAccepted, Some(user_id), &request)
+                .target(user_id);
+            record_audit_event(transaction.deref_mut(), event)
+                .await
+                .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the accepted invitation.")
+                .map_err(e500)?;
+            FlashMessage::info("Your account has been created. You can now log in.").send();
+            Ok(see_other("/login"))
+        }
+        AcceptOutcome::InvalidToken => {
+            FlashMessage::error(INVALID_INVITATION_MESSAGE).send();
+            Ok(see_other("/login"))
+        }
+        AcceptOutcome::UsernameTaken => {
+            FlashMessage::error("This username is already taken.").send();
+            Ok(see_other(&invitation_page))
+        }
+    }
+}
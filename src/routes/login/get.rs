@@ -33,6 +33,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
         </label>
         <button type="submit">Login</button>
    </form>
+   <p><a href="/login/reset">Forgot your password?</a></p>
 </body>
 </html>
 
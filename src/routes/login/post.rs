@@ -1,16 +1,24 @@
 use actix_web::error::InternalError;
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{LOCATION, USER_AGENT};
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::SecretString;
 use serde::Deserialize;
 use sqlx::PgPool;
 use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+use uuid::Uuid;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{
+    issue_csrf_token, record_session, two_factor_enabled, validate_credentials, AuthError,
+    Credentials, LoginRateLimiter,
+};
+use crate::configuration::PasswordHashingSettings;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
+use crate::utils::client_ip;
 
 #[derive(Deserialize)]
 pub struct FormData {
@@ -22,6 +30,8 @@ pub struct FormData {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, please try again in {} seconds.", .0.as_millis().div_ceil(1000))]
+    LockedOut(Duration),
     #[error("Login process failed at form submission")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -38,50 +48,126 @@ impl ResponseError for LoginError {
     }
 }
 
-#[tracing::instrument(skip(form, pool, session), fields(username = tracing::field::Empty, user_id = tracing::field::Empty))]
+#[tracing::instrument(skip(form, request, pool, hashing, session, rate_limiter), fields(username = tracing::field::Empty, user_id = tracing::field::Empty))]
 pub async fn login(
     form: web::Form<FormData>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
     session: TypedSession,
+    rate_limiter: web::Data<LoginRateLimiter>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &pool).await {
+    let username = credentials.username.clone();
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    let ip = client_ip(&request);
+    // Locked out logins are refused before spending an argon2 verification on them.
+    let lockout = rate_limiter
+        .lockout(&username, ip)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+    if let Some(lockout) = lockout {
+        record_failed_login(&username, "locked_out", &request, &pool)
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+        return Err(login_redirect(LoginError::LockedOut(lockout)));
+    }
+    match validate_credentials(credentials, &hashing, &pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             // セッション固定攻撃（Session Fixation Attack）対策.
             // 攻撃者が事前にセッショントークンをユーザーのブラウザに固定（または「植え付け」）を行ったとしても
             // ログインに成功した時点でSessionを更新してしまえば良い.
             session.renew();
-            // The values you pass in, in this case `&user_id: Uuid`,
-            // MUST be serializable - actix-session converts them into JSON behind the scenes.
-            // So uuid crate require serde feature for serialization.
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            // Users with two-factor authentication only get a pending session until
+            // they pass the second step, see `verify_two_factor`.
+            let two_factor = two_factor_enabled(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            if two_factor {
+                session
+                    .insert_pending_user_id(user_id)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/2fa"))
+                    .finish());
+            }
+            // The failures are only forgotten once the whole login has succeeded,
+            // see `verify_two_factor` for the users with two-factor authentication.
+            rate_limiter
+                .record_success(&username)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_user_session(&session, user_id, &request, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(e) => LoginError::AuthError(e),
+                AuthError::InvalidCredentials(e) => {
+                    record_failed_login(&username, "invalid_credentials", &request, &pool)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    let lockout = rate_limiter
+                        .record_failure(&username, ip)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    match lockout {
+                        Some(lockout) => LoginError::LockedOut(lockout),
+                        None => LoginError::AuthError(e),
+                    }
+                }
                 AuthError::UnexpectedError(e) => LoginError::UnexpectedError(e),
             };
-            // Error message sent by setting FlashMessageFramework
-            FlashMessage::error(e.to_string()).send();
-
-            let response = HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/login"))
-                .finish();
-            Err(InternalError::from_response(e, response))
+            Err(login_redirect(e))
         }
     }
 }
 
+/// Logs `user_id` in, recording the session so that it can be listed and revoked
+/// from `/admin/sessions`.
+pub(super) async fn start_user_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let ip = client_ip(request);
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let session_id = record_session(user_id, ip, user_agent, pool).await?;
+    let event = AuditEvent::new(AuditAction::LoginSucceeded, Some(user_id), request)
+        .details(serde_json::json!({ "session_id": session_id, "user_agent": user_agent }));
+    record_audit_event(pool, event).await?;
+    session.insert_session_id(session_id)?;
+    // The values you pass in, in this case `&user_id: Uuid`,
+    // MUST be serializable - actix-session converts them into JSON behind the scenes.
+    // So uuid crate require serde feature for serialization.
+    session.insert_user_id(user_id)?;
+    issue_csrf_token(session)?;
+    Ok(())
+}
+
+/// The actor is left unknown, the username may not even exist.
+async fn record_failed_login(
+    username: &str,
+    reason: &str,
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let event = AuditEvent::new(AuditAction::LoginFailed, None, request)
+        .details(serde_json::json!({ "username": username, "reason": reason }));
+    record_audit_event(pool, event).await
+}
+
 // Redirect to login page with an error message.
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
@@ -0,0 +1,122 @@
+use super::post::start_user_session;
+use crate::authentication::{verify_second_factor, LoginRateLimiter};
+use crate::routes::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// GET `/login/2fa`, the second login step for users with two-factor authentication.
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Two-factor authentication</title>
+</head>
+<body>
+  {message}
+   <form action="/login/2fa" method="post">
+        <label>Authentication code
+            <input type="text" name="code" inputmode="numeric" autocomplete="one-time-code" placeholder="Enter the 6-digit code or a recovery code">
+        </label>
+        <button type="submit">Verify</button>
+   </form>
+</body>
+</html>
+
+        "#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body))
+}
+
+#[derive(Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(name = "Verify two-factor login", skip(form, request, pool, session, rate_limiter), fields(user_id = tracing::field::Empty))]
+pub async fn verify_two_factor(
+    form: web::Form<FormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    rate_limiter: web::Data<LoginRateLimiter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_pending_user_id().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    // Wrong codes are counted per user rather than per session,
+    // which a new password check would start afresh.
+    if let Some(lockout) = rate_limiter
+        .two_factor_lockout(user_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(drop_pending_login(&session, lockout));
+    }
+
+    if verify_second_factor(user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    {
+        rate_limiter
+            .record_two_factor_success(user_id)
+            .await
+            .map_err(e500)?;
+        let username = get_username(user_id, &pool).await.map_err(e500)?;
+        rate_limiter.record_success(&username).await.map_err(e500)?;
+        session.renew();
+        session.remove_pending_user_id();
+        start_user_session(&session, user_id, &request, &pool)
+            .await
+            .map_err(e500)?;
+        session.insert_two_factor_verified().map_err(e500)?;
+        return Ok(see_other("/admin/dashboard"));
+    }
+
+    if let Some(lockout) = rate_limiter
+        .record_two_factor_failure(user_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(drop_pending_login(&session, lockout));
+    }
+    FlashMessage::error("The authentication code is invalid.").send();
+    Ok(see_other("/login/2fa"))
+}
+
+/// The password has to be entered again once the lockout is over.
+fn drop_pending_login(session: &TypedSession, lockout: Duration) -> HttpResponse {
+    session.log_out();
+    FlashMessage::error(too_many_codes_message(lockout)).send();
+    see_other("/login")
+}
+
+/// Shown while the two-factor codes of a user are locked out, see `LoginRateLimiter`.
+pub fn too_many_codes_message(lockout: Duration) -> String {
+    format!(
+        "Too many invalid codes, please try again in {} seconds.",
+        lockout.as_millis().div_ceil(1000)
+    )
+}
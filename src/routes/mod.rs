@@ -1,14 +1,22 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
 
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use password_reset::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
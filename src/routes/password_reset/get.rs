@@ -0,0 +1,91 @@
+use crate::utils::{escape_html, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use std::fmt::Write;
+
+/// GET `/login/reset`, where an admin who forgot their password asks for a reset link.
+pub async fn password_reset_request_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Forgot password</title>
+</head>
+<body>
+  {message}
+   <p>Enter your username, and we will email you a link to reset your password.</p>
+   <form action="/login/reset" method="post">
+        <label>Username
+            <input type="text" name="username" placeholder="Enter Username">
+        </label>
+        <button type="submit">Send reset link</button>
+   </form>
+    <p><a href="/login">&lt;- Back</a></p>
+</body>
+</html>
+
+        "#
+    );
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body)
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    token: Option<String>,
+}
+
+/// GET `/login/reset/confirm?token=...`, the page linked from the password reset email.
+/// The token is only checked once the new password is submitted.
+pub async fn password_reset_form(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+) -> HttpResponse {
+    let token = match query.into_inner().token {
+        Some(token) => escape_html(&token),
+        None => return see_other("/login/reset"),
+    };
+    let mut message = String::new();
+    for m in flash_messages.iter() {
+        writeln!(message, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_body = format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Reset password</title>
+</head>
+<body>
+  {message}
+   <form action="/login/reset/confirm" method="post">
+        <input type="hidden" name="token" value="{token}">
+        <label>New password
+            <input type="password" name="new_password" placeholder="Enter new password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" name="new_password_check" placeholder="Type the new password again">
+        </label>
+        <button type="submit">Reset password</button>
+   </form>
+</body>
+</html>
+
+        "#
+    );
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_body)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{password_reset_form, password_reset_request_form};
+pub use post::{request_password_reset, reset_password, RESET_REQUESTED_MESSAGE};
@@ -0,0 +1,192 @@
+use crate::audit::{record_audit_event, AuditAction, AuditEvent};
+use crate::authentication::{
+    change_password_in_db, check_password_strength, consume_reset_token, get_reset_token_username,
+    hash_password, issue_reset_token, revoke_other_sessions, BreachedPasswords, Password,
+    ResetToken,
+};
+use crate::configuration::PasswordHashingSettings;
+use crate::email_client::EmailTransport;
+use crate::startup::{ApplicationBaseUrl, BackgroundTasks, PasswordResetTokenTtl};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::ops::DerefMut;
+
+// The same message is shown whether the username exists or not.
+pub const RESET_REQUESTED_MESSAGE: &str =
+    "If the account exists, a link to reset its password has been sent to its email address.";
+const INVALID_TOKEN_MESSAGE: &str = "The password reset link is invalid or has expired.";
+
+#[derive(Deserialize)]
+pub struct RequestFormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, pool, email_client, base_url, token_ttl, background_tasks),
+    fields(username = %form.username)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailTransport>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_ttl: web::Data<PasswordResetTokenTtl>,
+    background_tasks: web::Data<BackgroundTasks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let reset_token = issue_reset_token(&form.username, token_ttl.0, &pool)
+        .await
+        .map_err(e500)?;
+    if let Some(reset_token) = reset_token {
+        // The email is sent in the background: waiting for it only when the user exists
+        // would tell apart known and unknown usernames by the response time.
+        // It is tracked, so that shutdown waits for it to be sent.
+        let email_client = email_client.into_inner();
+        let base_url = base_url.0.clone();
+        background_tasks.spawn(async move {
+            if let Err(e) =
+                send_password_reset_email(email_client.as_ref(), &reset_token, &base_url).await
+            {
+                tracing::error!(
+                    error.message = %e,
+                    "Failed to send the password reset email."
+                );
+            }
+        });
+    }
+    FlashMessage::info(RESET_REQUESTED_MESSAGE).send();
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_password_reset_email(
+    email_client: &dyn EmailTransport,
+    reset_token: &ResetToken,
+    base_url: &str,
+) -> Result<(), crate::email_client::SendError> {
+    let reset_link = format!(
+        "{}/login/reset/confirm?token={}",
+        base_url, reset_token.token
+    );
+    let plain_body = format!(
+        "Someone asked to reset the password of your account.\n\
+        Visit {} to choose a new password. If it was not you, just ignore this email.",
+        reset_link
+    );
+    let html_body = format!(
+        "Someone asked to reset the password of your account.<br />\
+        Click <a href=\"{}\">here</a> to choose a new password. If it was not you, just ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(
+            &reset_token.email,
+            "Reset your password",
+            &html_body,
+            &plain_body,
+        )
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct ResetFormData {
+    token: String,
+    new_password: SecretString,
+    new_password_check: SecretString,
+}
+
+#[tracing::instrument(
+    name = "Reset password",
+    skip(form, pool, hashing, breached_passwords, request)
+)]
+pub async fn reset_password(
+    form: web::Form<ResetFormData>,
+    pool: web::Data<PgPool>,
+    hashing: web::Data<PasswordHashingSettings>,
+    breached_passwords: web::Data<BreachedPasswords>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.into_inner();
+    // Tokens we issue are alphanumeric, which also keeps the redirect below well-formed.
+    if token.is_empty() || !token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        FlashMessage::error(INVALID_TOKEN_MESSAGE).send();
+        return Ok(see_other("/login/reset"));
+    }
+    let reset_page = format!("/login/reset/confirm?token={}", token);
+
+    let new_password = match Password::parse(new_password.expose_secret()) {
+        Ok(password) => password,
+        Err(_) => {
+            FlashMessage::error("The password must be between 12 and 128 characters long.").send();
+            return Ok(see_other(&reset_page));
+        }
+    };
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error(
+            "You entered two different new passwords - the field values must match.",
+        )
+        .send();
+        return Ok(see_other(&reset_page));
+    }
+
+    let username = match get_reset_token_username(&token, &pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(username) => username,
+        None => {
+            FlashMessage::error(INVALID_TOKEN_MESSAGE).send();
+            return Ok(see_other("/login/reset"));
+        }
+    };
+    if let Err(e) = check_password_strength(&new_password, &username, &breached_passwords) {
+        FlashMessage::error(e.to_string()).send();
+        return Ok(see_other(&reset_page));
+    }
+
+    let password_hash = hash_password(new_password, &hashing).await.map_err(e500)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire Postgres connection from the pool.")
+        .map_err(e500)?;
+    let user_id = match consume_reset_token(&token, &mut transaction)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error(INVALID_TOKEN_MESSAGE).send();
+            return Ok(see_other("/login/reset"));
+        }
+    };
+    change_password_in_db(user_id, &password_hash, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    // Whoever knew the old password must not stay logged in.
+    let n_revoked = revoke_other_sessions(user_id, None, transaction.deref_mut())
+        .await
+        .map_err(e500)?;
+    let event = AuditEvent::new(AuditAction::PasswordReset, Some(user_id), &request)
+        .target(user_id)
+        .details(serde_json::json!({ "revoked_sessions": n_revoked }));
+    record_audit_event(transaction.deref_mut(), event)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset.")
+        .map_err(e500)?;
+    FlashMessage::info("Your password has been reset. You can now log in with it.").send();
+    Ok(see_other("/login"))
+}
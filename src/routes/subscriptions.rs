@@ -1,11 +1,11 @@
 use std::error::Error;
 use std::fmt::{Debug, Formatter};
+use std::ops::DerefMut;
 
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
-use log::error;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::Deserialize;
@@ -13,7 +13,7 @@ use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailTransport, SendError};
 use crate::startup::ApplicationBaseUrl;
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -26,12 +26,27 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct FormData {
     pub email: String,
     pub name: String,
 }
 
+/// The public subscription form.
+///
+/// A confirmation email is sent to the address, and sent again if the address is still pending
+/// or has unsubscribed. Nothing is sent to an address which is already confirmed, but the
+/// response is the same, so as not to tell who is subscribed.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "public",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The confirmation email has been sent"),
+        (status = 400, description = "Invalid email address or name", body = String, content_type = "text/plain"),
+    )
+)]
 #[tracing::instrument(
 name = "Adding a new subscriber",
 skip(form, pool, email_client, base_url),
@@ -43,7 +58,7 @@ subscriber_name = % form.name
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailTransport>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
     use crate::routes::SubscribeError::*;
@@ -53,9 +68,32 @@ pub async fn subscribe(
         .begin()
         .await
         .context("Failed to acquire Postgres connection from the pool.")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let existing_subscriber = get_existing_subscriber(&mut transaction, &new_subscriber)
         .await
-        .context("Failed to insert a new subscriber in the database.")?;
+        .context("Failed to look up an existing subscriber.")?;
+    let subscriber_id = match existing_subscriber {
+        Some((_, status)) if status == "confirmed" => {
+            tracing::info!("The subscriber is already confirmed.");
+            return Ok(HttpResponse::Ok().finish());
+        }
+        // Subscribing again with a pending address re-sends the confirmation email,
+        // and only the newest link is accepted afterwards.
+        // An unsubscribed address goes back to pending until it is confirmed again.
+        Some((subscriber_id, status)) => {
+            if status != "pending_confirmation" {
+                mark_subscriber_as_pending(&mut transaction, subscriber_id)
+                    .await
+                    .context("Failed to mark the subscriber as pending again.")?;
+            }
+            delete_tokens(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to remove previous confirmation tokens.")?;
+            subscriber_id
+        }
+        None => insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert a new subscriber in the database.")?,
+    };
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
@@ -66,7 +104,7 @@ pub async fn subscribe(
     )?;
 
     send_confirmation_email(
-        &email_client,
+        email_client.as_ref(),
         new_subscriber,
         &base_url.0,
         &subscription_token,
@@ -76,6 +114,45 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// The id and status of the subscriber with the email address of `new_subscriber`, if any.
+/// The row is locked, so that concurrent submissions of the form do not both act on it.
+#[tracing::instrument(
+    name = "Looking up a subscriber by email",
+    skip(new_subscriber, transaction)
+)]
+pub async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let q = sqlx::query!(
+        r#"
+    SELECT id, status FROM subscriptions
+    WHERE email = $1
+    FOR UPDATE
+        "#,
+        new_subscriber.email.as_ref(),
+    );
+    let row = q.fetch_optional(transaction.deref_mut()).await?;
+    Ok(row.map(|r| (r.id, r.status)))
+}
+
+#[tracing::instrument(name = "Mark a subscriber as pending again", skip(transaction))]
+pub async fn mark_subscriber_as_pending(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let q = sqlx::query!(
+        r#"
+    UPDATE subscriptions
+    SET status = 'pending_confirmation'
+    WHERE id = $1
+        "#,
+        subscriber_id
+    );
+    transaction.execute(q).await?;
+    Ok(())
+}
+
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -85,15 +162,18 @@ pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    // Issued once per subscriber, used by `List-Unsubscribe` links in every newsletter issue.
+    let unsubscribe_token = generate_subscription_token();
     let q = sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+    VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
             "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        unsubscribe_token,
     );
     transaction.execute(q).await?;
     Ok(subscriber_id)
@@ -110,8 +190,8 @@ pub async fn store_token(
 ) -> Result<(), StoreTokenError> {
     let q = sqlx::query!(
         r#"
-    INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-    VALUES ($1, $2)
+    INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+    VALUES ($1, $2, now())
         "#,
         subscription_token,
         subscriber_id
@@ -120,6 +200,22 @@ pub async fn store_token(
     Ok(())
 }
 
+#[tracing::instrument(name = "Delete subscription tokens of a subscriber", skip(transaction))]
+pub async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let q = sqlx::query!(
+        r#"
+    DELETE FROM subscription_tokens
+    WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    );
+    transaction.execute(q).await?;
+    Ok(())
+}
+
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Display for StoreTokenError {
@@ -184,11 +280,11 @@ impl ResponseError for SubscribeError {
     skip(email_client, new_subscriber)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    email_client: &dyn EmailTransport,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
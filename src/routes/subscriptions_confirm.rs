@@ -1,62 +1,128 @@
 use actix_web::web::Query;
 use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 use uuid::Uuid;
 
+use crate::startup::SubscriptionTokenTtl;
+
 #[derive(Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, token_ttl)
+)]
+pub async fn confirm(
+    parameters: Query<Parameters>,
+    pool: web::Data<PgPool>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+) -> HttpResponse {
     // If the Query extraction fails,
     // this function returns automatically a 400 Bad Request error.
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
+    let token = match get_subscription_token(&pool, &parameters.subscription_token).await {
+        Ok(token) => token,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
+    match token {
         None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_ok() {
-                HttpResponse::Ok().finish()
-            } else {
-                HttpResponse::InternalServerError().finish()
-            }
-        }
+        Some(token) if token.is_expired(token_ttl.0) => HttpResponse::Gone().body(
+            "This confirmation link has expired. \
+            Please subscribe again to receive a new one.",
+        ),
+        Some(_) => match consume_subscription_token(&pool, &parameters.subscription_token).await {
+            Ok(true) => HttpResponse::Ok().finish(),
+            // The subscriber has unsubscribed since the link was sent.
+            Ok(false) => HttpResponse::Gone().body(
+                "This confirmation link is no longer valid. \
+                Please subscribe again to receive a new one.",
+            ),
+            Err(_) => HttpResponse::InternalServerError().finish(),
+        },
     }
 }
 
-#[tracing::instrument(name = "Mark a subscriber as confirmed", skip(pool, subscriber_id))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+/// Deletes the token, so that the link only works once, and confirms its subscriber
+/// if they are still pending. Returns `false` if they have unsubscribed in the meantime.
+#[tracing::instrument(name = "Consume a subscription token", skip_all)]
+async fn consume_subscription_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = sqlx::query_scalar!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscription_token = $1
+        RETURNING subscriber_id
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(subscriber_id) = subscriber_id else {
+        // Consumed by a concurrent request.
+        return Ok(true);
+    };
+    confirm_subscriber(&mut *transaction, subscriber_id).await?;
+    let status = sqlx::query_scalar!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(status == "confirmed")
+}
+
+/// Only pending subscribers are confirmed: those who have unsubscribed stay unsubscribed.
+/// Returns whether the subscriber has been confirmed.
+#[tracing::instrument(name = "Mark a subscriber as confirmed", skip(executor))]
+pub async fn confirm_subscriber(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
     let update_query = sqlx::query!(
         r#"
         UPDATE subscriptions
         SET status = 'confirmed'
-        WHERE id = $1
+        WHERE id = $1 AND status = 'pending_confirmation'
         "#,
         subscriber_id,
     );
-    update_query.execute(pool).await.map_err(|e| {
+    let result = update_query.execute(executor).await.map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+    Ok(result.rows_affected() > 0)
+}
+
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SubscriptionToken {
+    pub fn is_expired(&self, ttl: chrono::Duration) -> bool {
+        self.created_at + ttl < Utc::now()
+    }
 }
 
 #[tracing::instrument(
     name = "Retrieving subscriber_id from subscription_token",
     skip(pool, subscription_token)
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let select_query = sqlx::query!(
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let select_query = sqlx::query_as!(
+        SubscriptionToken,
         r#"
-    SELECT subscriber_id FROM subscription_tokens
+    SELECT subscriber_id, created_at FROM subscription_tokens
     WHERE subscription_token = $1
     "#,
         subscription_token
@@ -66,5 +132,5 @@ pub async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result)
 }
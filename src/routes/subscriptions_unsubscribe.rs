@@ -0,0 +1,124 @@
+use actix_web::http::header::ContentType;
+use actix_web::web::Query;
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct UnsubscribeParameters {
+    unsubscribe_token: String,
+}
+
+pub fn unsubscribe_link(base_url: &str, unsubscribe_token: &str) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        base_url, unsubscribe_token
+    )
+}
+
+/// # GET `/subscriptions/unsubscribe`
+/// Mail clients and link scanners prefetch GET links, so this only renders a confirmation form.
+/// The subscription is removed by the POST below (RFC 8058).
+#[tracing::instrument(name = "Show the unsubscribe form", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let token = &parameters.unsubscribe_token;
+    match get_subscriber_id_from_unsubscribe_token(&pool, token).await {
+        Err(_) => HttpResponse::InternalServerError().finish(),
+        Ok(None) => HttpResponse::Unauthorized().finish(),
+        Ok(Some(_)) => {
+            let html_body = format!(
+                r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+ <meta http-equiv="content-type" content="text/html">
+<title>Unsubscribe</title>
+</head>
+<body>
+   <p>Do you want to stop receiving our newsletter?</p>
+   <form action="/subscriptions/unsubscribe?unsubscribe_token={token}" method="post">
+        <button type="submit">Unsubscribe</button>
+   </form>
+</body>
+</html>
+"#
+            );
+            HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(html_body)
+        }
+    }
+}
+
+/// # POST `/subscriptions/unsubscribe`
+/// One-click unsubscribe endpoint advertised in the `List-Unsubscribe` header.
+/// Mailbox providers send `List-Unsubscribe=One-Click` as the form body, which we don't need to read:
+/// the token in the query string identifies the subscriber.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let id = match get_subscriber_id_from_unsubscribe_token(&pool, &parameters.unsubscribe_token)
+        .await
+    {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match id {
+        None => HttpResponse::Unauthorized().finish(),
+        Some(subscriber_id) => {
+            if unsubscribe_subscriber(&pool, subscriber_id).await.is_ok() {
+                HttpResponse::Ok()
+                    .content_type(ContentType::html())
+                    .body("<p>You have been unsubscribed.</p>")
+            } else {
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+#[tracing::instrument(name = "Mark a subscriber as unsubscribed", skip(pool, subscriber_id))]
+pub async fn unsubscribe_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    let update_query = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    );
+    update_query.execute(pool).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Retrieving subscriber_id from unsubscribe_token",
+    skip(pool, unsubscribe_token)
+)]
+pub async fn get_subscriber_id_from_unsubscribe_token(
+    pool: &PgPool,
+    unsubscribe_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let select_query = sqlx::query!(
+        r#"
+    SELECT id FROM subscriptions
+    WHERE unsubscribe_token = $1
+    "#,
+        unsubscribe_token
+    );
+
+    let result = select_query.fetch_optional(pool).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| r.id))
+}
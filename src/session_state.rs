@@ -1,13 +1,25 @@
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::cookie::time::Duration;
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
 use std::future::{ready, Ready};
 use uuid::Uuid;
 
+/// Sessions are forgotten by the session store once they have not been used for this long.
+pub const SESSION_TTL: Duration = Duration::days(1);
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    // The row of the session in `user_sessions`, see `record_session`.
+    const SESSION_ID_KEY: &'static str = "session_id";
+    // Set after the password check for users with two-factor authentication,
+    // until the second login step is passed.
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const TWO_FACTOR_VERIFIED_KEY: &'static str = "two_factor_verified";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+
     pub fn renew(&self) {
         self.0.renew()
     }
@@ -20,6 +32,46 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    /// Forgets the pending login, once the second factor has been checked.
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+    }
+
+    pub fn insert_two_factor_verified(&self) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::TWO_FACTOR_VERIFIED_KEY, true)
+    }
+
+    pub fn is_two_factor_verified(&self) -> Result<bool, SessionGetError> {
+        Ok(self
+            .0
+            .get::<bool>(Self::TWO_FACTOR_VERIFIED_KEY)?
+            .unwrap_or(false))
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(&self) {
         // Remove session for both client & server.
         self.0.purge()
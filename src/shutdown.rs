@@ -0,0 +1,33 @@
+use tokio_util::sync::CancellationToken;
+
+// Graceful shutdown
+// The token is shared by the HTTP server and the background workers, see main.rs.
+// Once cancelled, the server stops accepting connections and drains the in-flight requests,
+// while the workers finish their current task and return.
+
+/// Cancels `shutdown` on SIGTERM, sent on deploy, or SIGINT, i.e. Ctrl+C.
+pub async fn cancel_on_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install the SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT, shutting down."),
+        _ = terminate => tracing::info!("Received SIGTERM, shutting down."),
+        // Shutting down for another reason, e.g. a task has failed.
+        _ = shutdown.cancelled() => return,
+    }
+    shutdown.cancel();
+}
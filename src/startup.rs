@@ -1,8 +1,18 @@
+use actix_session::config::{BrowserSession, TtlExtensionPolicy};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
-use std::net::TcpListener;
+use anyhow::Context;
+use std::future::Future;
+use std::net::{IpAddr, TcpListener};
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 
-use crate::authentication::reject_anonymous_user;
+use crate::authentication::{
+    reject_anonymous_user, reject_invalid_api_token, reject_invalid_csrf_token, BreachedPasswords,
+    LoginRateLimiter,
+};
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
 use actix_web::middleware::from_fn;
@@ -15,17 +25,42 @@ use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
+use crate::configuration::{
+    ApplicationSettings, DatabaseSettings, IdempotencySettings, LoginRateLimitSettings,
+    PasswordHashingSettings, PasswordPolicySettings, Settings,
+};
+use crate::email_client::EmailTransport;
+use crate::idempotency::{idempotency, IdempotencyError};
+use crate::routes::{
+    accept_invitation, accept_invitation_form, change_user_role, deactivate_user, invite_user,
+    list_users, reactivate_user,
+};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, log_out, login,
-    publish_newsletter, publish_newsletter_form, subscribe,
+    admin_dashboard, change_password, change_password_form, confirm, edit_newsletter_form,
+    health_check, issue_detail, list_failures, list_issues, log_out, login, password_reset_form,
+    password_reset_request_form, publish_newsletter, publish_newsletter_form,
+    request_password_reset, requeue_failures, reset_password, subscribe, unsubscribe,
+    unsubscribe_form, update_newsletter,
 };
+use crate::routes::{
+    api_confirm_subscriber, api_create_issue, api_create_subscriber, api_delete_subscriber,
+    api_docs, api_get_issue, api_get_subscriber, api_list_subscribers, api_not_found,
+    extractor_error, openapi_json, redoc_bundle, ApiError,
+};
+use crate::routes::{
+    confirm_two_factor_setup, security_settings, start_two_factor_setup, turn_off_two_factor,
+    two_factor_form, verify_two_factor,
+};
+use crate::routes::{create_user_api_token, list_api_tokens, revoke_user_api_token};
+use crate::routes::{export_audit_events, list_audit_events};
 use crate::routes::{home, login_form};
+use crate::routes::{list_sessions, revoke_other_user_sessions, revoke_user_session};
+use crate::session_state::SESSION_TTL;
 
 pub struct Application {
     server: Server,
     port: u16,
+    background_tasks: BackgroundTasks,
 }
 
 impl Application {
@@ -38,31 +73,89 @@ impl Application {
             .local_addr()
             .expect("Failed to get local address")
             .port();
+        let background_tasks = BackgroundTasks::on_current_runtime();
         let server = run(
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            background_tasks.clone(),
+            configuration.application,
+            configuration.login_rate_limit,
+            configuration.idempotency,
+            configuration.password_hashing,
+            configuration.password_policy,
             configuration.redis_uri,
         )
         .await?;
-        Ok(Self { server, port })
+        Ok(Self {
+            server,
+            port,
+            background_tasks,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), anyhow::Error> {
+    /// Runs until `shutdown` is cancelled, then stops accepting connections
+    /// and waits for in-flight requests, up to the configured shutdown timeout,
+    /// and for the tasks they have left running in the background.
+    pub async fn run_until_stopped(self, shutdown: CancellationToken) -> Result<(), anyhow::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            handle.stop(true).await;
+        });
         self.server.await?;
+        self.background_tasks.wait().await;
         Ok(())
     }
 }
 
+/// Tasks left running by handlers, e.g. to send an email without making the response wait.
+/// They run on the runtime the application is built on, rather than on the one of the worker
+/// thread handling the request, which is dropped with its tasks as soon as the server stops.
+#[derive(Clone)]
+pub struct BackgroundTasks {
+    tracker: TaskTracker,
+    runtime: Handle,
+}
+
+impl BackgroundTasks {
+    pub fn on_current_runtime() -> Self {
+        Self {
+            tracker: TaskTracker::new(),
+            runtime: Handle::current(),
+        }
+    }
+
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.tracker.spawn_on(task, &self.runtime);
+    }
+
+    /// Waits for the tasks spawned so far, refusing to wait for any spawned later.
+    pub async fn wait(&self) {
+        self.tracker.close();
+        self.tracker.wait().await;
+    }
+}
+
+// The reverse proxies whose forwarding headers are believed, see `utils::client_ip`.
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
 // We need to define a wrapper type in order to retrieve the base url from the configuration file.
 pub struct ApplicationBaseUrl(pub String);
 
+// How long a subscription confirmation token stays valid after being issued.
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+// How long an invitation link for a new admin user stays valid.
+pub struct InvitationTtl(pub chrono::Duration);
+
+// How long a password reset token stays valid after being issued.
+pub struct PasswordResetTokenTtl(pub chrono::Duration);
+
 #[derive(Clone)]
 pub struct HmacSecret(pub SecretString);
 
@@ -72,17 +165,44 @@ pub fn get_connection_pool(database_settings: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(database_settings.with_db())
 }
 
+// One argument per section of the settings the application needs.
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: SecretString,
+    email_client: Arc<dyn EmailTransport>,
+    // Tasks spawned by handlers, which shutdown waits for.
+    background_tasks: BackgroundTasks,
+    settings: ApplicationSettings,
+    login_rate_limit: LoginRateLimitSettings,
+    idempotency_settings: IdempotencySettings,
+    password_hashing: PasswordHashingSettings,
+    password_policy: PasswordPolicySettings,
     redis_uri: SecretString,
 ) -> Result<Server, anyhow::Error> {
+    // Caught here rather than on the first login.
+    password_hashing
+        .params()
+        .context("Invalid password hashing settings.")?;
+    let password_hashing = web::Data::new(password_hashing);
+    let breached_passwords = match &password_policy.breached_passwords_file {
+        Some(path) => BreachedPasswords::load(path)?,
+        None => BreachedPasswords::default(),
+    };
+    let breached_passwords = web::Data::new(breached_passwords);
+    let idempotency_settings = web::Data::new(idempotency_settings);
+    let background_tasks = web::Data::new(background_tasks);
     let connection = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let email_client: web::Data<dyn EmailTransport> = web::Data::from(email_client);
+    let subscription_token_ttl =
+        web::Data::new(SubscriptionTokenTtl(settings.subscription_token_ttl()));
+    let password_reset_token_ttl =
+        web::Data::new(PasswordResetTokenTtl(settings.password_reset_token_ttl()));
+    let invitation_ttl = web::Data::new(InvitationTtl(settings.invitation_ttl()));
+    let shutdown_timeout = settings.shutdown_timeout();
+    let trusted_proxies = web::Data::new(TrustedProxies(settings.trusted_proxies));
+    let base_url = web::Data::new(ApplicationBaseUrl(settings.base_url));
+    let hmac_secret = settings.hmac_secret;
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
 
@@ -90,36 +210,157 @@ pub async fn run(
     let flash_message_framework = FlashMessagesFramework::builder(message_store).build();
 
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_rate_limiter =
+        web::Data::new(LoginRateLimiter::new(&redis_uri, login_rate_limit).await?);
 
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
             .wrap(flash_message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                // Idle sessions expire, matching the `last_seen_at` of `user_sessions`.
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .session_lifecycle(
+                        BrowserSession::default()
+                            .state_ttl(SESSION_TTL)
+                            .state_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest),
+                    )
+                    .build(),
+            )
             .route("/", web::get().to(home))
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
+            .route("/login/2fa", web::get().to(two_factor_form))
+            .route("/login/2fa", web::post().to(verify_two_factor))
+            .route("/login/reset", web::get().to(password_reset_request_form))
+            .route("/login/reset", web::post().to(request_password_reset))
+            .route("/login/reset/confirm", web::get().to(password_reset_form))
+            .route("/login/reset/confirm", web::post().to(reset_password))
             .service(
                 web::scope("/admin")
+                    // Each runs after the ones added below it, the last middleware added runs first.
+                    .wrap(from_fn(reject_invalid_csrf_token))
                     .wrap(from_fn(reject_anonymous_user))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out))
+                    .route("/security", web::get().to(security_settings))
+                    .route("/security/totp", web::post().to(start_two_factor_setup))
+                    .route(
+                        "/security/totp/confirm",
+                        web::post().to(confirm_two_factor_setup),
+                    )
+                    .route(
+                        "/security/totp/disable",
+                        web::post().to(turn_off_two_factor),
+                    )
+                    .route("/sessions", web::get().to(list_sessions))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(revoke_other_user_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(revoke_user_session),
+                    )
+                    .route("/api-tokens", web::get().to(list_api_tokens))
+                    .route("/api-tokens", web::post().to(create_user_api_token))
+                    .route(
+                        "/api-tokens/{token_id}/revoke",
+                        web::post().to(revoke_user_api_token),
+                    )
                     .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route(
+                        "/newsletters",
+                        // Runs after the middlewares of the scope.
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(idempotency::<IdempotencyError>)),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(edit_newsletter_form),
+                    )
+                    .route("/newsletters/{issue_id}", web::post().to(update_newsletter))
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues/{issue_id}", web::get().to(issue_detail))
+                    .route("/failures", web::get().to(list_failures))
+                    .route("/failures/requeue", web::post().to(requeue_failures))
+                    .route("/users", web::get().to(list_users))
+                    .route("/audit", web::get().to(list_audit_events))
+                    .route("/audit/export", web::get().to(export_audit_events))
+                    .route("/users/invite", web::post().to(invite_user))
+                    .route("/users/{user_id}/role", web::post().to(change_user_role))
+                    .route(
+                        "/users/{user_id}/deactivate",
+                        web::post().to(deactivate_user),
+                    )
+                    .route(
+                        "/users/{user_id}/reactivate",
+                        web::post().to(reactivate_user),
+                    ),
+            )
+            .route("/api/openapi.json", web::get().to(openapi_json))
+            .route("/api/docs", web::get().to(api_docs))
+            .route("/api/docs/redoc.standalone.js", web::get().to(redoc_bundle))
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_token))
+                    // Malformed requests get the same JSON errors as the handlers return.
+                    .app_data(web::JsonConfig::default().error_handler(extractor_error))
+                    .app_data(web::QueryConfig::default().error_handler(extractor_error))
+                    .app_data(web::PathConfig::default().error_handler(extractor_error))
+                    .route("/subscribers", web::get().to(api_list_subscribers))
+                    .route("/subscribers", web::post().to(api_create_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(api_get_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(api_delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(api_confirm_subscriber),
+                    )
+                    .route(
+                        "/issues",
+                        web::post()
+                            .to(api_create_issue)
+                            .wrap(from_fn(idempotency::<ApiError>)),
+                    )
+                    .route("/issues/{issue_id}", web::get().to(api_get_issue))
+                    .default_service(web::to(api_not_found)),
             )
             .app_data(connection.clone())
             .app_data(email_client.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(password_reset_token_ttl.clone())
+            .app_data(invitation_ttl.clone())
+            .app_data(login_rate_limiter.clone())
+            .app_data(idempotency_settings.clone())
+            .app_data(background_tasks.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(password_hashing.clone())
+            .app_data(breached_passwords.clone())
             .app_data(base_url.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
+    // Signals are handled by the caller, see `Application::run_until_stopped`.
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs())
     .listen(listener)?
     .run();
     Ok(server)
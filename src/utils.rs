@@ -1,5 +1,7 @@
-use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use crate::startup::TrustedProxies;
+use actix_web::http::header::{HeaderMap, FORWARDED, LOCATION, X_FORWARDED_FOR};
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::net::{IpAddr, SocketAddr};
 
 // Return a Bad Request (400) with the user-representation of the validation error as body.
 // The error root cause is preserved for logging purposes.
@@ -10,6 +12,14 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+// Return a Forbidden (403), for logged-in users whose role does not allow the action.
+pub fn e403<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorForbidden(e)
+}
+
 // Return an opaque Internal Server Error (500),
 // while preserving the error root's cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -26,3 +36,79 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Escape user-provided text before interpolating it into an HTML page,
+// e.g. issue contents pre-filled in a `<textarea>`.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The address of the client. It is the peer address, unless the peer is one of the trusted
+/// proxies, which append the address they got the request from to the forwarding headers:
+/// the last address there which is not a trusted proxy is then the client.
+/// The headers of any other peer are ignored, as clients could forge them.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    let Some(trusted_proxies) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+    if !trusted_proxies.0.contains(&peer) {
+        return Some(peer);
+    }
+    let client = forwarded_for(request.headers())
+        .into_iter()
+        .rev()
+        .find(|ip| !trusted_proxies.0.contains(ip));
+    Some(client.unwrap_or(peer))
+}
+
+/// The addresses of `Forwarded`, or of `X-Forwarded-For` without it, in order.
+/// Obfuscated identifiers like `unknown` are skipped.
+fn forwarded_for(headers: &HeaderMap) -> Vec<IpAddr> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+    let forwarded = values(FORWARDED);
+    if !forwarded.is_empty() {
+        forwarded
+            .into_iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.trim().split_once('=')?;
+                    name.eq_ignore_ascii_case("for").then_some(value)
+                })
+            })
+            .filter_map(parse_node)
+            .collect()
+    } else {
+        values(X_FORWARDED_FOR)
+            .into_iter()
+            .filter_map(parse_node)
+            .collect()
+    }
+}
+
+/// An address, possibly quoted and with a port, e.g. `"[2001:db8::17]:4711"`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim_matches('"');
+    node.parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
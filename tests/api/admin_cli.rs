@@ -0,0 +1,204 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, sample_newsletter_form, spawn_app, TestApp,
+};
+use uuid::Uuid;
+
+fn generated_password(output: &str) -> String {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("Password: "))
+        .expect("No generated password in the output.")
+        .to_string()
+}
+
+async fn login(app: &TestApp, username: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({ "username": username, "password": password }))
+        .await
+}
+
+#[tokio::test]
+async fn the_seeded_user_is_gone() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let seeded_user = sqlx::query!(
+        "SELECT user_id FROM users WHERE user_id = '4d0dea38-5c5a-4865-8de0-c9b84b52ea82'"
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Assert
+    assert!(seeded_user.is_none());
+}
+
+#[tokio::test]
+async fn a_created_user_can_log_in_with_the_generated_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+
+    // Act
+    let output = app
+        .run_admin_cli(&[
+            "create-user",
+            &username,
+            "--role",
+            "editor",
+            "--email",
+            "editor@example.com",
+        ])
+        .await
+        .unwrap();
+
+    // Assert
+    let response = login(&app, &username, &generated_password(&output)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {} (editor)", username)));
+    let event =
+        sqlx::query!("SELECT actor_id, target_id FROM audit_events WHERE action = 'user.created'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(event.actor_id, None);
+    assert!(output.contains(&event.target_id.unwrap()));
+}
+
+#[tokio::test]
+async fn an_existing_username_cannot_be_created_again() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let outcome = app
+        .run_admin_cli(&["create-user", &app.test_user.username])
+        .await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn a_disabled_user_cannot_log_in_until_enabled_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = app.test_user.username.clone();
+
+    // Act - Part 1 - Disable
+    app.run_admin_cli(&["disable-user", &username])
+        .await
+        .unwrap();
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Enable
+    app.run_admin_cli(&["enable-user", &username])
+        .await
+        .unwrap();
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_out_the_user() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let output = app
+        .run_admin_cli(&["reset-password", &app.test_user.username])
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(output.contains("1 sessions have been revoked"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = login(&app, &app.test_user.username, &generated_password(&output)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_listed_and_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    let output = app.run_admin_cli(&["queue", "list"]).await.unwrap();
+    assert!(output.contains("Queued deliveries: 1"));
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        app.delivery.max_attempts as i16
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let failure_id = sqlx::query_scalar!("SELECT failure_id FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - List
+    let output = app.run_admin_cli(&["queue", "list"]).await.unwrap();
+    assert!(output.contains("Queued deliveries: 0"));
+    assert!(output.contains("Failed deliveries: 1"));
+    assert!(output.contains(&format!("failure_id={}", failure_id)));
+
+    // Act - Part 2 - Requeue
+    let output = app
+        .run_admin_cli(&["queue", "requeue", "--all"])
+        .await
+        .unwrap();
+    assert_eq!(output, "1 failed deliveries have been requeued.\n");
+    let output = app.run_admin_cli(&["queue", "list"]).await.unwrap();
+    assert!(output.contains("Queued deliveries: 1"));
+    assert!(output.contains("Failed deliveries: 0"));
+}
+
+#[tokio::test]
+async fn old_idempotency_keys_are_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    for (key, age_hours) in [("old-key", 72), ("recent-key", 1)] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency (user_id, idempotency_key, created_at)
+            VALUES ($1, $2, now() - make_interval(hours => $3))
+            "#,
+            app.test_user.user_id,
+            key,
+            age_hours,
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let output = app
+        .run_admin_cli(&["purge-idempotency-keys", "--older-than-hours", "24"])
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(output, "Deleted 1 idempotency keys older than 24 hours.\n");
+    let keys = sqlx::query_scalar!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec!["recent-key".to_string()]);
+}
+
+#[tokio::test]
+async fn migrating_an_up_to_date_database_is_a_no_op() {
+    let app = spawn_app().await;
+    let output = app.run_admin_cli(&["migrate"]).await.unwrap();
+    assert_eq!(output, "The database schema is up to date.\n");
+}
@@ -0,0 +1,315 @@
+use crate::helpers::{
+    api_error_code, create_confirmed_subscriber, spawn_app_with_api_token, PostmarkBatchResponder,
+    TestApp,
+};
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const ISSUES_READ_WRITE: [&str; 2] = ["issues:read", "issues:write"];
+
+fn sample_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body",
+        "html_content": "<p>Newsletter body</p>",
+    })
+}
+
+async fn post_issue(
+    app: &TestApp,
+    token: &str,
+    idempotency_key: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    app.api_request(Method::POST, "/issues", token)
+        .header("Idempotency-Key", idempotency_key)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn an_accepted_issue_reports_its_delivery_progress() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Create the issue
+    let response = post_issue(&app, &token, &Uuid::new_v4().to_string(), &sample_issue()).await;
+
+    // Assert - Part 1
+    assert_eq!(response.status().as_u16(), 202);
+    let accepted: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(accepted["status"], "sending");
+    let status_url = accepted["status_url"].as_str().unwrap();
+    assert_eq!(
+        status_url,
+        format!("/api/v1/issues/{}", accepted["issue_id"].as_str().unwrap())
+    );
+    let status_path = status_url.trim_start_matches("/api/v1");
+    let progress: serde_json::Value = app
+        .api_request(Method::GET, status_path, &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["title"], "Newsletter title");
+    assert_eq!(progress["delivery"]["queued"], 1);
+
+    // Act - Part 2 - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let progress: serde_json::Value = app
+        .api_request(Method::GET, status_path, &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(progress["delivery"]["queued"], 0);
+    assert_eq!(progress["delivery"]["delivered"], 1);
+}
+
+#[tokio::test]
+async fn issue_creation_through_the_api_is_idempotent() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Act
+    let first = post_issue(&app, &token, &idempotency_key, &sample_issue()).await;
+    let second = post_issue(&app, &token, &idempotency_key, &sample_issue()).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_issue_is_rejected() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut draft = sample_issue();
+    draft["action"] = "draft".into();
+    let mut another_draft = draft.clone();
+    another_draft["title"] = "Another title".into();
+
+    // Act
+    let first = post_issue(&app, &token, &idempotency_key, &draft).await;
+    let second = post_issue(&app, &token, &idempotency_key, &another_draft).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 422);
+    assert_eq!(api_error_code(second).await, "idempotency_key_reused");
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 1);
+}
+
+/// Leaves `idempotency_key` locked until `locked_until` seconds from now, as a request still
+/// being processed, or abandoned if it is in the past.
+async fn lock_idempotency_key(app: &TestApp, idempotency_key: &str, locked_until: f64) {
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at, locked_until)
+        VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+        "#,
+        app.test_user.user_id,
+        idempotency_key,
+        locked_until,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_request_whose_key_is_in_progress_is_told_to_retry_later() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    lock_idempotency_key(&app, &idempotency_key, 30.0).await;
+
+    // Act
+    let response = post_issue(&app, &token, &idempotency_key, &sample_issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=30).contains(&retry_after));
+    assert_eq!(api_error_code(response).await, "request_in_progress");
+}
+
+#[tokio::test]
+async fn a_key_left_in_progress_can_be_retried_once_its_lock_expires() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    lock_idempotency_key(&app, &idempotency_key, -1.0).await;
+    let mut draft = sample_issue();
+    draft["action"] = "draft".into();
+
+    // Act
+    let response = post_issue(&app, &token, &idempotency_key, &draft).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let saved = sqlx::query!(
+        "SELECT response_status_code, locked_until FROM idempotency WHERE idempotency_key = $1",
+        idempotency_key,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.response_status_code, Some(202));
+    assert!(saved.locked_until.is_none());
+}
+
+#[tokio::test]
+async fn the_issue_is_not_created_if_the_response_cannot_be_saved() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut draft = sample_issue();
+    draft["action"] = "draft".into();
+    sqlx::raw_sql(
+        r#"
+        CREATE FUNCTION reject_saved_responses() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'Saving responses is broken';
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER idempotency_no_saved_responses
+            BEFORE UPDATE ON idempotency
+            FOR EACH ROW WHEN (NEW.response_status_code IS NOT NULL)
+            EXECUTE FUNCTION reject_saved_responses();
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = post_issue(&app, &token, &idempotency_key, &draft).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let n_keys = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+    // Released, for the request to be retried.
+    assert_eq!(n_keys, 0);
+}
+
+#[tokio::test]
+async fn invalid_issue_requests_are_rejected() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&ISSUES_READ_WRITE).await;
+    let mut scheduled_in_the_past = sample_issue();
+    scheduled_in_the_past["action"] = "schedule".into();
+    scheduled_in_the_past["send_at"] = "2020-01-01T00:00:00Z".into();
+
+    let test_cases = [
+        (
+            app.api_request(Method::POST, "/issues", &token)
+                .json(&sample_issue()),
+            400,
+            "invalid_request",
+            "missing Idempotency-Key header",
+        ),
+        (
+            app.api_request(Method::POST, "/issues", &token)
+                .header("Idempotency-Key", Uuid::new_v4().to_string())
+                .json(&scheduled_in_the_past),
+            400,
+            "invalid_request",
+            "schedule in the past",
+        ),
+        (
+            app.api_request(Method::POST, "/issues", &token)
+                .header("Idempotency-Key", "k".repeat(50))
+                .json(&sample_issue()),
+            400,
+            "invalid_request",
+            "idempotency key too long",
+        ),
+        (
+            app.api_request(Method::GET, &format!("/issues/{}", Uuid::new_v4()), &token),
+            404,
+            "not_found",
+            "unknown issue",
+        ),
+    ];
+    for (request, status, code, description) in test_cases {
+        // Act
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "Unexpected status for {}",
+            description
+        );
+        assert_eq!(api_error_code(response).await, code);
+    }
+}
+
+#[tokio::test]
+async fn issues_cannot_be_created_without_the_issues_write_scope() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&["issues:read"]).await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_issue(&app, &token, &Uuid::new_v4().to_string(), &sample_issue()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(api_error_code(response).await, "missing_scope");
+}
@@ -0,0 +1,417 @@
+use crate::helpers::{
+    api_error_code, create_confirmed_subscriber, sample_newsletter_form, spawn_app,
+    spawn_app_with_api_token, TestApp,
+};
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const READ_WRITE: [&str; 2] = ["subscribers:read", "subscribers:write"];
+
+async fn create_subscriber(app: &TestApp, token: &str, email: &str) -> reqwest::Response {
+    app.api_request(Method::POST, "/subscribers", token)
+        .json(&serde_json::json!({ "name": "Ursula Le Guin", "email": email }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    // A logged-in session is not enough.
+    app.test_user.login(&app).await;
+
+    for response in [
+        app.api_client
+            .get(format!("{}/api/v1/subscribers", &app.address))
+            .send()
+            .await
+            .unwrap(),
+        app.api_request(Method::GET, "/subscribers", "z2p_not-a-token")
+            .send()
+            .await
+            .unwrap(),
+    ] {
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+        assert_eq!(api_error_code(response).await, "unauthorized");
+    }
+}
+
+#[tokio::test]
+async fn tokens_are_stored_hashed() {
+    // Act
+    let (app, token) = spawn_app_with_api_token(&["subscribers:read"]).await;
+
+    // Assert
+    let stored = sqlx::query!("SELECT name, token_hash, scopes FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+    assert!(!stored.token_hash.contains(&token));
+    assert_eq!(stored.scopes, vec!["subscribers:read".to_string()]);
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains(&stored.name));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_and_searched() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&["subscribers:read"]).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, 'le_guin@example.com', 'Ursula', now(), 'pending_confirmation', 'token')
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let all: serde_json::Value = app
+        .api_request(Method::GET, "/subscribers", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let searched: serde_json::Value = app
+        .api_request(Method::GET, "/subscribers?q=E_GUIN", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let confirmed: serde_json::Value = app
+        .api_request(Method::GET, "/subscribers?status=confirmed", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(all["subscribers"].as_array().unwrap().len(), 2);
+    let searched = searched["subscribers"].as_array().unwrap();
+    assert_eq!(searched.len(), 1);
+    assert_eq!(searched[0]["email"], "le_guin@example.com");
+    assert_eq!(searched[0]["status"], "pending_confirmation");
+    let confirmed = confirmed["subscribers"].as_array().unwrap();
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected_with_json_errors() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&READ_WRITE).await;
+
+    let test_cases = [
+        (
+            app.api_request(Method::GET, "/subscribers?status=gone", &token),
+            400,
+            "invalid_request",
+        ),
+        (
+            app.api_request(Method::GET, "/subscribers?limit=many", &token),
+            400,
+            "invalid_request",
+        ),
+        (
+            app.api_request(Method::GET, "/subscribers/not-a-uuid", &token),
+            400,
+            "invalid_request",
+        ),
+        (
+            app.api_request(Method::POST, "/subscribers", &token)
+                .json(&serde_json::json!({ "name": "Ursula" })),
+            400,
+            "invalid_request",
+        ),
+        (
+            app.api_request(Method::POST, "/subscribers", &token)
+                .json(&serde_json::json!({ "name": "Ursula", "email": "not-an-email" })),
+            400,
+            "invalid_request",
+        ),
+        (
+            app.api_request(
+                Method::GET,
+                &format!("/subscribers/{}", Uuid::new_v4()),
+                &token,
+            ),
+            404,
+            "not_found",
+        ),
+        (
+            app.api_request(Method::GET, "/newsletters", &token),
+            404,
+            "not_found",
+        ),
+    ];
+    for (request, status, code) in test_cases {
+        // Act
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), status);
+        assert_eq!(api_error_code(response).await, code);
+    }
+}
+
+#[tokio::test]
+async fn a_created_subscriber_receives_a_confirmation_email() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&READ_WRITE).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = create_subscriber(&app, &token, "ursula@example.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let location = response.headers()["Location"].to_str().unwrap().to_string();
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["email"], "ursula@example.com");
+    assert_eq!(created["status"], "pending_confirmation");
+    assert_eq!(
+        location,
+        format!("/api/v1/subscribers/{}", created["id"].as_str().unwrap())
+    );
+    let duplicate = create_subscriber(&app, &token, "ursula@example.com").await;
+    assert_eq!(duplicate.status().as_u16(), 409);
+    assert_eq!(api_error_code(duplicate).await, "conflict");
+}
+
+#[tokio::test]
+async fn an_idempotency_key_is_ignored_when_creating_a_subscriber() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&READ_WRITE).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_request(Method::POST, "/subscribers", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({ "name": "Ursula Le Guin", "email": "ursula@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let n_keys = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_keys, 0);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_confirmed_and_deleted() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&READ_WRITE).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let created: serde_json::Value = create_subscriber(&app, &token, "ursula@example.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let subscriber_path = format!("/subscribers/{}", created["id"].as_str().unwrap());
+
+    // Act - Part 1 - Confirm
+    let response = app
+        .api_request(
+            Method::POST,
+            &format!("{}/confirm", subscriber_path),
+            &token,
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 1
+    assert_eq!(response.status().as_u16(), 200);
+    let confirmed: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(confirmed["status"], "confirmed");
+
+    // Act - Part 2 - Delete
+    let response = app
+        .api_request(Method::DELETE, &subscriber_path, &token)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert - Part 2
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .api_request(Method::GET, &subscriber_path, &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let events = sqlx::query!(
+        "SELECT action, actor_id, target_id FROM audit_events WHERE action LIKE 'subscriber.%' ORDER BY event_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let actions: Vec<&str> = events.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(
+        actions,
+        [
+            "subscriber.created",
+            "subscriber.confirmed",
+            "subscriber.deleted"
+        ]
+    );
+    for event in events {
+        assert_eq!(event.actor_id, Some(app.test_user.user_id));
+        assert_eq!(event.target_id.as_deref(), created["id"].as_str());
+    }
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_erases_their_deliveries() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&READ_WRITE).await;
+    create_confirmed_subscriber(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log
+            (newsletter_issue_id, subscriber_email, outcome, n_retries, error_message, recorded_at)
+        SELECT newsletter_issue_id, subscriber_email, 'failed', 3, 'Mailbox full', now()
+        FROM issue_delivery_queue
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            failure_id, newsletter_issue_id, subscriber_email,
+            n_retries, last_error, enqueued_at, failed_at
+        )
+        SELECT gen_random_uuid(), newsletter_issue_id, subscriber_email, 3, 'Mailbox full', now(), now()
+        FROM issue_delivery_queue
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .api_request(
+            Method::DELETE,
+            &format!("/subscribers/{}", subscriber.id),
+            &token,
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    let n_queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let n_failures =
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_failures"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    let logged = sqlx::query!("SELECT subscriber_email, error_message FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+    assert_eq!(n_failures, 0);
+    assert_ne!(logged.subscriber_email, subscriber.email);
+    assert_eq!(logged.error_message, None);
+}
+
+#[tokio::test]
+async fn a_read_only_token_cannot_change_subscribers() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&["subscribers:read"]).await;
+
+    // Act
+    let response = create_subscriber(&app, &token, "ursula@example.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "missing_scope");
+    assert_eq!(
+        body["error"]["message"],
+        "This API token does not have the subscribers:write scope."
+    );
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    // Arrange
+    let (app, token) = spawn_app_with_api_token(&["subscribers:read"]).await;
+    let token_id = sqlx::query_scalar!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api-tokens/{}/revoke",
+            &app.address, token_id
+        ))
+        .form(&app.with_csrf_token(&()).await)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("The API token has been revoked."));
+    let response = app
+        .api_request(Method::GET, "/subscribers", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
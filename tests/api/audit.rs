@@ -0,0 +1,257 @@
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with_api_token, TestApp, TestUser,
+};
+use uuid::Uuid;
+
+async fn recorded_actions(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar!("SELECT action FROM audit_events ORDER BY event_id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser {
+        role: "editor",
+        ..TestUser::generate()
+    };
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    // Act
+    let page = app.get_audit("").await;
+    let export = app.get_audit("/export").await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(export.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn logins_logouts_and_password_changes_are_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    }))
+    .await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+    app.post_logout().await;
+
+    // Assert
+    assert_eq!(
+        recorded_actions(&app).await,
+        [
+            "login.failed",
+            "login.succeeded",
+            "password.changed",
+            "logout"
+        ]
+    );
+    let event = sqlx::query!(
+        "SELECT actor_id, ip, details FROM audit_events WHERE action = 'login.failed'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(event.actor_id, None);
+    assert_eq!(event.ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(event.details["username"], app.test_user.username.as_str());
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_issue_is_recorded_with_the_issue_as_target() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let issue_id = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let event = sqlx::query!(
+        "SELECT actor_id, target_id, details FROM audit_events WHERE action = 'newsletter_issue.created'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(event.actor_id, Some(app.test_user.user_id));
+    assert_eq!(event.target_id, Some(issue_id.to_string()));
+    assert_eq!(event.details["title"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action_and_user() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_login(&serde_json::json!({
+        "username": "someone-else",
+        "password": "wrong-password",
+    }))
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let failed_logins = app.get_audit_html("?action=login.failed").await;
+    let by_test_user = app
+        .get_audit_html(&format!("?actor={}", app.test_user.username))
+        .await;
+
+    // Assert
+    assert!(failed_logins.contains("<td>login.failed</td>"));
+    assert!(!failed_logins.contains("<td>login.succeeded</td>"));
+    assert!(by_test_user.contains("<td>login.succeeded</td>"));
+    assert!(!by_test_user.contains("<td>login.failed</td>"));
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_rejected_with_a_flash_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_audit("?since=yesterday").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/audit");
+    assert!(app
+        .get_audit_html("")
+        .await
+        .contains("<p><i>yesterday is not a valid date.</i></p>"));
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_audit("/export?action=login.succeeded").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "event_id,occurred_at,actor,action,target_id,ip,details"
+    );
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].contains(&format!(
+        ",{},login.succeeded,,127.0.0.1,\"{{\"\"session_id\"\":",
+        app.test_user.username
+    )));
+}
+
+#[tokio::test]
+async fn audit_events_cannot_be_changed_or_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_events SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_events")
+        .execute(&app.db_pool)
+        .await;
+    let truncate = sqlx::query!("TRUNCATE audit_events")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert!(truncate.is_err());
+    assert_eq!(recorded_actions(&app).await, ["login.succeeded"]);
+}
+
+#[tokio::test]
+async fn revoking_other_sessions_is_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_from_other_browser("Other browser").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_sessions("revoke-others").await;
+
+    // Assert
+    let event =
+        sqlx::query!("SELECT actor_id, details FROM audit_events WHERE action = 'session.revoked'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(event.actor_id, Some(app.test_user.user_id));
+    assert_eq!(event.details["revoked_sessions"], 1);
+}
+
+#[tokio::test]
+async fn creating_and_revoking_an_api_token_are_recorded_with_the_token_as_target() {
+    // Arrange
+    let (app, _token) = spawn_app_with_api_token(&["subscribers:read"]).await;
+    let token_id = sqlx::query_scalar!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    app.api_client
+        .post(format!(
+            "{}/admin/api-tokens/{}/revoke",
+            &app.address, token_id
+        ))
+        .form(&app.with_csrf_token(&()).await)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let events = sqlx::query!(
+        "SELECT action, target_id FROM audit_events WHERE action LIKE 'api_token.%' ORDER BY event_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    let events: Vec<_> = events
+        .into_iter()
+        .map(|event| (event.action, event.target_id))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            ("api_token.created".to_string(), Some(token_id.to_string())),
+            ("api_token.revoked".to_string(), Some(token_id.to_string())),
+        ]
+    );
+}
@@ -189,3 +189,50 @@ async fn changing_password_works() {
     let response = app.post_login(&login_body).await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn weak_new_passwords_are_rejected_with_a_specific_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let cases = [
+        // Listed in configurations/breached_passwords.txt
+        (
+            "password1234".to_string(),
+            "This password has appeared in a data breach, please choose another one.",
+        ),
+        (
+            format!("my-{}-password", app.test_user.username.to_uppercase()),
+            "The password must not contain your username.",
+        ),
+        (
+            "abababababababab".to_string(),
+            "The password must not be made of a few repeated characters.",
+        ),
+    ];
+
+    for (new_password, message) in cases {
+        // Act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(&format!("<p><i>{}</i></p>", message)),
+            "Expected `{}` for the password `{}`.",
+            message,
+            new_password
+        );
+    }
+    // The password is unchanged.
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,109 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+
+const INVALID_TOKEN_MESSAGE: &str =
+    "Your form has expired, please reload the page and submit it again.";
+
+#[tokio::test]
+async fn admin_forms_carry_the_csrf_token_of_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.csrf_token().await;
+
+    // Act
+    let change_password_page = app.get_change_password_html().await;
+    let publish_page = app.get_publish_newsletter_html().await;
+
+    // Assert
+    assert_eq!(token.len(), 32);
+    let field = format!(
+        r#"<input type="hidden" name="csrf_token" value="{}">"#,
+        token
+    );
+    assert!(change_password_page.contains(&field));
+    assert!(publish_page.contains(&field));
+}
+
+#[tokio::test]
+async fn a_post_without_csrf_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(response.text().await.unwrap(), INVALID_TOKEN_MESSAGE);
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(&format!("<p><i>{}</i></p>", INVALID_TOKEN_MESSAGE)));
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_csrf_token_of_another_session_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_browser = app.login_from_other_browser("Other browser/1.0").await;
+    let html_page = other_browser
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let other_token = html_page
+        .split(r#"name="csrf_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_string();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&[("csrf_token", other_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn anonymous_posts_are_still_redirected_to_login() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
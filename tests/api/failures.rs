@@ -0,0 +1,89 @@
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+use zero2prod::routes::NOTHING_SELECTED_MESSAGE;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, sample_newsletter_form, spawn_app,
+    PostmarkBatchResponder,
+};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+    let response = app.get_failures().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_failed_deliveries() {
+    let app = spawn_app().await;
+    let response = app.post_requeue_failures(&[uuid::Uuid::new_v4()]).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn given_up_deliveries_can_be_inspected_and_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        app.delivery.max_attempts as i16
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Give up
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - The task has been moved to the dead letters
+    let failure = sqlx::query!("SELECT failure_id, subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let html_page = app.get_failures_html().await;
+    assert!(html_page.contains(&failure.subscriber_email));
+    assert!(html_page.contains("Gave up after 5 attempts."));
+
+    // Act - Part 2 - Requeue
+    let response = app.post_requeue_failures(&[failure.failure_id]).await;
+    assert_is_redirect_to(&response, "/admin/failures");
+    let html_page = app.get_failures_html().await;
+    assert!(html_page.contains("1 failed deliveries have been requeued."));
+    assert!(!html_page.contains(&failure.subscriber_email));
+
+    // Act - Part 3 - Deliver
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let logged = sqlx::query!("SELECT outcome, n_retries FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(logged.outcome, "delivered");
+    assert_eq!(logged.n_retries, 0);
+}
+
+#[tokio::test]
+async fn requeuing_without_a_selection_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_requeue_failures(&[]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/failures");
+    let html_page = app.get_failures_html().await;
+    assert!(html_page.contains(NOTHING_SELECTED_MESSAGE));
+}
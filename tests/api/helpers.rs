@@ -1,14 +1,21 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use linkify::Link;
 use once_cell::sync::Lazy;
 use sqlx::{ConnectOptions, Executor, PgPool};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
 
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
-use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::admin_cli::{AdminCli, Command};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, DeliverySettings};
+use zero2prod::email_client::EmailTransport;
+use zero2prod::issue_delivery_worker::{try_execute_tasks, ExecutionOutcome};
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -31,24 +38,41 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub base_url: String,
+    pub delivery: DeliverySettings,
+    pub admin_cli: AdminCli,
+    pub shutdown: CancellationToken,
+    // Completes once the server has stopped on shutdown.
+    pub server: tokio::task::JoinHandle<Result<(), anyhow::Error>>,
 }
 
 // A set of API client implementations for testing.
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            let outcome = try_execute_task(&self.db_pool, &self.email_client).await;
+            let outcome = try_execute_tasks(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.base_url,
+                &self.delivery,
+            )
+            .await;
             match outcome {
-                Ok(ExecutionOutcome::TaskCompleted) => continue,
-                Ok(ExecutionOutcome::TaskRetryScheduled) => continue,
-                Ok(ExecutionOutcome::TaskSkipped) => continue,
+                Ok(ExecutionOutcome::TasksProcessed(_)) => continue,
                 Ok(ExecutionOutcome::EmptyQueue) => break,
                 Err(_) => continue,
             }
         }
     }
 
+    /// Runs `zero2prod-admin` with `args` against the database of the app.
+    pub async fn run_admin_cli(&self, args: &[&str]) -> Result<String, anyhow::Error> {
+        let command = Command::parse(args.iter().map(|arg| arg.to_string()))
+            .map_err(|e| anyhow::anyhow!(e))?;
+        self.admin_cli.execute(command).await
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscriptions", self.address))
@@ -59,18 +83,122 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_unsubscribe(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/subscriptions/unsubscribe{}",
+                self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/unsubscribe{}",
+                self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_publish_newsletter<Body: serde::Serialize>(
         &self,
         body: &Body,
     ) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/newsletters", &self.address))
-            .form(body)
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_edit_newsletter<Body: serde::Serialize>(
+        &self,
+        issue_id: Uuid,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_edit_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/newsletters/{issue_id} response")
+    }
+
+    pub async fn get_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/issues response")
+    }
+
+    pub async fn get_issues_html(&self) -> String {
+        self.get_issues()
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the issue history html page")
+    }
+
+    pub async fn get_issue_detail(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/issues/{issue_id} response")
+    }
+
+    pub async fn get_issue_detail_html(&self, issue_id: Uuid) -> String {
+        self.get_issue_detail(issue_id)
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the issue detail html page")
+    }
+
+    pub async fn get_failures(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/failures", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/failures response")
+    }
+
+    pub async fn get_failures_html(&self) -> String {
+        self.get_failures()
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the failed deliveries html page")
+    }
+
+    pub async fn post_requeue_failures(&self, failure_ids: &[Uuid]) -> reqwest::Response {
+        let form: Vec<(&str, String)> = failure_ids
+            .iter()
+            .map(|id| ("failure_id", id.to_string()))
+            .collect();
+        self.api_client
+            .post(format!("{}/admin/failures/requeue", &self.address))
+            .form(&self.with_csrf_token(&form).await)
+            .send()
+            .await
+            .expect("Failed to execute POST /admin/failures/requeue request")
+    }
+
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/newsletters", &self.address))
@@ -104,6 +232,39 @@ impl TestApp {
         response.text().await.expect("Failed get the login page.")
     }
 
+    pub async fn post_password_reset_request(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/reset", &self.address))
+            .form(&serde_json::json!({ "username": username }))
+            .send()
+            .await
+            .expect("Failed to execute POST /login/reset request.")
+    }
+
+    pub async fn post_password_reset<Body: serde::Serialize>(
+        &self,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST /login/reset/confirm request.")
+    }
+
+    /// Password reset emails are sent in the background, so we poll the mock server for them.
+    pub async fn wait_for_email_requests(&self, n: usize) -> Vec<Request> {
+        for _ in 0..50 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= n {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("Timed out waiting for {} email request(s).", n)
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/dashboard", &self.address))
@@ -134,7 +295,7 @@ impl TestApp {
     ) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/password", &self.address))
-            .form(body)
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to fetch POST /admin/password response")
@@ -148,9 +309,226 @@ impl TestApp {
             .expect("Failed to fetch the change password html page")
     }
 
+    pub async fn get_security(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/security", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/security response")
+    }
+
+    pub async fn get_security_html(&self) -> String {
+        self.get_security()
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the security settings html page")
+    }
+
+    pub async fn post_security<Body: serde::Serialize>(
+        &self,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/security/{}", &self.address, action))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute POST /admin/security request")
+    }
+
+    pub async fn get_audit(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/audit response")
+    }
+
+    pub async fn get_audit_html(&self, query: &str) -> String {
+        self.get_audit(query)
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the audit log html page")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/sessions response")
+            .text()
+            .await
+            .expect("Failed to fetch the sessions html page")
+    }
+
+    pub async fn post_sessions(&self, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/{}", &self.address, action))
+            .form(&self.with_csrf_token(&()).await)
+            .send()
+            .await
+            .expect("Failed to execute POST /admin/sessions request")
+    }
+
+    /// Logs the test user in from another browser, with its own cookies.
+    pub async fn login_from_other_browser(&self, user_agent: &str) -> reqwest::Client {
+        let browser = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .user_agent(user_agent)
+            .build()
+            .unwrap();
+        let response = browser
+            .post(format!("{}/login", &self.address))
+            .form(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .send()
+            .await
+            .expect("Failed to execute POST /login request");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        browser
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/2fa", &self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute POST /login/2fa request")
+    }
+
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/users response")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users()
+            .await
+            .text()
+            .await
+            .expect("Failed to fetch the users html page")
+    }
+
+    pub async fn post_users<Body: serde::Serialize>(
+        &self,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/{}", &self.address, action))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute POST /admin/users request")
+    }
+
+    pub async fn post_accept_invitation<Body: serde::Serialize>(
+        &self,
+        body: &Body,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST /invitations/accept request")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to fetch GET /admin/api-tokens response")
+            .text()
+            .await
+            .expect("Failed to fetch the API tokens html page")
+    }
+
+    pub async fn post_create_api_token(&self, name: &str, scopes: &[&str]) -> reqwest::Response {
+        let mut fields = self
+            .with_csrf_token(&serde_json::json!({ "name": name }))
+            .await;
+        fields.extend(
+            scopes
+                .iter()
+                .map(|scope| ("scope".into(), scope.to_string())),
+        );
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .form(&fields)
+            .send()
+            .await
+            .expect("Failed to execute POST /admin/api-tokens request")
+    }
+
+    /// Creates an API token for the logged-in user, read from the page which shows it once.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let html_page = self
+            .post_create_api_token("Test integration", scopes)
+            .await
+            .text()
+            .await
+            .unwrap();
+        html_page
+            .split("<code>")
+            .find(|part| part.starts_with("z2p_"))
+            .and_then(|part| part.split("</code>").next())
+            .expect("The API token is not on the page")
+            .to_string()
+    }
+
+    /// A request to the JSON API, authenticated with `token`.
+    pub fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(token)
+    }
+
+    /// The CSRF token of the current session, read from the logout form of the dashboard.
+    /// Empty when logged out, such requests are turned away before the token is checked.
+    pub async fn csrf_token(&self) -> String {
+        let html_page = self.get_admin_dashboard_html().await;
+        html_page
+            .split(r#"name="csrf_token" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The fields of `body`, with the CSRF token of the current session added.
+    pub async fn with_csrf_token<Body: serde::Serialize>(
+        &self,
+        body: &Body,
+    ) -> Vec<(String, String)> {
+        // Bodies without fields, e.g. `&()`, fail to encode.
+        let encoded = serde_urlencoded::to_string(body).unwrap_or_default();
+        let mut fields: Vec<(String, String)> = serde_urlencoded::from_str(&encoded).unwrap();
+        fields.push(("csrf_token".into(), self.csrf_token().await));
+        fields
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/logout", &self.address))
+            .form(&self.with_csrf_token(&()).await)
             .send()
             .await
             .expect("Failed to fetch POST /logout response")
@@ -165,6 +543,88 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, redirect_endpoint: &s
     )
 }
 
+/// An app with the test user logged in, and an API token of theirs with `scopes`.
+pub async fn spawn_app_with_api_token(scopes: &[&str]) -> (TestApp, String) {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(scopes).await;
+    (app, token)
+}
+
+/// The `code` of a JSON API error response.
+pub async fn api_error_code(response: reqwest::Response) -> String {
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["error"]["code"].as_str().unwrap().to_string()
+}
+
+pub fn sample_newsletter_form() -> serde_json::Value {
+    let idempotency_key = Uuid::new_v4().to_string();
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body",
+        "html_content": "<p>Newsletter body</p>",
+        "idempotency_key": idempotency_key,
+    })
+}
+
+/// Mimics Postmark's `/email/batch` endpoint, accepting every message of the batch.
+pub struct PostmarkBatchResponder;
+
+impl Respond for PostmarkBatchResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<serde_json::Value> = messages
+            .iter()
+            .map(|_| serde_json::json!({"ErrorCode": 0, "Message": "OK"}))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email,
+    }))
+    .expect("Failed to serialize the subscriber name and email data.");
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Create a subscriber, but not confirmed.
+    let _api_response = app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    // Start from unconfirmed subscriber
+    let confirmation_link = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
     pub plain_text: reqwest::Url,
@@ -197,6 +657,8 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub email: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
@@ -205,6 +667,8 @@ impl TestUser {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            email: SafeEmail().fake(),
+            role: "owner",
             // password: "everything-has-to-start-somewhere".to_string(), // For just seeding.
         }
     }
@@ -219,10 +683,12 @@ impl TestUser {
         .unwrap()
         .to_string();
         let q = sqlx::query!(
-            "INSERT INTO USERS (user_id, username, password_hash) values ($1, $2, $3)",
+            "INSERT INTO USERS (user_id, username, password_hash, email, role) values ($1, $2, $3, $4, $5)",
             self.user_id,
             self.username,
-            password_hash
+            password_hash,
+            self.email,
+            self.role
         );
 
         q.execute(pool).await.expect("Failed to insert test user");
@@ -273,6 +739,12 @@ pub async fn spawn_app() -> TestApp {
         // Use random database name for each test cases.
         c.database.database_name = Uuid::new_v4().to_string();
         c.email_client.base_url = email_server.uri();
+        // All test apps share one Redis and log in from 127.0.0.1, keep their lockouts apart.
+        c.login_rate_limit.key_prefix = Uuid::new_v4().to_string();
+        // Requests waiting for another one with the same idempotency key give up sooner.
+        c.idempotency.wait_timeout_milliseconds = 500;
+        // Tests may pose as a reverse proxy forwarding for other clients.
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
         c
     };
     let app = Application::build(configuration.clone())
@@ -287,7 +759,9 @@ pub async fn spawn_app() -> TestApp {
         .cookie_store(true)
         .build()
         .expect("Failed to build a API client for testing.");
-    tokio::spawn(app.run_until_stopped());
+    let admin_cli = AdminCli::new(&configuration).expect("Failed to build the admin CLI.");
+    let shutdown = CancellationToken::new();
+    let server = tokio::spawn(app.run_until_stopped(shutdown.clone()));
     let test_app = TestApp {
         port,
         address: addr,
@@ -296,6 +770,11 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client,
         email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url,
+        delivery: configuration.delivery,
+        admin_cli,
+        shutdown,
+        server,
     };
 
     // Create a test user
@@ -0,0 +1,96 @@
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+use zero2prod::routes::ISSUE_NOT_FOUND_MESSAGE;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, sample_newsletter_form, spawn_app,
+    PostmarkBatchResponder,
+};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issue_history() {
+    let app = spawn_app().await;
+    let response = app.get_issues().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn issue_detail_reports_delivery_progress() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Assert - The recipient is still queued
+    let html_page = app.get_issue_detail_html(issue_id).await;
+    assert!(html_page.contains("<li>Queued: 1</li>"));
+    assert!(html_page.contains("<li>Delivered: 0</li>"));
+
+    // Act - Part 2 - Deliver
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - The delivery has been logged
+    let html_page = app.get_issue_detail_html(issue_id).await;
+    assert!(html_page.contains("<li>Queued: 0</li>"));
+    assert!(html_page.contains("<li>Delivered: 1</li>"));
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("Newsletter title"));
+}
+
+#[tokio::test]
+async fn deliveries_given_up_after_too_many_retries_are_logged_as_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        app.delivery.max_attempts as i16
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let logged = sqlx::query!("SELECT outcome, error_message FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(logged.outcome, "failed");
+    assert!(logged.error_message.is_some());
+    let queued = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, Some(0));
+}
+
+#[tokio::test]
+async fn unknown_issues_redirect_back_to_the_history() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_issue_detail(uuid::Uuid::new_v4()).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains(ISSUE_NOT_FOUND_MESSAGE));
+}
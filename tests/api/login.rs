@@ -1,4 +1,7 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
+use uuid::Uuid;
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -42,3 +45,246 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let admin_dashboard_page = app.get_admin_dashboard_html().await;
     assert!(admin_dashboard_page.contains(&format!("Welcome {}", test_user.username)))
 }
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failed_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    let wrong_password = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..4 {
+        let response = app.post_login(&wrong_password).await;
+        assert_is_redirect_to(&response, "/login");
+        assert!(app
+            .get_login_html()
+            .await
+            .contains("<p><i>Authentication failed</i></p>"));
+    }
+
+    // Act - Part 1 - The fifth failure triggers the lockout
+    let response = app.post_login(&wrong_password).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts, please try again in 60 seconds."));
+
+    // Act - Part 2 - Even the right password is refused while locked out
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts"));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failed_attempts_of_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    let wrong_password = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..4 {
+        app.post_login(&wrong_password).await;
+    }
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    // Act
+    for _ in 0..4 {
+        app.post_login(&wrong_password).await;
+    }
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_ip_address_is_locked_out_after_too_many_failures_across_usernames() {
+    // Arrange - Each username stays under its own limit.
+    let app = spawn_app().await;
+    for _ in 0..19 {
+        let body = serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": "random-password"
+        });
+        app.post_login(&body).await;
+    }
+
+    // Act
+    let body = serde_json::json!({
+        "username": Uuid::new_v4().to_string(),
+        "password": "random-password"
+    });
+    app.post_login(&body).await;
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts"));
+}
+
+async fn post_login_forwarded_for(
+    app: &TestApp,
+    body: &serde_json::Value,
+    client: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", format!("198.51.100.7, {}", client))
+        .form(body)
+        .send()
+        .await
+        .expect("Failed to send request to `/login` endpoint.")
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_locked_out_by_their_own_address() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..20 {
+        let body = serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": "random-password"
+        });
+        post_login_forwarded_for(&app, &body, "203.0.113.1").await;
+    }
+    let body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    });
+
+    // Act
+    let locked_out = post_login_forwarded_for(&app, &body, "203.0.113.1").await;
+    let other_client = post_login_forwarded_for(&app, &body, "203.0.113.2").await;
+
+    // Assert
+    assert_is_redirect_to(&locked_out, "/login");
+    assert_is_redirect_to(&other_client, "/admin/dashboard");
+}
+
+async fn store_password_hash(app: &TestApp, algorithm: Algorithm, params: Params) -> String {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(algorithm, Version::V0x13, params)
+        .hash_password(app.test_user.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        password_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    password_hash
+}
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
+
+#[tokio::test]
+async fn a_hash_with_weaker_parameters_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let weak_hash = store_password_hash(
+        &app,
+        Algorithm::Argon2id,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let upgraded_hash = stored_password_hash(&app).await;
+    assert_ne!(upgraded_hash, weak_hash);
+    let upgraded_hash = PasswordHash::new(&upgraded_hash).unwrap();
+    assert_eq!(upgraded_hash.algorithm, Algorithm::Argon2id.ident());
+    let params = Params::try_from(&upgraded_hash).unwrap();
+    assert_eq!(
+        (params.m_cost(), params.t_cost(), params.p_cost()),
+        (15000, 2, 1)
+    );
+    // The password still works against the new hash.
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_hash_from_another_argon2_variant_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    store_password_hash(
+        &app,
+        Algorithm::Argon2i,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let upgraded_hash = stored_password_hash(&app).await;
+    let upgraded_hash = PasswordHash::new(&upgraded_hash).unwrap();
+    assert_eq!(upgraded_hash.algorithm, Algorithm::Argon2id.ident());
+}
+
+#[tokio::test]
+async fn an_up_to_date_hash_is_left_alone_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let password_hash = stored_password_hash(&app).await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(stored_password_hash(&app).await, password_hash);
+}
+
+#[tokio::test]
+async fn a_weak_hash_is_not_upgraded_on_a_failed_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let weak_hash = store_password_hash(
+        &app,
+        Algorithm::Argon2id,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "not-the-password",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(stored_password_hash(&app).await, weak_hash);
+}
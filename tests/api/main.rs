@@ -1,8 +1,22 @@
+mod admin_cli;
 mod admin_dashboard;
+mod api_issues;
+mod api_subscribers;
+mod audit;
 mod change_password;
+mod csrf;
+mod failures;
 mod health_check;
 mod helpers;
+mod issues;
 mod login;
 mod newsletters;
+mod openapi;
+mod password_reset;
+mod sessions;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod two_factor;
+mod users;
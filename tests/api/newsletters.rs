@@ -1,28 +1,23 @@
 use chrono::Utc;
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
 use serde_json::Value;
+use sqlx::postgres::PgListener;
 use sqlx::PgPool;
 use std::ops::Sub;
 use std::time::Duration;
 use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
-use wiremock::{Mock, ResponseTemplate};
+use wiremock::{Mock, Request, Respond, ResponseTemplate};
 use zero2prod::idempotency_expiring_worker::delete_expired_idempotency_key;
-use zero2prod::routes::SUCCESS_MESSAGE;
-
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
-
-fn sample_newsletter_form() -> Value {
-    let idempotency_key = Uuid::new_v4().to_string();
-    serde_json::json!({
-        "title": "Newsletter title",
-        "text_content": "Newsletter body",
-        "html_content": "<p>Newsletter body</p>",
-        "idempotency_key": idempotency_key,
-    })
-}
+use zero2prod::issue_delivery_worker::ISSUE_DELIVERY_CHANNEL;
+use zero2prod::issue_scheduling_worker::{enqueue_due_issues, mark_delivered_issues_as_sent};
+use zero2prod::routes::{
+    CANCELLED_MESSAGE, DRAFT_SAVED_MESSAGE, NOT_EDITABLE_MESSAGE, SUCCESS_MESSAGE,
+};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    sample_newsletter_form, spawn_app, PostmarkBatchResponder,
+};
 
 #[tokio::test]
 async fn news_letters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -50,70 +45,212 @@ async fn news_letters_are_not_delivered_to_unconfirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(serde_json::json!({
-        "name": name,
-        "email": email,
-    }))
-    .expect("Failed to serialize the subscriber name and email data.");
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    create_confirmed_subscriber(&app).await; // Create a confirmed subscriber simulating a user clicking the confirmation link in the email.
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .respond_with(PostmarkBatchResponder)
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
 
-    // Create a subscriber, but not confirmed.
-    let _api_response = app
-        .post_subscriptions(body)
+    let response_body = app.post_publish_newsletter(&sample_newsletter_form()).await;
+    assert_is_redirect_to(&response_body, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(SUCCESS_MESSAGE));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
         .await
-        .error_for_status()
         .unwrap();
-    let email_request = &app
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_publish_newsletter(&sample_newsletter_form()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that no email has been sent.
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_one_click_unsubscribe_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unsubscribe_token;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
         .email_server
         .received_requests()
         .await
         .unwrap()
         .pop()
         .unwrap();
-
-    app.get_confirmation_links(email_request)
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    let expected_link = format!(
+        "<{}/subscriptions/unsubscribe?unsubscribe_token={}>",
+        app.base_url, unsubscribe_token
+    );
+    let message = &body[0];
+    assert_eq!(message["Headers"][0]["Name"], "List-Unsubscribe");
+    assert_eq!(message["Headers"][0]["Value"], expected_link.as_str());
+    assert_eq!(message["Headers"][1]["Name"], "List-Unsubscribe-Post");
+    assert_eq!(message["Headers"][1]["Value"], "List-Unsubscribe=One-Click");
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    // Start from unconfirmed subscriber
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_link.html)
+#[tokio::test]
+async fn newsletters_are_delivered_to_all_subscribers_in_a_single_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
         .await
         .unwrap()
-        .error_for_status()
+        .pop()
         .unwrap();
+    let body: Vec<Value> = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body.len(), 3);
+    let delivered = sqlx::query!(
+        "SELECT COUNT(*) AS count FROM issue_delivery_log WHERE outcome = 'delivered'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivered.count, Some(3));
+}
+
+/// Accepts every message of a batch but the ones sent to `0`, which are reported inactive.
+struct InactiveRecipientResponder(String);
+
+impl Respond for InactiveRecipientResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let messages: Vec<Value> = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<Value> = messages
+            .iter()
+            .map(|m| match m["To"].as_str() {
+                Some(to) if to == self.0 => {
+                    serde_json::json!({"ErrorCode": 406, "Message": "Inactive recipient"})
+                }
+                _ => serde_json::json!({"ErrorCode": 0, "Message": "OK"}),
+            })
+            .collect();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
 }
 
 #[tokio::test]
-async fn newsletters_are_delivered_to_confirmed_subscribers() {
+async fn permanently_rejected_messages_of_a_batch_are_not_retried() {
     // Arrange
     let app = spawn_app().await;
     app.test_user.login(&app).await;
-
-    create_confirmed_subscriber(&app).await; // Create a confirmed subscriber simulating a user clicking the confirmation link in the email.
-    Mock::given(path("/email"))
+    for _ in 0..2 {
+        create_confirmed_subscriber(&app).await;
+    }
+    let inactive_email = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(InactiveRecipientResponder(inactive_email.clone()))
         .expect(1)
         .mount(&app.email_server)
         .await;
 
-    let response_body = app.post_publish_newsletter(&sample_newsletter_form()).await;
-    assert_is_redirect_to(&response_body, "/admin/newsletters");
-
-    let html_page = app.get_publish_newsletter_html().await;
-    assert!(html_page.contains(SUCCESS_MESSAGE));
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
     app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let logged = sqlx::query!(
+        "SELECT subscriber_email, outcome, error_message FROM issue_delivery_log ORDER BY outcome"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(logged.len(), 2);
+    assert_eq!(logged[0].outcome, "delivered");
+    assert_ne!(logged[0].subscriber_email, inactive_email);
+    assert_eq!(logged[1].outcome, "failed");
+    assert_eq!(logged[1].subscriber_email, inactive_email);
+    assert!(logged[1]
+        .error_message
+        .as_deref()
+        .unwrap()
+        .contains("Inactive recipient"));
+}
+
+#[tokio::test]
+async fn publishing_an_issue_wakes_up_the_delivery_workers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let mut listener = PgListener::connect_with(&app.db_pool).await.unwrap();
+    listener.listen(ISSUE_DELIVERY_CHANNEL).await.unwrap();
+
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+
+    // Assert
+    let notification = tokio::time::timeout(Duration::from_secs(1), listener.recv())
+        .await
+        .expect("The delivery workers have not been notified.")
+        .unwrap();
+    assert_eq!(notification.channel(), ISSUE_DELIVERY_CHANNEL);
 }
 
 #[tokio::test]
@@ -141,9 +278,9 @@ async fn newsletter_creation_is_idempotent() {
     app.test_user.login(&app).await;
     // 3. Mount mockserver,
     // which expects to be called with a POST /email endpoint, only once with response 200.
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder)
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -180,11 +317,15 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         // Setting a long delay to ensure that the second request
         // arrives before the first one completes
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!([{"ErrorCode": 0, "Message": "OK"}]))
+                .set_delay(Duration::from_secs(2)),
+        )
         // .expect(1)
         .mount(&app.email_server)
         .await;
@@ -204,6 +345,80 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_another_newsletter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_form = sample_newsletter_form();
+    let mut another_newsletter_form = newsletter_form.clone();
+    another_newsletter_form["title"] = "Another title".into();
+
+    // Act
+    let response = app.post_publish_newsletter(&newsletter_form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let response = app.post_publish_newsletter(&another_newsletter_form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn deliveries_claimed_by_another_worker_are_left_until_the_claim_times_out() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    // As left by a worker which has crashed while sending the batch.
+    sqlx::query!("UPDATE issue_delivery_queue SET claimed_until = now() + interval '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - The claim is still running
+    {
+        let _mock_guard = Mock::given(path("/email/batch"))
+            .respond_with(PostmarkBatchResponder)
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Act - Part 2 - The claim has timed out
+    sqlx::query!("UPDATE issue_delivery_queue SET claimed_until = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email/batch"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+}
+
 #[tokio::test]
 async fn transient_errors_get_retried() {
     // Arrange
@@ -211,7 +426,7 @@ async fn transient_errors_get_retried() {
     create_confirmed_subscriber(&app).await;
 
     // First failure response from external external service.
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(500))
         .up_to_n_times(1)
@@ -220,9 +435,9 @@ async fn transient_errors_get_retried() {
         .await;
 
     // Second time, success.
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(PostmarkBatchResponder)
         .up_to_n_times(1)
         .expect(1)
         .mount(&app.email_server)
@@ -238,10 +453,83 @@ async fn transient_errors_get_retried() {
 
     app.dispatch_all_pending_emails().await;
 
+    // Assert - The retry is delayed by the backoff
+    let queued = sqlx::query!(
+        "SELECT n_retries, retry_after > now() AS \"is_delayed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.n_retries, 1);
+    assert!(queued.is_delayed);
+
+    // Act - Part 3 - Time passes
+    sqlx::query!("UPDATE issue_delivery_queue SET retry_after = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
     // Mock verifies on Drop that we have attempted to send the email twice.
     // and the second time should have been successful.
 }
 
+#[tokio::test]
+async fn rate_limited_deliveries_are_retried_after_the_requested_delay() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "600"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!(
+        r#"SELECT retry_after > now() + interval '590 seconds' AS "honours_retry_after!" FROM issue_delivery_queue"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(queued.honours_retry_after);
+}
+
+#[tokio::test]
+async fn invalid_messages_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 300,
+            "Message": "Invalid email request"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failure = sqlx::query!("SELECT n_retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.n_retries, 0);
+    assert!(failure.last_error.contains("422"));
+}
+
 #[tokio::test]
 async fn old_idempotency_key_is_cleaned_up() {
     // Arrange
@@ -306,3 +594,173 @@ async fn create_valid_idempotency_key(pool: &PgPool, user_id: Uuid) {
     .await
     .expect("Failed to insert a new idempotency key.");
 }
+
+fn sample_newsletter_form_with_action(action: &str, send_at: &str) -> Value {
+    let mut form = sample_newsletter_form();
+    form["action"] = action.into();
+    form["send_at"] = send_at.into();
+    form
+}
+
+async fn issue_id_and_status(pool: &PgPool) -> (Uuid, String) {
+    let row = sqlx::query!("SELECT newsletter_issue_id, status FROM newsletter_issues")
+        .fetch_one(pool)
+        .await
+        .expect("Failed to fetch the newsletter issue.");
+    (row.newsletter_issue_id, row.status)
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered_until_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Save a draft
+    let response = app
+        .post_publish_newsletter(&sample_newsletter_form_with_action("draft", ""))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(DRAFT_SAVED_MESSAGE));
+    let (issue_id, status) = issue_id_and_status(&app.db_pool).await;
+    assert_eq!(status, "draft");
+
+    {
+        let _mock_guard = Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Act - Part 2 - Edit and publish the draft
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let edited_form = serde_json::json!({
+        "title": "Edited title",
+        "text_content": "Edited body",
+        "html_content": "<p>Edited body</p>",
+        "action": "publish",
+    });
+    let response = app.post_edit_newsletter(issue_id, &edited_form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(SUCCESS_MESSAGE));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body[0]["Subject"], "Edited title");
+    assert_eq!(
+        mark_delivered_issues_as_sent(&app.db_pool).await.unwrap(),
+        1
+    );
+    let (_, status) = issue_id_and_status(&app.db_pool).await;
+    assert_eq!(status, "sent");
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_they_are_due() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(PostmarkBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Schedule an issue in an hour
+    let send_at = (Utc::now() + chrono::Duration::hours(1)).format("%Y-%m-%dT%H:%M");
+    let response = app
+        .post_publish_newsletter(&sample_newsletter_form_with_action(
+            "schedule",
+            &send_at.to_string(),
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled for"));
+
+    // Not due yet.
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Part 2 - Time passes
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(enqueue_due_issues(&app.db_pool).await.unwrap(), 1);
+    let (_, status) = issue_id_and_status(&app.db_pool).await;
+    assert_eq!(status, "sending");
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the issue has been delivered once.
+}
+
+#[tokio::test]
+async fn scheduling_an_issue_in_the_past_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&sample_newsletter_form_with_action(
+            "schedule",
+            "2020-01-01T09:00",
+        ))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The scheduled time must be in the future."));
+    let count = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, Some(0));
+}
+
+#[tokio::test]
+async fn cancelled_issues_can_no_longer_be_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&sample_newsletter_form_with_action("draft", ""))
+        .await;
+    let (issue_id, _) = issue_id_and_status(&app.db_pool).await;
+
+    // Act - Part 1 - Cancel the draft
+    let mut cancel_form = sample_newsletter_form();
+    cancel_form["action"] = "cancel".into();
+    let response = app.post_edit_newsletter(issue_id, &cancel_form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(CANCELLED_MESSAGE));
+
+    // Act - Part 2 - Try to open the edit form
+    let response = app.get_edit_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(NOT_EDITABLE_MESSAGE));
+    let (_, status) = issue_id_and_status(&app.db_pool).await;
+    assert_eq!(status, "cancelled");
+}
@@ -0,0 +1,66 @@
+use crate::helpers::spawn_app;
+use utoipa::OpenApi;
+use zero2prod::routes::ApiDoc;
+
+// Run with `UPDATE_OPENAPI=1` to write the generated document instead, see `just openapi`.
+const COMMITTED_SPEC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+#[tokio::test]
+async fn the_committed_openapi_document_is_up_to_date() {
+    // Arrange
+    let generated = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Failed to serialize the OpenAPI document");
+    if std::env::var("UPDATE_OPENAPI").is_ok() {
+        std::fs::write(COMMITTED_SPEC, format!("{}\n", generated)).unwrap();
+    }
+
+    // Act
+    let committed = std::fs::read_to_string(COMMITTED_SPEC).expect("openapi.json is missing");
+
+    // Assert
+    let committed: serde_json::Value = serde_json::from_str(&committed).unwrap();
+    let generated: serde_json::Value = serde_json::from_str(&generated).unwrap();
+    assert!(
+        committed == generated,
+        "openapi.json is out of date, regenerate it with `just openapi`."
+    );
+}
+
+#[tokio::test]
+async fn the_openapi_document_is_served_with_its_docs_page() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let spec: serde_json::Value = app
+        .api_client
+        .get(format!("{}/api/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let docs_page = app
+        .api_client
+        .get(format!("{}/api/docs", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(spec, serde_json::to_value(ApiDoc::openapi()).unwrap());
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+    assert!(spec["paths"]["/api/v1/subscribers"]["get"].is_object());
+    assert_eq!(
+        spec["components"]["securitySchemes"]["api_token"]["scheme"],
+        "bearer"
+    );
+    assert!(docs_page.contains(r#"spec-url="/api/openapi.json""#));
+    assert!(docs_page.contains(r#"<script src="/api/docs/redoc.standalone.js"></script>"#));
+    assert!(!docs_page.contains("https://"));
+}
@@ -0,0 +1,228 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::routes::RESET_REQUESTED_MESSAGE;
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// Requests a reset for the test user and returns the token from the emailed link.
+async fn request_reset_token(app: &TestApp) -> String {
+    app.post_password_reset_request(&app.test_user.username)
+        .await;
+    let email_request = &app.wait_for_email_requests(1).await[0];
+    let links = app.get_confirmation_links(email_request);
+    links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .expect("The reset link has no token.")
+}
+
+fn reset_form(token: &str, new_password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    })
+}
+
+#[tokio::test]
+async fn the_login_form_links_to_the_password_reset_page() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"<a href="/login/reset">"#));
+}
+
+#[tokio::test]
+async fn unknown_and_known_usernames_get_the_same_response() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    // Act - Part 1 - Unknown username
+    let response = app
+        .post_password_reset_request(&Uuid::new_v4().to_string())
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let unknown_user_page = app.get_login_html().await;
+
+    // Act - Part 2 - Known username
+    let response = app
+        .post_password_reset_request(&app.test_user.username)
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let known_user_page = app.get_login_html().await;
+
+    // Assert
+    assert!(unknown_user_page.contains(RESET_REQUESTED_MESSAGE));
+    assert_eq!(unknown_user_page, known_user_page);
+    let email_requests = app.wait_for_email_requests(1).await;
+    assert_eq!(email_requests.len(), 1);
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[0].body).unwrap();
+    assert_eq!(body["To"], app.test_user.email);
+}
+
+#[tokio::test]
+async fn only_the_hash_of_the_reset_token_is_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    // Act
+    let token = request_reset_token(&app).await;
+
+    // Assert
+    let stored = sqlx::query!("SELECT token_hash, user_id FROM password_reset_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the stored reset token.");
+    assert_eq!(stored.user_id, app.test_user.user_id);
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn the_password_can_be_reset_with_the_emailed_link() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act - Part 1 - Reset the password
+    let response = app
+        .post_password_reset(&reset_form(&token, &new_password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - The old password no longer works
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Log in with the new password
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = request_reset_token(&app).await;
+    app.post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Act
+    let response = app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/reset");
+    let html_page = app
+        .api_client
+        .get(format!("{}/login/reset", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The password reset link is invalid or has expired.</i></p>"));
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_password_reset(&reset_form(&token, &new_password))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/reset");
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn new_password_fields_must_match() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = request_reset_token(&app).await;
+
+    // Act
+    let response = app
+        .post_password_reset(&serde_json::json!({
+            "token": &token,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/login/reset/confirm?token={}", token));
+    // The token has not been used up by the failed attempt.
+    let response = app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_breached_password_is_rejected_without_using_up_the_link() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let token = request_reset_token(&app).await;
+
+    // Act
+    let response = app
+        .post_password_reset(&reset_form(&token, "password1234"))
+        .await;
+
+    // Assert
+    let reset_page = format!("/login/reset/confirm?token={}", token);
+    assert_is_redirect_to(&response, &reset_page);
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, reset_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "<p><i>This password has appeared in a data breach, please choose another one.</i></p>"
+    ));
+    let response = app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
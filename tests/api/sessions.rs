@@ -0,0 +1,121 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn is_logged_in(app: &TestApp, browser: &reqwest::Client) -> bool {
+    let response = browser
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    response.status().as_u16() == 200
+}
+
+async fn session_id_of(app: &TestApp, user_agent: &str) -> Uuid {
+    sqlx::query_scalar!(
+        "SELECT session_id FROM user_sessions WHERE user_agent = $1",
+        user_agent,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the session.")
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_the_sessions_of_the_user() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_from_other_browser("Other browser/1.0").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let html_page = app.get_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("Other browser/1.0"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("(this session)"));
+    assert_eq!(html_page.matches("Revoke</button>").count(), 1);
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_browser = app.login_from_other_browser("Other browser/1.0").await;
+    app.test_user.login(&app).await;
+    let session_id = session_id_of(&app, "Other browser/1.0").await;
+
+    // Act
+    let response = app.post_sessions(&format!("{}/revoke", session_id)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("The session has been revoked."));
+    assert!(!is_logged_in(&app, &other_browser).await);
+    assert!(is_logged_in(&app, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_revoked_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_browser = app.login_from_other_browser("First browser/1.0").await;
+    let second_browser = app.login_from_other_browser("Second browser/1.0").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_sessions("revoke-others").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("2 other sessions have been revoked."));
+    assert!(!is_logged_in(&app, &first_browser).await);
+    assert!(!is_logged_in(&app, &second_browser).await);
+    assert!(is_logged_in(&app, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_browser = app.login_from_other_browser("Other browser/1.0").await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    assert!(!is_logged_in(&app, &other_browser).await);
+    assert!(is_logged_in(&app, &app.api_client).await);
+}
+
+#[tokio::test]
+async fn logging_out_forgets_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    let n_sessions = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM user_sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_sessions, 0);
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, Request, Respond, ResponseTemplate};
+use zero2prod::issue_delivery_worker::worker_loop;
+
+use crate::helpers::{
+    create_confirmed_subscriber, sample_newsletter_form, spawn_app, PostmarkBatchResponder,
+};
+
+#[tokio::test]
+async fn the_server_stops_accepting_connections_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let endpoint = format!("{}/health_check", app.address);
+    let response = client.get(&endpoint).send().await.unwrap();
+    assert!(response.status().is_success());
+
+    // Act
+    app.shutdown.cancel();
+
+    // Assert
+    let mut stopped = false;
+    for _ in 0..50 {
+        // A fresh client, so that no pooled connection is reused.
+        if reqwest::Client::new().get(&endpoint).send().await.is_err() {
+            stopped = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(stopped, "The server is still accepting connections.");
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_the_password_reset_email_to_be_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_password_reset_request(&app.test_user.username)
+        .await;
+
+    // Act
+    app.shutdown.cancel();
+    app.server.await.unwrap().unwrap();
+
+    // Assert
+    // The mock server checks on drop that the email has been sent.
+}
+
+/// Accepts the batch, but only after a while.
+struct SlowBatchResponder;
+
+impl Respond for SlowBatchResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        PostmarkBatchResponder
+            .respond(request)
+            .set_delay(Duration::from_secs(1))
+    }
+}
+
+#[tokio::test]
+async fn the_delivery_worker_completes_the_batch_in_flight_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(SlowBatchResponder)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&sample_newsletter_form()).await;
+    let worker = tokio::spawn(worker_loop(
+        app.db_pool.clone(),
+        app.email_client.clone(),
+        app.base_url.clone(),
+        app.delivery.clone(),
+        app.shutdown.clone(),
+    ));
+    // Waits for the batch to be sent.
+    for _ in 0..50 {
+        let requests = app.email_server.received_requests().await.unwrap();
+        if requests.iter().any(|r| r.url.path() == "/email/batch") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Act
+    app.shutdown.cancel();
+    worker.await.unwrap().unwrap();
+
+    // Assert
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    let delivered = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_log WHERE outcome = 'delivered'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(delivered.count, 1);
+}
@@ -1,7 +1,7 @@
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{create_unconfirmed_subscriber, spawn_app};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400_bad_request() {
@@ -68,3 +68,73 @@ async fn calling_http_get_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn an_expired_confirmation_link_is_rejected_with_a_410_gone() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    // Pretend the token has been issued well before the configured TTL.
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '25 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_confirmation_link_only_works_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&test_app).await;
+
+    // Act
+    let first = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let second = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(401, second.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_resubscribe_an_unsubscribed_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&test_app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(410, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
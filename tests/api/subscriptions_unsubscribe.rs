@@ -0,0 +1,86 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_subscriber(app: &TestApp) -> String {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    saved.unsubscribe_token
+}
+
+async fn subscription_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription")
+        .status
+}
+
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_a_400_bad_request() {
+    let app = spawn_app().await;
+
+    let response = app.get_unsubscribe("").await;
+    assert_eq!(400, response.status().as_u16());
+    let response = app.post_unsubscribe("").await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unsubscribe_with_an_unknown_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = app.post_unsubscribe("?unsubscribe_token=unknown").await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn get_unsubscribe_shows_a_form_without_unsubscribing() {
+    let app = spawn_app().await;
+    let token = create_subscriber(&app).await;
+
+    let response = app
+        .get_unsubscribe(&format!("?unsubscribe_token={}", token))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"method="post""#));
+    assert_eq!(subscription_status(&app).await, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn one_click_post_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    let token = create_subscriber(&app).await;
+
+    // Mailbox providers send this body as defined by RFC 8058.
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+            app.address, token
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(subscription_status(&app).await, "unsubscribed");
+}
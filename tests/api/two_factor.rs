@@ -0,0 +1,352 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// The code the user's authenticator app shows, `steps_ahead` time steps from now.
+/// Every accepted step is recorded, so a login right after enrollment needs the next step's code.
+async fn totp_code(app: &TestApp, steps_ahead: u64) -> String {
+    let secret = sqlx::query_scalar!(
+        "SELECT secret FROM user_totp_secrets WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the TOTP secret.");
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(secret).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp.generate(now + 30 * steps_ahead)
+}
+
+/// Logs in, enables two-factor authentication and returns the recovery codes.
+async fn enable_two_factor(app: &TestApp) -> Vec<String> {
+    app.test_user.login(app).await;
+    app.post_security("totp", &()).await;
+    let code = totp_code(app, 0).await;
+    let response = app
+        .post_security("totp/confirm", &serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_security_settings() {
+    let app = spawn_app().await;
+
+    let response = app.get_security().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn starting_the_setup_shows_a_qr_code_and_the_provisioning_uri() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_security("totp", &()).await;
+    assert_is_redirect_to(&response, "/admin/security");
+    let html_page = app.get_security_html().await;
+
+    // Assert
+    assert!(html_page.contains("<svg"));
+    assert!(html_page.contains(&format!(
+        "otpauth://totp/zero2prod:{}?secret=",
+        app.test_user.username
+    )));
+}
+
+#[tokio::test]
+async fn confirming_the_setup_enables_two_factor_and_shows_recovery_codes() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let recovery_codes = enable_two_factor(&app).await;
+
+    // Assert
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_security_html().await;
+    assert!(
+        html_page.contains("Two-factor authentication is enabled. 10 unused recovery codes left.")
+    );
+    let stored_hashes = sqlx::query_scalar!(
+        "SELECT code_hash FROM user_recovery_codes WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored_hashes.len(), 10);
+    assert!(stored_hashes
+        .iter()
+        .all(|hash| !recovery_codes.contains(hash)));
+}
+
+#[tokio::test]
+async fn a_wrong_code_does_not_enable_two_factor() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_security("totp", &()).await;
+
+    // Act
+    let response = app
+        .post_security("totp/confirm", &serde_json::json!({ "code": "000000x" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is invalid.</i></p>"));
+    assert!(html_page.contains("Enable two-factor authentication"));
+}
+
+#[tokio::test]
+async fn login_asks_for_the_second_factor_once_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - The password alone is not enough
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Pass the second step
+    let code = totp_code(&app, 1).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_totp_code_cannot_be_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+    let code = totp_code(&app, 1).await;
+    app.test_user.login(&app).await;
+    app.post_login_two_factor(&code).await;
+    app.post_logout().await;
+
+    // Act
+    app.test_user.login(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let recovery_codes = enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - Use a recovery code
+    app.test_user.login(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - Use it again
+    app.test_user.login(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_drop_the_pending_login() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    for _ in 0..4 {
+        let response = app.post_login_two_factor("wrong-code").await;
+        assert_is_redirect_to(&response, "/login/2fa");
+    }
+    let response = app.post_login_two_factor("wrong-code").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Too many invalid codes, please try again in"));
+    let code = totp_code(&app, 1).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn passing_the_password_step_alone_keeps_the_failed_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+    let wrong_password = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..4 {
+        app.post_login(&wrong_password).await;
+    }
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    // Act
+    app.post_login(&wrong_password).await;
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts"));
+}
+
+#[tokio::test]
+async fn wrong_codes_are_counted_across_logins() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    app.post_logout().await;
+
+    // Act - Part 1 - A fresh login for every wrong code
+    for _ in 0..5 {
+        let response = app.test_user.login(&app).await;
+        assert_is_redirect_to(&response, "/login/2fa");
+        app.post_login_two_factor("wrong-code").await;
+    }
+
+    // Act - Part 2 - The right code is refused during the lockout
+    app.test_user.login(&app).await;
+    let code = totp_code(&app, 1).await;
+    let response = app.post_login_two_factor(&code).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Too many invalid codes, please try again in"));
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_which_only_passed_the_password_check_are_refused() {
+    // Arrange - Another browser logs in before two-factor authentication is enabled.
+    let app = spawn_app().await;
+    let other_browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = other_browser
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    enable_two_factor(&app).await;
+
+    // Act
+    let response = other_browser
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    // The session which enabled it has just passed the second factor.
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn two_factor_can_be_turned_off_with_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let recovery_codes = enable_two_factor(&app).await;
+
+    // Act - Part 1 - A wrong code is refused
+    let response = app
+        .post_security("totp/disable", &serde_json::json!({ "code": "wrong-code" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/security");
+    assert!(app
+        .get_security_html()
+        .await
+        .contains("The authentication code is invalid."));
+
+    // Act - Part 2 - A recovery code works
+    let response = app
+        .post_security(
+            "totp/disable",
+            &serde_json::json!({ "code": recovery_codes[0] }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/security");
+
+    // Assert
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn wrong_codes_to_turn_off_two_factor_are_locked_out() {
+    // Arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+    for _ in 0..5 {
+        app.post_security("totp/disable", &serde_json::json!({ "code": "wrong-code" }))
+            .await;
+    }
+
+    // Act - The right code is refused during the lockout
+    let code = totp_code(&app, 1).await;
+    let response = app
+        .post_security("totp/disable", &serde_json::json!({ "code": code }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>Too many invalid codes, please try again in"));
+    assert!(html_page.contains("Turn off"));
+}
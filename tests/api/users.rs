@@ -0,0 +1,362 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn store_user_with_role(app: &TestApp, role: &'static str) -> TestUser {
+    let user = TestUser {
+        role,
+        ..TestUser::generate()
+    };
+    user.store(&app.db_pool).await;
+    user
+}
+
+/// Invites `email` as `role` and returns the token from the emailed link.
+async fn invite(app: &TestApp, email: &str, role: &str) -> String {
+    let response = app
+        .post_users(
+            "invite",
+            &serde_json::json!({ "email": email, "role": role }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let links = app.get_confirmation_links(email_requests.last().unwrap());
+    links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .expect("The invitation link has no token.")
+}
+
+fn accept_form(token: &str, username: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "username": username,
+        "password": password,
+        "password_check": password,
+    })
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = store_user_with_role(&app, "editor").await;
+    editor.login(&app).await;
+
+    // Act
+    let page = app.get_users().await;
+    let invitation = app
+        .post_users(
+            "invite",
+            &serde_json::json!({ "email": "someone@example.com", "role": "owner" }),
+        )
+        .await;
+    let role_change = app
+        .post_users(
+            &format!("{}/role", app.test_user.user_id),
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(invitation.status().as_u16(), 403);
+    assert_eq!(role_change.status().as_u16(), 403);
+    assert!(!app
+        .get_admin_dashboard_html()
+        .await
+        .contains("/admin/users"));
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_newsletter_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = store_user_with_role(&app, "viewer").await;
+    viewer.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let n_issues = sqlx::query_scalar!(r#"SELECT count(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn an_invited_user_can_join_with_the_invited_role() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.test_user.login(&app).await;
+    let email: String = SafeEmail().fake();
+    let token = invite(&app, &email, "editor").await;
+    assert!(app.get_users_html().await.contains(&email));
+    app.post_logout().await;
+
+    // Act - Part 1 - Accept the invitation
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+    let response = app
+        .post_accept_invitation(&accept_form(&token, &username, &password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Log in as the new user
+    let response = app
+        .post_login(&serde_json::json!({ "username": &username, "password": &password }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {} (editor)", username)));
+    let stored = sqlx::query!(
+        "SELECT email, role FROM users WHERE username = $1",
+        username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.email.as_deref(), Some(email.as_str()));
+    assert_eq!(stored.role, "editor");
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_accepted_once() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.test_user.login(&app).await;
+    let token = invite(&app, &SafeEmail().fake::<String>(), "viewer").await;
+    let password = Uuid::new_v4().to_string();
+    app.post_accept_invitation(&accept_form(&token, &Uuid::new_v4().to_string(), &password))
+        .await;
+
+    // Act
+    let username = Uuid::new_v4().to_string();
+    let response = app
+        .post_accept_invitation(&accept_form(&token, &username, &password))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("The invitation link is invalid or has expired."));
+    let response = app
+        .post_login(&serde_json::json!({ "username": &username, "password": &password }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn inviting_an_existing_email_address_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_users(
+            "invite",
+            &serde_json::json!({ "email": &app.test_user.email, "role": "editor" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("<p><i>A user with this email address already exists.</i></p>"));
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn an_invitation_is_recorded_even_if_its_email_cannot_be_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    let email: String = SafeEmail().fake();
+
+    // Act
+    let response = app
+        .post_users(
+            "invite",
+            &serde_json::json!({ "email": &email, "role": "editor" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let n_invitations = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM user_invitations"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let target =
+        sqlx::query_scalar!("SELECT target_id FROM audit_events WHERE action = 'user.invited'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_invitations, 1);
+    assert_eq!(target, Some(email));
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_other_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = store_user_with_role(&app, "editor").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_users(
+            &format!("{}/role", editor.user_id),
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    app.post_logout().await;
+    editor.login(&app).await;
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {} (viewer)", editor.username)));
+}
+
+#[tokio::test]
+async fn owners_cannot_change_their_own_account() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_users(&format!("{}/deactivate", app.test_user.user_id), &())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("You cannot change the role or deactivate your own account."));
+}
+
+#[tokio::test]
+async fn deactivated_users_are_logged_out_and_cannot_log_in() {
+    // Arrange - The editor is logged in from another browser.
+    let app = spawn_app().await;
+    let editor = store_user_with_role(&app, "editor").await;
+    let editor_browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let login_form = serde_json::json!({
+        "username": &editor.username,
+        "password": &editor.password,
+    });
+    editor_browser
+        .post(format!("{}/login", &app.address))
+        .form(&login_form)
+        .send()
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_users(&format!("{}/deactivate", editor.user_id), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // Assert
+    let response = editor_browser
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = editor_browser
+        .post(format!("{}/login", &app.address))
+        .form(&login_form)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_invitation_cannot_be_accepted_with_a_password_containing_the_username() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.test_user.login(&app).await;
+    let token = invite(&app, &SafeEmail().fake::<String>(), "editor").await;
+    app.post_logout().await;
+    let username = "newsletter-editor";
+
+    // Act
+    let response = app
+        .post_accept_invitation(&accept_form(&token, username, "newsletter-editor-2024"))
+        .await;
+
+    // Assert
+    let invitation_page = format!("/invitations/accept?token={}", token);
+    assert_is_redirect_to(&response, &invitation_page);
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", &app.address, invitation_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The password must not contain your username.</i></p>"));
+    let user = sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(user.is_none());
+}